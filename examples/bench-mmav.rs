@@ -3,8 +3,60 @@ use std::{
   sync::{Arc, Mutex},
 };
 
+/// Number of records read by the read benchmark
+const READ_RECORDS: usize = 100_000;
+
+/// Number of times the records are read by the read benchmark
+const READ_ROUNDS: usize = 10;
+
+/// Compare reading records by copying them out of the memory map with
+/// reading them through zero-copy handles
+///
+/// Run with `cargo run --release --example bench-mmav -- reads`.
+fn bench_reads() {
+  use rapiddb_web::rapiddb::traits::IDatabase;
+
+  let db_path = ".temp/bench-mmav/reads";
+  std::fs::remove_dir_all(db_path).unwrap_or_default();
+
+  let mut db = rapiddb_web::rapiddb::db::MMAVDatabase::new_with_all(
+    db_path,
+    Default::default(),
+  );
+
+  let records = (0..READ_RECORDS)
+    .map(|x| serde_json::json!({ "n": x, "temp": 20.5 }).to_string())
+    .collect::<Vec<_>>();
+  let batch = records.iter().map(|x| x.as_bytes()).collect::<Vec<_>>();
+  db.post_batch("test-0", &batch).unwrap();
+
+  let start = std::time::Instant::now();
+  let mut bytes = 0;
+  for _ in 0..READ_ROUNDS {
+    for record in db.get_range("test-0", 0, READ_RECORDS - 1) {
+      bytes += record.len();
+    }
+  }
+  println!("copy:      {:?} ({bytes} bytes)", start.elapsed());
+
+  let start = std::time::Instant::now();
+  let mut bytes = 0;
+  for _ in 0..READ_ROUNDS {
+    for record in db.iter_range_ref("test-0", 0, READ_RECORDS - 1) {
+      bytes += record.len();
+    }
+  }
+  println!("zero-copy: {:?} ({bytes} bytes)", start.elapsed());
+
+  std::fs::remove_dir_all(db_path).unwrap_or_default();
+}
+
 #[tokio::main]
 async fn main() {
+  if std::env::args().nth(1).as_deref() == Some("reads") {
+    return bench_reads();
+  }
+
   let mut aggregates_fn: HashMap<
     String,
     rapiddb_web::rapiddb::types::AggregateFn,
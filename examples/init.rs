@@ -2,7 +2,6 @@ use std::{
   collections::HashMap,
   sync::{Arc, Mutex},
 };
-use tokio::sync::RwLock;
 
 use rapiddb_web::api::helpers::with_db;
 use rapiddb_web::rapiddb::traits::IAsyncDatabase;
@@ -11,7 +10,7 @@ use warp::{Filter, Rejection, Reply};
 
 /// GET /api/custom/:String/latest
 pub fn get_latest_custom(
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
   warp::path!("api" / "custom" / String / "latest")
     .and(warp::get())
@@ -22,9 +21,9 @@ pub fn get_latest_custom(
 /// GET /api/custom/:String/latest
 pub async fn _get_latest_custom(
   id: String,
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
-  let result = db.write().await.get_latest(&id).await;
+  let result = db.get_latest(&id).await;
 
   if !result.is_empty() {
     return Ok(
@@ -85,20 +84,17 @@ async fn main() {
   aggregates_fn.insert("test-0".to_string(), test_fn.clone());
   aggregates_fn.insert("test-1".to_string(), test_fn);
 
-  let db = Arc::new(RwLock::new(
-    rapiddb_web::rapiddb::db::MMAVAsyncDatabase::new_with_all(
-      ".db",
-      aggregates_fn,
-    ),
+  let db = Arc::new(rapiddb_web::rapiddb::db::MMAVAsyncDatabase::new_with_all(
+    ".db",
+    aggregates_fn,
   ));
 
   let value = b"{\"key\": \"value\"}";
-  db.write().await.post("test-0", value).await;
-  assert_eq!(db.write().await.get_latest("test-0").await, value);
+  db.post("test-0", value).await.unwrap();
+  assert_eq!(db.get_latest("test-0").await, value);
 
   warp::serve(
-    rapiddb_web::api::endpoints_with_arc_rwlock(db.clone())
-      .or(get_latest_custom(db)),
+    rapiddb_web::api::endpoints_with_arc(db.clone()).or(get_latest_custom(db)),
   )
   .run(([0, 0, 0, 0], 3030))
   .await;
@@ -4,6 +4,6 @@ pub fn main() {
   let mut db = rapiddb::db::MMAVDatabase::new();
 
   let value = b"{\"key\": \"value\"}";
-  db.post("test-0", value);
+  db.post("test-0", value).unwrap();
   assert_eq!(db.get_latest("test-0"), value);
 }
@@ -0,0 +1,34 @@
+/// How the REST API treats record bodies posted to `/api/v0/:id`
+///
+/// ## Examples
+/// ```no_run
+/// let config = rapiddb_web::api::ApiConfig {
+///   ingest_mode: rapiddb_web::api::IngestMode::Raw,
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IngestMode {
+  /// Store any body as is
+  Raw,
+  /// Reject bodies that are not valid JSON with `400 Bad Request`
+  #[default]
+  Json,
+}
+
+/// REST API configuration
+///
+/// ## Examples
+/// ```no_run
+/// let db =
+///   std::sync::Arc::new(rapiddb_web::rapiddb::db::MMAVAsyncDatabase::new());
+///
+/// let config = rapiddb_web::api::ApiConfig {
+///   ingest_mode: rapiddb_web::api::IngestMode::Raw,
+/// };
+///
+/// let api = rapiddb_web::api::endpoints_with_all(db, config);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ApiConfig {
+  pub ingest_mode: IngestMode,
+}
@@ -0,0 +1,159 @@
+use rapiddb::db::{Notification, SubscriptionFilter};
+use rapiddb::traits::IAsyncDatabase;
+
+/// An event of a `Follower`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Followed {
+  /// The record with `rec_id` and `value` of the sensor with `id`
+  Record { id: String, rec_id: usize, value: Vec<u8> },
+  /// The metadata `value` posted to the sensor with `id`
+  Meta { id: String, value: Vec<u8> },
+}
+
+/// Events queued by a `Follower`, which are not read yet
+enum Pending {
+  /// The records of the sensor with id from an index up to a length
+  Records(String, usize, usize),
+  /// The metadata of the sensor with id
+  Meta(String),
+}
+
+/// Follower
+///
+/// Replays the records of the selected sensors from a starting index,
+/// and then follows the records and metadata as they are posted, driven
+/// by the notifications of the database. Sensors which are created while
+/// following are followed from their first record. If the follower lags
+/// behind the notifications, it catches up from a snapshot, so no
+/// records are skipped.
+pub struct Follower<D: IAsyncDatabase + ?Sized> {
+  db: std::sync::Arc<D>,
+  filter: SubscriptionFilter,
+  notifications: tokio::sync::broadcast::Receiver<Notification>,
+  next: std::collections::HashMap<String, usize>,
+  pending: std::collections::VecDeque<Pending>,
+  lagged: bool,
+}
+impl<D: IAsyncDatabase + ?Sized> Follower<D> {
+  /// Follow the sensors selected by `filter` in `db`, replaying from
+  /// record index `start`, or only new records if `start` is `None`
+  pub async fn new(
+    db: std::sync::Arc<D>,
+    filter: SubscriptionFilter,
+    start: Option<usize>,
+  ) -> Self {
+    let notifications = db.notifications();
+    let snapshot = db.snapshot().await;
+
+    let mut result = Self {
+      db,
+      filter,
+      notifications,
+      next: Default::default(),
+      pending: Default::default(),
+      lagged: false,
+    };
+
+    let mut ids = result.selected(&snapshot);
+    if let SubscriptionFilter::Id(x) = &result.filter {
+      ids = vec![x.clone()];
+    }
+
+    for id in ids {
+      let len = snapshot.len(&id);
+      let next = start.unwrap_or(len);
+
+      if next < len {
+        result.pending.push_back(Pending::Records(id.clone(), next, len));
+      }
+      result.next.insert(id, next.max(len));
+    }
+
+    result
+  }
+
+  /// Get the next event of the selected sensors
+  ///
+  /// Returns `None` once the database stops sending notifications. This
+  /// is cancel safe, so it can be used in `tokio::select!`.
+  pub async fn next(&mut self) -> Option<Followed> {
+    loop {
+      match self.pending.front() {
+        Some(Pending::Records(id, rec_id, _)) => {
+          let (id, rec_id) = (id.clone(), *rec_id);
+          let value = self.db.get(&id, rec_id).await;
+
+          if let Some(Pending::Records(_, x, len)) = self.pending.front_mut() {
+            *x += 1;
+            if x >= len {
+              self.pending.pop_front();
+            }
+          }
+
+          return Some(Followed::Record { id, rec_id, value });
+        }
+        Some(Pending::Meta(id)) => {
+          let id = id.clone();
+          let value = self.db.get_meta(&id).await;
+          self.pending.pop_front();
+
+          return Some(Followed::Meta { id, value });
+        }
+        None => {}
+      }
+
+      if self.lagged {
+        let snapshot = self.db.snapshot().await;
+        self.lagged = false;
+
+        for id in self.selected(&snapshot) {
+          self.advance(&id, snapshot.len(&id));
+        }
+
+        continue;
+      }
+
+      match self.notifications.recv().await {
+        Ok(Notification::Record { id, rec_id }) if self.filter.matches(&id) => {
+          self.advance(&id, rec_id + 1);
+        }
+        Ok(Notification::Meta { id }) if self.filter.matches(&id) => {
+          self.pending.push_back(Pending::Meta(id));
+        }
+        Ok(_) => {}
+        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {
+          self.lagged = true;
+        }
+        Err(tokio::sync::broadcast::error::RecvError::Closed) => {
+          return None;
+        }
+      }
+    }
+  }
+
+  /// Get the ids of the selected sensors in `snapshot`, in order
+  fn selected(&self, snapshot: &rapiddb::db::Snapshot) -> Vec<String> {
+    let mut result = snapshot
+      .ids()
+      .filter(|x| self.filter.matches(x))
+      .map(|x| x.to_owned())
+      .collect::<Vec<_>>();
+    result.sort_unstable();
+
+    result
+  }
+
+  /// Queue the records of the sensor with `id` up to `len`, which were
+  /// not queued yet
+  ///
+  /// A sensor which is not followed yet was created after the follower,
+  /// so all of its records are new.
+  fn advance(&mut self, id: &str, len: usize) {
+    let next = self.next.entry(id.to_owned()).or_insert(0);
+
+    if len > *next {
+      self.pending.push_back(Pending::Records(id.to_owned(), *next, len));
+      *next = len;
+    }
+  }
+}
@@ -1,16 +1,966 @@
+use base64::Engine;
 use warp::Filter;
 
 pub fn with_db(
-  db: std::sync::Arc<
-    tokio::sync::RwLock<impl rapiddb::traits::IAsyncDatabase + ?Sized>,
-  >,
+  db: std::sync::Arc<impl rapiddb::traits::IAsyncDatabase + ?Sized>,
 ) -> impl Filter<
-  Extract = (
-    std::sync::Arc<
-      tokio::sync::RwLock<impl rapiddb::traits::IAsyncDatabase + ?Sized>,
-    >,
-  ),
+  Extract = (std::sync::Arc<impl rapiddb::traits::IAsyncDatabase + ?Sized>,),
   Error = std::convert::Infallible,
 > + Clone {
   warp::any().map(move || db.clone())
 }
+
+pub fn with_config(
+  config: crate::api::ApiConfig,
+) -> impl Filter<
+  Extract = (crate::api::ApiConfig,),
+  Error = std::convert::Infallible,
+> + Clone {
+  warp::any().map(move || config.clone())
+}
+
+pub fn with_snapshots(
+  snapshots: crate::api::snapshots::Snapshots,
+) -> impl Filter<
+  Extract = (crate::api::snapshots::Snapshots,),
+  Error = std::convert::Infallible,
+> + Clone {
+  warp::any().map(move || snapshots.clone())
+}
+
+/// Characters which are percent-encoded in a path segment
+const PATH_SEGMENT: &percent_encoding::AsciiSet = &percent_encoding::CONTROLS
+  .add(b' ')
+  .add(b'"')
+  .add(b'#')
+  .add(b'/')
+  .add(b'<')
+  .add(b'>')
+  .add(b'?')
+  .add(b'`')
+  .add(b'{')
+  .add(b'}');
+
+/// Percent-encode `value` to be used as one segment of a URL path
+///
+/// Path parameters are not percent-decoded, so sensor ids taken from a
+/// path keep their escapes, which are left as they are, and encoding an
+/// id taken from a path returns it unchanged.
+///
+/// ## Examples
+/// ```
+/// use rapiddb_web::api::helpers::encode_path_segment;
+///
+/// assert_eq!(encode_path_segment("test%200"), "test%200");
+/// assert_eq!(encode_path_segment("a b/c?"), "a%20b%2Fc%3F");
+/// ```
+pub fn encode_path_segment(value: &str) -> String {
+  percent_encoding::utf8_percent_encode(value, PATH_SEGMENT).to_string()
+}
+
+/// Convert a stored record into raw JSON, so it can be embedded in a
+/// response built with `serde_json`
+///
+/// Records that are not valid JSON are embedded as a JSON string.
+///
+/// ## Examples
+/// ```
+/// let value = rapiddb_web::api::helpers::to_raw_json(b"{\"key\":\"value\"}");
+/// assert_eq!(value.get(), "{\"key\":\"value\"}");
+///
+/// let value = rapiddb_web::api::helpers::to_raw_json(b"value");
+/// assert_eq!(value.get(), "\"value\"");
+/// ```
+pub fn to_raw_json(value: &[u8]) -> Box<serde_json::value::RawValue> {
+  std::str::from_utf8(value)
+    .ok()
+    .and_then(|x| serde_json::value::RawValue::from_string(x.to_owned()).ok())
+    .unwrap_or_else(|| {
+      serde_json::value::to_raw_value(&String::from_utf8_lossy(value))
+        .unwrap_or_default()
+    })
+}
+
+/// Query options for reading records
+///
+/// `encoding=base64` encodes every record as a base64 JSON string,
+/// regardless of the content type of the sensor.
+///
+/// `as_of` reads as of a snapshot taken with
+/// `POST /api/v0/sensors/snapshot`.
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+pub struct ReadQuery {
+  pub encoding: Option<String>,
+  pub as_of: Option<u64>,
+}
+impl ReadQuery {
+  /// Checks if records should be base64 encoded in JSON responses,
+  /// given the `content_type` of the sensor
+  pub fn base64(&self, content_type: &str) -> bool {
+    self.encoding.as_deref() == Some("base64") || !is_json(content_type)
+  }
+}
+
+/// Maximum number of records of one page of a paginated read
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// Query options for paginated reads
+///
+/// `page_size` caps the number of records of a page, at most
+/// `MAX_PAGE_SIZE`, and `cursor` continues a read from the `next_cursor`
+/// of the previous page. Either of them switches the response from a
+/// plain array of records to a page with a `next_cursor`, which is set
+/// whenever records were left out of the page, also when `page_size` was
+/// capped. Reads with neither are streamed in full, without a cap.
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+pub struct PageQuery {
+  pub cursor: Option<String>,
+  pub page_size: Option<usize>,
+}
+impl PageQuery {
+  /// Checks if a paginated response was requested
+  pub fn is_paged(&self) -> bool {
+    self.cursor.is_some() || self.page_size.is_some()
+  }
+
+  /// Get the number of records of a page, at least one and at most
+  /// `MAX_PAGE_SIZE`
+  pub fn page_size(&self) -> usize {
+    self.page_size.unwrap_or(MAX_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+  }
+}
+
+/// Cursor
+///
+/// The position of a paginated read, as the index of the next record and
+/// the index of the last record of the read. Both are absolute record
+/// indexes, so a cursor stays valid while new records are appended.
+///
+/// ## Examples
+/// ```
+/// use rapiddb_web::api::helpers::Cursor;
+///
+/// let cursor = Cursor { next: 0, end: 9 };
+/// assert_eq!(Cursor::decode(&cursor.encode()), Some(cursor));
+///
+/// let (first, last, next) = cursor.page(4);
+/// assert_eq!((first, last), (0, 3));
+/// assert_eq!(next, Some(Cursor { next: 4, end: 9 }));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+  pub next: usize,
+  pub end: usize,
+}
+impl Cursor {
+  /// Encode the cursor as an opaque URL safe string
+  pub fn encode(&self) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD
+      .encode(format!("{}:{}", self.next, self.end))
+  }
+
+  /// Decode a cursor encoded with `encode`
+  ///
+  /// Returns `None` if `value` is not a valid cursor.
+  pub fn decode(value: &str) -> Option<Self> {
+    let value = base64::engine::general_purpose::URL_SAFE_NO_PAD
+      .decode(value)
+      .ok()
+      .and_then(|x| String::from_utf8(x).ok())?;
+    let (next, end) = value.split_once(':')?;
+    let (next, end) = (next.parse().ok()?, end.parse().ok()?);
+
+    (next <= end).then_some(Self { next, end })
+  }
+
+  /// Split a page of at most `page_size` records off the cursor
+  ///
+  /// Returns the indexes of the first and last record of the page, and
+  /// the cursor of the next page, if there is one.
+  pub fn page(&self, page_size: usize) -> (usize, usize, Option<Self>) {
+    let last = self.end.min(self.next.saturating_add(page_size.max(1) - 1));
+    let next =
+      (last < self.end).then_some(Self { next: last + 1, end: self.end });
+
+    (self.next, last, next)
+  }
+}
+
+/// Encode a page of records as a JSON object with the `records` and the
+/// `next_cursor`, which is `null` on the last page
+///
+/// ## Examples
+/// ```
+/// use rapiddb_web::api::helpers::to_page;
+///
+/// let page = to_page(&[b"{}".to_vec()], None, false);
+/// assert_eq!(
+///   serde_json::from_slice::<serde_json::Value>(&page).unwrap(),
+///   serde_json::json!({ "records": [{}], "next_cursor": null })
+/// );
+/// ```
+pub fn to_page(
+  data: &[Vec<u8>],
+  next_cursor: Option<Cursor>,
+  base64: bool,
+) -> Vec<u8> {
+  serde_json::to_vec(&serde_json::json!({
+    "records": data.iter().map(|x| encode_record(x, base64)).collect::<Vec<_>>(),
+    "next_cursor": next_cursor.map(|x| x.encode()),
+  }))
+  .unwrap_or_default()
+}
+
+/// Content type of the records of a sensor, as declared by the
+/// `content_type` key in the sensor metadata `meta`
+///
+/// Defaults to `application/json`. Records posted as CBOR or MessagePack
+/// are transcoded to this format before they are stored.
+///
+/// ## Examples
+/// ```
+/// use rapiddb_web::api::helpers::content_type;
+///
+/// assert_eq!(content_type(b"{\"content_type\":\"application/cbor\"}"), "application/cbor");
+/// assert_eq!(content_type(b""), "application/json");
+/// ```
+pub fn content_type(meta: &[u8]) -> String {
+  serde_json::from_slice::<serde_json::Value>(meta)
+    .ok()
+    .and_then(|x| x["content_type"].as_str().map(|x| x.to_owned()))
+    .unwrap_or_else(|| "application/json".to_owned())
+}
+
+/// Checks if `content_type` is a JSON media type
+///
+/// ## Examples
+/// ```
+/// use rapiddb_web::api::helpers::is_json;
+///
+/// assert!(is_json("application/json"));
+/// assert!(is_json("application/geo+json"));
+/// assert!(!is_json("application/octet-stream"));
+/// ```
+pub fn is_json(content_type: &str) -> bool {
+  let media_type = content_type.split(';').next().unwrap_or_default().trim();
+  media_type == "application/json" || media_type.ends_with("+json")
+}
+
+/// Checks if `content_type` is a CBOR media type
+///
+/// ## Examples
+/// ```
+/// use rapiddb_web::api::helpers::is_cbor;
+///
+/// assert!(is_cbor("application/cbor"));
+/// assert!(!is_cbor("application/json"));
+/// ```
+pub fn is_cbor(content_type: &str) -> bool {
+  let media_type = content_type.split(';').next().unwrap_or_default().trim();
+  media_type == "application/cbor" || media_type.ends_with("+cbor")
+}
+
+/// Checks if `content_type` is a MessagePack media type
+///
+/// ## Examples
+/// ```
+/// use rapiddb_web::api::helpers::is_msgpack;
+///
+/// assert!(is_msgpack("application/msgpack"));
+/// assert!(is_msgpack("application/x-msgpack"));
+/// assert!(!is_msgpack("application/json"));
+/// ```
+pub fn is_msgpack(content_type: &str) -> bool {
+  let media_type = content_type.split(';').next().unwrap_or_default().trim();
+  matches!(
+    media_type,
+    "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack"
+  )
+}
+
+/// Decode `value` encoded as `content_type`, which is JSON, CBOR or
+/// MessagePack
+///
+/// ## Examples
+/// ```
+/// use rapiddb_web::api::helpers::decode_value;
+///
+/// assert_eq!(
+///   decode_value(&[0xa1, 0x61, 0x6e, 0x01], "application/cbor"),
+///   Ok(serde_json::json!({ "n": 1 }))
+/// );
+/// assert!(decode_value(b"", "application/octet-stream").is_err());
+/// ```
+pub fn decode_value(
+  value: &[u8],
+  content_type: &str,
+) -> Result<serde_json::Value, String> {
+  if is_json(content_type) {
+    return serde_json::from_slice(value).map_err(|x| x.to_string());
+  }
+  if is_cbor(content_type) {
+    return ciborium::from_reader(value).map_err(|x| x.to_string());
+  }
+  if is_msgpack(content_type) {
+    return rmp_serde::from_slice(value).map_err(|x| x.to_string());
+  }
+
+  Err(format!("unsupported content type {content_type}"))
+}
+
+/// Encode `value` as `content_type`, which is JSON, CBOR or MessagePack
+///
+/// ## Examples
+/// ```
+/// use rapiddb_web::api::helpers::encode_value;
+///
+/// assert_eq!(
+///   encode_value(&serde_json::json!({ "n": 1 }), "application/msgpack"),
+///   Ok(vec![0x81, 0xa1, b'n', 0x01])
+/// );
+/// ```
+pub fn encode_value(
+  value: &serde_json::Value,
+  content_type: &str,
+) -> Result<Vec<u8>, String> {
+  if is_json(content_type) {
+    return serde_json::to_vec(value).map_err(|x| x.to_string());
+  }
+  if is_cbor(content_type) {
+    let mut result = vec![];
+    ciborium::into_writer(value, &mut result).map_err(|x| x.to_string())?;
+    return Ok(result);
+  }
+  if is_msgpack(content_type) {
+    return rmp_serde::to_vec(value).map_err(|x| x.to_string());
+  }
+
+  Err(format!("unsupported content type {content_type}"))
+}
+
+/// Transcode `value` from the `from` to the `to` content type, values
+/// already in the `to` format are returned as is
+///
+/// ## Examples
+/// ```
+/// use rapiddb_web::api::helpers::transcode;
+///
+/// assert_eq!(
+///   transcode(&[0xa1, 0x61, 0x6e, 0x01], "application/cbor", "application/json"),
+///   Ok(b"{\"n\":1}".to_vec())
+/// );
+/// ```
+pub fn transcode(
+  value: &[u8],
+  from: &str,
+  to: &str,
+) -> Result<Vec<u8>, String> {
+  let media_type =
+    |x: &str| x.split(';').next().unwrap_or_default().trim().to_owned();
+  if media_type(from) == media_type(to) {
+    return Ok(value.to_vec());
+  }
+
+  encode_value(&decode_value(value, from)?, to)
+}
+
+/// Decode a stored record of `content_type` into a value, or a base64
+/// string if it can not be decoded
+///
+/// ## Examples
+/// ```
+/// use rapiddb_web::api::helpers::to_value;
+///
+/// assert_eq!(to_value(b"{}", "application/json"), serde_json::json!({}));
+/// assert_eq!(to_value(b"{}", "text/plain"), serde_json::json!("e30="));
+/// ```
+pub fn to_value(value: &[u8], content_type: &str) -> serde_json::Value {
+  decode_value(value, content_type).unwrap_or_else(|_| {
+    base64::engine::general_purpose::STANDARD.encode(value).into()
+  })
+}
+
+/// Checks if the `accept` header explicitly accepts `content_type`
+///
+/// ## Examples
+/// ```
+/// use rapiddb_web::api::helpers::accepts;
+///
+/// let accept = Some("application/octet-stream, */*;q=0.1".to_owned());
+/// assert!(accepts(&accept, "application/octet-stream"));
+/// assert!(!accepts(&None, "application/octet-stream"));
+/// ```
+pub fn accepts(accept: &Option<String>, content_type: &str) -> bool {
+  accept
+    .as_deref()
+    .unwrap_or_default()
+    .split(',')
+    .any(|x| x.split(';').next().unwrap_or_default().trim() == content_type)
+}
+
+/// Response format of a read, negotiated with the `Accept` header
+///
+/// ## Examples
+/// ```
+/// use rapiddb_web::api::helpers::Format;
+///
+/// let accept = Some("text/csv".to_owned());
+/// assert_eq!(Format::negotiate(&accept), Format::Csv);
+/// assert_eq!(Format::negotiate(&None), Format::Json);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+  /// A JSON array of records
+  Json,
+  /// Length-prefixed records, see `to_length_prefixed`
+  OctetStream,
+  /// One JSON record per line, see `to_ndjson`
+  NdJson,
+  /// One record per row, see `to_csv`
+  Csv,
+  /// A CBOR array of records, decoded from their stored format
+  Cbor,
+  /// A MessagePack array of records, decoded from their stored format
+  MsgPack,
+}
+impl Format {
+  /// Pick the format explicitly accepted by the `accept` header, which
+  /// defaults to `Json`
+  pub fn negotiate(accept: &Option<String>) -> Self {
+    [Self::OctetStream, Self::NdJson, Self::Csv, Self::Cbor, Self::MsgPack]
+      .into_iter()
+      .find(|x| accepts(accept, x.content_type()))
+      .unwrap_or(Self::Json)
+  }
+
+  /// Media type of the format
+  pub fn content_type(&self) -> &'static str {
+    match self {
+      Self::Json => "application/json",
+      Self::OctetStream => "application/octet-stream",
+      Self::NdJson => "application/x-ndjson",
+      Self::Csv => "text/csv",
+      Self::Cbor => "application/cbor",
+      Self::MsgPack => "application/msgpack",
+    }
+  }
+
+  /// Checks if the format is CBOR or MessagePack, which carry decoded
+  /// records rather than JSON text
+  pub fn is_binary(&self) -> bool {
+    matches!(self, Self::Cbor | Self::MsgPack)
+  }
+
+  /// Encode `value` in the format, see `encode_value`
+  ///
+  /// Formats which can not carry an arbitrary value are encoded as JSON.
+  pub fn encode(&self, value: &serde_json::Value) -> Vec<u8> {
+    let content_type = match self.is_binary() {
+      true => self.content_type(),
+      false => "application/json",
+    };
+
+    encode_value(value, content_type).unwrap_or_default()
+  }
+}
+
+/// Respond with the JSON document `value`, transcoded to CBOR or
+/// MessagePack if the `accept` header asks for one of them
+///
+/// Records embedded in the document are transcoded as they are encoded
+/// in JSON, so base64 encoded records stay base64 strings.
+pub fn reply_document(
+  accept: &Option<String>,
+  value: Vec<u8>,
+) -> Result<warp::hyper::Response<Vec<u8>>, warp::http::Error> {
+  let format = Format::negotiate(accept);
+  let result =
+    warp::hyper::Response::builder().status(warp::http::StatusCode::OK);
+  if !format.is_binary() {
+    return result.body(value);
+  }
+
+  match transcode(&value, "application/json", format.content_type()) {
+    Ok(x) => result
+      .header(warp::http::header::CONTENT_TYPE, format.content_type())
+      .body(x),
+    Err(_) => result.body(value),
+  }
+}
+
+/// Encode records as newline delimited JSON, one record per line, either
+/// as is or as base64 JSON strings
+///
+/// ## Examples
+/// ```
+/// use rapiddb_web::api::helpers::to_ndjson;
+///
+/// assert_eq!(to_ndjson(&[b"{}".to_vec(), b"[]".to_vec()], false), b"{}\n[]\n");
+/// ```
+pub fn to_ndjson(data: &[impl AsRef<[u8]>], base64: bool) -> Vec<u8> {
+  let mut result = vec![];
+
+  for item in data {
+    write_record(&mut result, item.as_ref(), base64);
+    result.push(b'\n');
+  }
+
+  result
+}
+
+/// Stream `records` as newline delimited JSON in a chunked response
+/// body, see `to_ndjson`
+pub fn to_ndjson_body(
+  records: rapiddb::traits::RecordStream,
+  base64: bool,
+) -> warp::hyper::Body {
+  use futures_util::StreamExt;
+
+  let body = records
+    .ready_chunks(BODY_CHUNK_SIZE)
+    .map(move |x| Ok::<_, std::convert::Infallible>(to_ndjson(&x, base64)));
+
+  warp::hyper::Body::wrap_stream(body)
+}
+
+/// Encode the records of several sensors as newline delimited JSON, one
+/// object with the `id`, `index` and `value` of a record per line
+///
+/// The value is base64 encoded if the flag of its record is set.
+///
+/// ## Examples
+/// ```
+/// use rapiddb_web::api::helpers::to_sensors_ndjson;
+///
+/// assert_eq!(
+///   to_sensors_ndjson([("test-0", 1, b"{}".as_slice(), false)]),
+///   b"{\"id\":\"test-0\",\"index\":1,\"value\":{}}\n"
+/// );
+/// ```
+pub fn to_sensors_ndjson<'a>(
+  rows: impl IntoIterator<Item = (&'a str, usize, &'a [u8], bool)>,
+) -> Vec<u8> {
+  let mut result = vec![];
+
+  for (id, index, record, base64) in rows {
+    result.extend_from_slice(b"{\"id\":");
+    result.extend_from_slice(
+      serde_json::to_string(id).unwrap_or_default().as_bytes(),
+    );
+    result
+      .extend_from_slice(format!(",\"index\":{index},\"value\":").as_bytes());
+    write_record(&mut result, record, base64);
+    result.extend_from_slice(b"}\n");
+  }
+
+  result
+}
+
+/// Encode records as CSV, one record per row
+///
+/// Each row starts with the values of `columns`, given per record,
+/// followed by the keys of the record, which is base64 encoded if its
+/// flag is set. Nested objects are flattened into
+/// columns with dotted names, arrays are written as JSON, and records
+/// which are not JSON objects, or are base64 encoded, are written to a
+/// `value` column. The header is the union of the keys of all records,
+/// in the order they are first seen.
+///
+/// ## Examples
+/// ```
+/// use rapiddb_web::api::helpers::to_csv;
+///
+/// let rows = vec![
+///   (vec!["0".to_owned()], b"{\"a\":{\"b\":1}}".as_slice(), false),
+///   (vec!["1".to_owned()], b"{\"c\":\"x,y\"}".as_slice(), false),
+/// ];
+/// assert_eq!(
+///   String::from_utf8(to_csv(&["index"], rows)).unwrap(),
+///   "index,a.b,c\r\n0,1,\r\n1,,\"x,y\"\r\n"
+/// );
+/// ```
+pub fn to_csv<'a>(
+  columns: &[&str],
+  rows: impl IntoIterator<Item = (Vec<String>, &'a [u8], bool)>,
+) -> Vec<u8> {
+  let rows = rows
+    .into_iter()
+    .map(|(values, record, base64)| (values, to_csv_fields(record, base64)))
+    .collect::<Vec<_>>();
+  let keys = to_csv_keys(rows.iter().map(|(_, x)| x));
+
+  let mut result = vec![];
+  write_csv_header(&mut result, columns, &keys);
+  for (values, fields) in &rows {
+    write_csv_fields(&mut result, values, &keys, fields);
+  }
+
+  result
+}
+
+/// Stream `records` from index `start` as CSV in a chunked response
+/// body, with the index of each record in an `index` column, see
+/// `to_csv`
+///
+/// The header is the union of the keys of the first chunk of records,
+/// keys which only appear in later records are left out.
+///
+/// ## Examples
+/// ```no_run
+/// use rapiddb_web::rapiddb::traits::IAsyncDatabase;
+///
+/// let db = std::sync::Arc::new(rapiddb_web::rapiddb::db::MMAVAsyncDatabase::new());
+///
+/// let body = rapiddb_web::api::helpers::to_csv_body(
+///   db.stream_range("test-0", 0, usize::MAX),
+///   0,
+///   false,
+/// );
+/// ```
+pub fn to_csv_body(
+  records: rapiddb::traits::RecordStream,
+  start: usize,
+  base64: bool,
+) -> warp::hyper::Body {
+  use futures_util::StreamExt;
+
+  let mut keys: Option<Vec<String>> = None;
+  let mut index = start;
+
+  let body = records.ready_chunks(BODY_CHUNK_SIZE).map(move |chunk| {
+    let rows =
+      chunk.iter().map(|x| to_csv_fields(x, base64)).collect::<Vec<_>>();
+
+    let mut result = vec![];
+    let keys = keys.get_or_insert_with(|| {
+      let keys = to_csv_keys(rows.iter());
+      write_csv_header(&mut result, &["index"], &keys);
+      keys
+    });
+
+    for fields in &rows {
+      write_csv_fields(&mut result, &[index.to_string()], keys, fields);
+      index += 1;
+    }
+
+    Ok::<_, std::convert::Infallible>(result)
+  });
+
+  warp::hyper::Body::wrap_stream(body)
+}
+
+/// Flatten a record into the fields of a CSV row
+fn to_csv_fields(
+  record: &[u8],
+  base64: bool,
+) -> Vec<(String, serde_json::Value)> {
+  let mut fields = vec![];
+  match serde_json::from_slice::<serde_json::Value>(record) {
+    Ok(x @ serde_json::Value::Object(_)) if !base64 => {
+      flatten_json("", x, &mut fields)
+    }
+    _ => fields.push((
+      "value".to_owned(),
+      serde_json::from_str(encode_record(record, base64).get())
+        .unwrap_or_default(),
+    )),
+  }
+
+  fields
+}
+
+/// Union of the keys of `rows`, in the order they are first seen
+fn to_csv_keys<'a>(
+  rows: impl Iterator<Item = &'a Vec<(String, serde_json::Value)>>,
+) -> Vec<String> {
+  let mut keys: Vec<String> = vec![];
+  let mut seen: std::collections::HashSet<&str> = Default::default();
+
+  for (key, _) in rows.flatten() {
+    if seen.insert(key) {
+      keys.push(key.clone());
+    }
+  }
+
+  keys
+}
+
+/// Write the header of `columns` followed by `keys` to `buffer`
+fn write_csv_header(buffer: &mut Vec<u8>, columns: &[&str], keys: &[String]) {
+  let header = columns.iter().copied().chain(keys.iter().map(|x| x.as_str()));
+  write_csv_row(buffer, header.map(std::borrow::Cow::Borrowed));
+}
+
+/// Write a row of `values` followed by the `fields` of `keys` to `buffer`
+fn write_csv_fields(
+  buffer: &mut Vec<u8>,
+  values: &[String],
+  keys: &[String],
+  fields: &[(String, serde_json::Value)],
+) {
+  let fields = fields
+    .iter()
+    .map(|(key, value)| (key.as_str(), value))
+    .collect::<std::collections::HashMap<_, _>>();
+  let cells = values.iter().map(|x| std::borrow::Cow::Borrowed(x.as_str()));
+  let fields = keys.iter().map(|x| match fields.get(x.as_str()) {
+    None | Some(serde_json::Value::Null) => std::borrow::Cow::Borrowed(""),
+    Some(serde_json::Value::String(x)) => {
+      std::borrow::Cow::Borrowed(x.as_str())
+    }
+    Some(x) => std::borrow::Cow::Owned(x.to_string()),
+  });
+  write_csv_row(buffer, cells.chain(fields));
+}
+
+/// Flatten the nested objects of `value` into `fields`, with the keys
+/// joined by dots
+fn flatten_json(
+  prefix: &str,
+  value: serde_json::Value,
+  fields: &mut Vec<(String, serde_json::Value)>,
+) {
+  match value {
+    serde_json::Value::Object(x) => {
+      for (key, value) in x {
+        let key = match prefix {
+          "" => key,
+          _ => format!("{prefix}.{key}"),
+        };
+        flatten_json(&key, value, fields);
+      }
+    }
+    x => fields.push((prefix.to_owned(), x)),
+  }
+}
+
+/// Write one row of `cells` to `buffer`, quoting cells as needed
+fn write_csv_row<'a>(
+  buffer: &mut Vec<u8>,
+  cells: impl Iterator<Item = std::borrow::Cow<'a, str>>,
+) {
+  for (i, cell) in cells.enumerate() {
+    if i > 0 {
+      buffer.push(b',');
+    }
+
+    if cell.contains([',', '"', '\r', '\n']) {
+      buffer.push(b'"');
+      buffer.extend_from_slice(cell.replace('"', "\"\"").as_bytes());
+      buffer.push(b'"');
+    } else {
+      buffer.extend_from_slice(cell.as_bytes());
+    }
+  }
+
+  buffer.extend_from_slice(b"\r\n");
+}
+
+/// Encode a stored record into raw JSON, either as is or as a base64
+/// JSON string
+///
+/// ## Examples
+/// ```
+/// use rapiddb_web::api::helpers::encode_record;
+///
+/// assert_eq!(encode_record(b"{}", false).get(), "{}");
+/// assert_eq!(encode_record(b"{}", true).get(), "\"e30=\"");
+/// ```
+pub fn encode_record(
+  value: &[u8],
+  base64: bool,
+) -> Box<serde_json::value::RawValue> {
+  if base64 {
+    return serde_json::value::to_raw_value(
+      &base64::engine::general_purpose::STANDARD.encode(value),
+    )
+    .unwrap_or_default();
+  }
+
+  to_raw_json(value)
+}
+
+/// Write a stored record as JSON to `buffer`, either as is or as a
+/// base64 JSON string, see `encode_record`
+///
+/// Valid JSON is written straight from `value`, without an intermediate
+/// copy.
+///
+/// ## Examples
+/// ```
+/// use rapiddb_web::api::helpers::write_record;
+///
+/// let mut buffer = vec![];
+/// write_record(&mut buffer, b"{}", false);
+/// write_record(&mut buffer, b"{}", true);
+/// assert_eq!(buffer, b"{}\"e30=\"");
+/// ```
+pub fn write_record(buffer: &mut Vec<u8>, value: &[u8], base64: bool) {
+  if !base64
+    && serde_json::from_slice::<&serde_json::value::RawValue>(value).is_ok()
+  {
+    buffer.extend_from_slice(value);
+    return;
+  }
+
+  buffer.extend_from_slice(encode_record(value, base64).get().as_bytes());
+}
+
+/// Number of records written to a streamed response body at once
+const BODY_CHUNK_SIZE: usize = 256;
+
+/// Stream `records` as a JSON array in a chunked response body, either
+/// as is or as base64 JSON strings
+///
+/// ## Examples
+/// ```no_run
+/// use rapiddb_web::rapiddb::traits::IAsyncDatabase;
+///
+/// let db = std::sync::Arc::new(rapiddb_web::rapiddb::db::MMAVAsyncDatabase::new());
+///
+/// let body = rapiddb_web::api::helpers::to_json_array_body(
+///   db.stream_range("test-0", 0, usize::MAX),
+///   false,
+/// );
+/// ```
+pub fn to_json_array_body(
+  records: rapiddb::traits::RecordStream,
+  base64: bool,
+) -> warp::hyper::Body {
+  use futures_util::StreamExt;
+
+  let records =
+    records.ready_chunks(BODY_CHUNK_SIZE).enumerate().map(move |(i, chunk)| {
+      let mut result = vec![];
+
+      for (j, item) in chunk.iter().enumerate() {
+        if i > 0 || j > 0 {
+          result.push(b',');
+        }
+        write_record(&mut result, item, base64);
+      }
+
+      result
+    });
+
+  let body = futures_util::stream::once(async { b"[".to_vec() })
+    .chain(records)
+    .chain(futures_util::stream::once(async { b"]".to_vec() }));
+
+  warp::hyper::Body::wrap_stream(body.map(Ok::<_, std::convert::Infallible>))
+}
+
+/// Stream `records` as a CBOR or MessagePack array in a chunked
+/// response body, each decoded from its stored `content_type` as by
+/// `to_value`
+///
+/// CBOR arrays are written with an indefinite length. MessagePack has no
+/// such arrays, and the stream may end before the range it was asked
+/// for, so MessagePack records are buffered until the stream ends, and
+/// the array header is written for the number of records read.
+///
+/// ## Examples
+/// ```no_run
+/// use rapiddb_web::api::helpers::Format;
+/// use rapiddb_web::rapiddb::traits::IAsyncDatabase;
+///
+/// let db = std::sync::Arc::new(rapiddb_web::rapiddb::db::MMAVAsyncDatabase::new());
+///
+/// let body = rapiddb_web::api::helpers::to_binary_array_body(
+///   db.stream_range("test-0", 0, 9),
+///   Format::Cbor,
+///   "application/json".to_owned(),
+/// );
+/// ```
+pub fn to_binary_array_body(
+  records: rapiddb::traits::RecordStream,
+  format: Format,
+  content_type: String,
+) -> warp::hyper::Body {
+  use futures_util::{FutureExt, StreamExt};
+
+  let records = records.ready_chunks(BODY_CHUNK_SIZE).map(move |chunk| {
+    let encoded = chunk
+      .iter()
+      .flat_map(|x| format.encode(&to_value(x, &content_type)))
+      .collect::<Vec<_>>();
+    (chunk.len(), encoded)
+  });
+
+  if format == Format::MsgPack {
+    let body = records
+      .fold((0, vec![]), |(len, mut body), (count, chunk)| async move {
+        body.extend(chunk);
+        (len + count, body)
+      })
+      .map(|(len, body)| {
+        let header = match len {
+          0..=15 => vec![0x90 | len as u8],
+          16..=0xffff => [&[0xdc][..], &(len as u16).to_be_bytes()].concat(),
+          _ => [&[0xdd][..], &(len as u32).to_be_bytes()].concat(),
+        };
+        [header, body].concat()
+      });
+
+    return warp::hyper::Body::wrap_stream(
+      futures_util::stream::once(body).map(Ok::<_, std::convert::Infallible>),
+    );
+  }
+
+  let body = futures_util::stream::once(async { vec![0x9f] })
+    .chain(records.map(|(_, x)| x))
+    .chain(futures_util::stream::once(async { vec![0xff] }));
+
+  warp::hyper::Body::wrap_stream(body.map(Ok::<_, std::convert::Infallible>))
+}
+
+/// Stream `records` as length-prefixed frames in a chunked response
+/// body, see `to_length_prefixed`
+///
+/// ## Examples
+/// ```no_run
+/// use rapiddb_web::rapiddb::traits::IAsyncDatabase;
+///
+/// let db = std::sync::Arc::new(rapiddb_web::rapiddb::db::MMAVAsyncDatabase::new());
+///
+/// let body = rapiddb_web::api::helpers::to_length_prefixed_body(
+///   db.stream_range("test-0", 0, usize::MAX),
+/// );
+/// ```
+pub fn to_length_prefixed_body(
+  records: rapiddb::traits::RecordStream,
+) -> warp::hyper::Body {
+  use futures_util::StreamExt;
+
+  let body = records
+    .ready_chunks(BODY_CHUNK_SIZE)
+    .map(|x| Ok::<_, std::convert::Infallible>(to_length_prefixed(&x)));
+
+  warp::hyper::Body::wrap_stream(body)
+}
+
+/// Frame records as a length-prefixed stream, where each record is
+/// preceded by its length as a big-endian `u32`
+///
+/// ## Examples
+/// ```
+/// use rapiddb_web::api::helpers::to_length_prefixed;
+///
+/// assert_eq!(
+///   to_length_prefixed(&[b"ab".to_vec()]),
+///   vec![0, 0, 0, 2, b'a', b'b']
+/// );
+/// ```
+pub fn to_length_prefixed(data: &[impl AsRef<[u8]>]) -> Vec<u8> {
+  let mut result = Vec::with_capacity(
+    data.iter().map(|x| x.as_ref().len() + 4).sum::<usize>(),
+  );
+
+  for item in data {
+    let item = item.as_ref();
+    result.extend_from_slice(&(item.len() as u32).to_be_bytes());
+    result.extend_from_slice(item);
+  }
+
+  result
+}
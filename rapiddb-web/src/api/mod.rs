@@ -1,24 +1,56 @@
 //! RapidDB REST API
 
 mod api_endpoint;
+mod config;
+mod follower;
 pub mod helpers;
+mod rw_lock_database;
+mod snapshots;
 mod v0;
 
+pub use config::ApiConfig;
+pub use config::IngestMode;
+
 use rapiddb::traits::IAsyncDatabase;
 use warp::{Filter, Rejection, Reply};
 
 /// Sensor API Endpoints
 pub fn endpoints(
-  db: impl IAsyncDatabase,
+  db: impl IAsyncDatabase + 'static,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
-  let _db = std::sync::Arc::new(tokio::sync::RwLock::new(db));
+  let _db = std::sync::Arc::new(db);
 
   api_endpoint::get().or(v0::endpoints(_db))
 }
 
 /// Sensor API Endpoints
-pub fn endpoints_with_arc_rwlock(
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+pub fn endpoints_with_arc(
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized + 'static>,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
   api_endpoint::get().or(v0::endpoints(db))
 }
+
+/// Sensor API Endpoints
+///
+/// The database no longer needs a `RwLock`, as it locks sensors
+/// individually. The API only takes read locks on `db`, and sync
+/// methods, such as `subscribe`, block the worker thread while a writer
+/// holds the lock.
+#[deprecated(note = "use `endpoints_with_arc` without the `RwLock`")]
+pub fn endpoints_with_arc_rwlock(
+  db: std::sync::Arc<
+    tokio::sync::RwLock<impl IAsyncDatabase + ?Sized + 'static>,
+  >,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  endpoints_with_arc(std::sync::Arc::new(
+    rw_lock_database::RwLockDatabase::new(db),
+  ))
+}
+
+/// Sensor API Endpoints with all
+pub fn endpoints_with_all(
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized + 'static>,
+  config: ApiConfig,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  api_endpoint::get().or(v0::endpoints_with_all(db, config))
+}
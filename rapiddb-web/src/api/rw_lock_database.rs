@@ -0,0 +1,248 @@
+use rapiddb::db::{
+  Change, Notification, Snapshot, Subscription, SubscriptionFilter, WriteBatch,
+};
+use rapiddb::errors::Error;
+use rapiddb::traits::IAsyncDatabase;
+
+/// RwLock Database
+///
+/// Implements `IAsyncDatabase` for a database behind a
+/// `tokio::sync::RwLock`, as passed to the deprecated
+/// `endpoints_with_arc_rwlock`. Every operation takes a read lock, as
+/// the database synchronizes internally, so the lock only excludes
+/// writers which hold the lock outside of the API.
+pub struct RwLockDatabase<T: IAsyncDatabase + ?Sized> {
+  db: std::sync::Arc<tokio::sync::RwLock<T>>,
+}
+impl<T: IAsyncDatabase + ?Sized> RwLockDatabase<T> {
+  /// RwLock Database Constructor
+  pub fn new(db: std::sync::Arc<tokio::sync::RwLock<T>>) -> Self {
+    Self { db }
+  }
+
+  /// Take a read lock on the database from a sync method
+  ///
+  /// Blocks the worker thread while a writer holds the lock, which is
+  /// only supported on the multi-threaded runtime.
+  fn read_blocking(&self) -> tokio::sync::RwLockReadGuard<'_, T> {
+    match self.db.try_read() {
+      Ok(x) => x,
+      Err(_) => tokio::task::block_in_place(|| self.db.blocking_read()),
+    }
+  }
+}
+
+#[async_trait::async_trait]
+impl<T: IAsyncDatabase + ?Sized> IAsyncDatabase for RwLockDatabase<T> {
+  async fn contains(&self, id: &str) -> bool {
+    self.db.read().await.contains(id).await
+  }
+
+  async fn len(&self, id: &str) -> usize {
+    self.db.read().await.len(id).await
+  }
+
+  async fn get(&self, id: &str, rec_id: usize) -> Vec<u8> {
+    self.db.read().await.get(id, rec_id).await
+  }
+
+  async fn post(&self, id: &str, value: &[u8]) -> Result<usize, Error> {
+    self.db.read().await.post(id, value).await
+  }
+
+  async fn post_with_key(
+    &self,
+    id: &str,
+    key: &str,
+    value: &[u8],
+  ) -> Result<usize, Error> {
+    self.db.read().await.post_with_key(id, key, value).await
+  }
+
+  async fn post_batch(
+    &self,
+    id: &str,
+    values: &[&[u8]],
+  ) -> Result<Vec<usize>, Error> {
+    self.db.read().await.post_batch(id, values).await
+  }
+
+  async fn post_many(
+    &self,
+    records: &[(&str, &[u8])],
+  ) -> Result<Vec<usize>, Error> {
+    self.db.read().await.post_many(records).await
+  }
+
+  async fn commit(&self, batch: &WriteBatch) -> Result<Vec<usize>, Error> {
+    self.db.read().await.commit(batch).await
+  }
+
+  async fn get_meta(&self, id: &str) -> Vec<u8> {
+    self.db.read().await.get_meta(id).await
+  }
+
+  async fn post_meta(&self, id: &str, value: Vec<u8>) -> Result<(), Error> {
+    self.db.read().await.post_meta(id, value).await
+  }
+
+  async fn get_aggregates(&self, id: &str) -> Vec<u8> {
+    self.db.read().await.get_aggregates(id).await
+  }
+
+  async fn get_latest(&self, id: &str) -> Vec<u8> {
+    self.db.read().await.get_latest(id).await
+  }
+
+  async fn get_latest_with_limit(
+    &self,
+    id: &str,
+    limit: usize,
+  ) -> Vec<Vec<u8>> {
+    self.db.read().await.get_latest_with_limit(id, limit).await
+  }
+
+  async fn get_range(
+    &self,
+    id: &str,
+    start: usize,
+    end: usize,
+  ) -> Vec<Vec<u8>> {
+    self.db.read().await.get_range(id, start, end).await
+  }
+
+  async fn get_all_meta(&self) -> std::collections::HashMap<String, Vec<u8>> {
+    self.db.read().await.get_all_meta().await
+  }
+
+  async fn get_all_aggregates(
+    &self,
+  ) -> std::collections::HashMap<String, Vec<u8>> {
+    self.db.read().await.get_all_aggregates().await
+  }
+
+  async fn get_all_latest(&self) -> std::collections::HashMap<String, Vec<u8>> {
+    self.db.read().await.get_all_latest().await
+  }
+
+  async fn get_all_latest_with_limit(
+    &self,
+    limit: usize,
+  ) -> std::collections::HashMap<String, Vec<Vec<u8>>> {
+    self.db.read().await.get_all_latest_with_limit(limit).await
+  }
+
+  async fn snapshot(&self) -> Snapshot {
+    self.db.read().await.snapshot().await
+  }
+
+  async fn get_latest_as_of(&self, snapshot: &Snapshot, id: &str) -> Vec<u8> {
+    self.db.read().await.get_latest_as_of(snapshot, id).await
+  }
+
+  async fn get_latest_with_limit_as_of(
+    &self,
+    snapshot: &Snapshot,
+    id: &str,
+    limit: usize,
+  ) -> Vec<Vec<u8>> {
+    self.db.read().await.get_latest_with_limit_as_of(snapshot, id, limit).await
+  }
+
+  async fn get_range_as_of(
+    &self,
+    snapshot: &Snapshot,
+    id: &str,
+    start: usize,
+    end: usize,
+  ) -> Vec<Vec<u8>> {
+    self.db.read().await.get_range_as_of(snapshot, id, start, end).await
+  }
+
+  async fn get_all_latest_as_of(
+    &self,
+    snapshot: &Snapshot,
+  ) -> std::collections::HashMap<String, Vec<u8>> {
+    self.db.read().await.get_all_latest_as_of(snapshot).await
+  }
+
+  async fn get_all_latest_with_limit_as_of(
+    &self,
+    snapshot: &Snapshot,
+    limit: usize,
+  ) -> std::collections::HashMap<String, Vec<Vec<u8>>> {
+    self.db.read().await.get_all_latest_with_limit_as_of(snapshot, limit).await
+  }
+
+  async fn changes_since(&self, lsn: usize, limit: usize) -> Vec<Change> {
+    self.db.read().await.changes_since(lsn, limit).await
+  }
+
+  async fn get_offset(&self, consumer: &str, id: &str) -> usize {
+    self.db.read().await.get_offset(consumer, id).await
+  }
+
+  async fn poll(
+    &self,
+    consumer: &str,
+    id: &str,
+    max: usize,
+  ) -> (usize, Vec<(usize, Vec<u8>)>) {
+    self.db.read().await.poll(consumer, id, max).await
+  }
+
+  async fn commit_offset(
+    &self,
+    consumer: &str,
+    id: &str,
+    offset: usize,
+  ) -> Result<(), Error> {
+    self.db.read().await.commit_offset(consumer, id, offset).await
+  }
+
+  fn notifications(&self) -> tokio::sync::broadcast::Receiver<Notification> {
+    self.read_blocking().notifications()
+  }
+
+  fn subscribe(&self, filter: SubscriptionFilter) -> Subscription {
+    self.read_blocking().subscribe(filter)
+  }
+}
+
+#[tokio::test]
+async fn test_endpoints_with_arc_rwlock() {
+  let db_path = ".temp/test/rw_lock_database/test_endpoints_with_arc_rwlock";
+  let db = std::sync::Arc::new(tokio::sync::RwLock::new(
+    rapiddb::db::MMAVAsyncDatabase::new_with_all(db_path, Default::default()),
+  ));
+
+  #[allow(deprecated)]
+  let api = super::endpoints_with_arc_rwlock(db.clone());
+
+  let id = "test-0";
+  let value = serde_json::json!({ "key": "value" });
+
+  let resp = warp::test::request()
+    .method("POST")
+    .json(&value)
+    .path(&format!("/api/v0/{id}"))
+    .reply(&api)
+    .await;
+  assert_eq!(resp.status(), 202);
+
+  let resp = warp::test::request()
+    .method("GET")
+    .path(&format!("/api/v0/{id}/latest"))
+    .reply(&api)
+    .await;
+  assert_eq!(resp.status(), 200);
+  assert_eq!(
+    serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+    value
+  );
+
+  db.write().await.post(id, b"{}").await.unwrap();
+  assert_eq!(db.read().await.len(id).await, 2);
+
+  std::fs::remove_dir_all(db_path).unwrap_or_default();
+}
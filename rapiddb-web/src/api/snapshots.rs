@@ -0,0 +1,62 @@
+use rapiddb::db::Snapshot;
+
+/// Number of recent snapshots kept, older snapshots expire
+const SNAPSHOT_LIMIT: usize = 1024;
+
+/// Snapshots
+///
+/// A registry of the most recent snapshots taken through the API, so
+/// later requests can read as of a snapshot with the `as_of` query
+/// parameter.
+#[derive(Clone, Default)]
+pub struct Snapshots {
+  inner: std::sync::Arc<std::sync::Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+  as_of: u64,
+  snapshots: std::collections::VecDeque<(u64, std::sync::Arc<Snapshot>)>,
+}
+
+impl Snapshots {
+  /// Insert `snapshot` and get its `as_of` id
+  pub fn insert(&self, snapshot: Snapshot) -> u64 {
+    let mut inner = self.inner.lock().unwrap();
+
+    inner.as_of += 1;
+    let as_of = inner.as_of;
+
+    inner.snapshots.push_back((as_of, std::sync::Arc::new(snapshot)));
+    while inner.snapshots.len() > SNAPSHOT_LIMIT {
+      inner.snapshots.pop_front();
+    }
+
+    as_of
+  }
+
+  /// Get the snapshot with `as_of` id, if it has not expired
+  pub fn get(&self, as_of: u64) -> Option<std::sync::Arc<Snapshot>> {
+    self
+      .inner
+      .lock()
+      .unwrap()
+      .snapshots
+      .iter()
+      .find(|(x, _)| *x == as_of)
+      .map(|(_, x)| x.clone())
+  }
+
+  /// Resolve the optional `as_of` query parameter of a read
+  ///
+  /// Returns `Err` if `as_of` is given, but is unknown or has expired.
+  pub fn resolve(
+    &self,
+    as_of: Option<u64>,
+  ) -> Result<Option<std::sync::Arc<Snapshot>>, ()> {
+    match as_of {
+      Some(x) => self.get(x).map(Some).ok_or(()),
+      None => Ok(None),
+    }
+  }
+}
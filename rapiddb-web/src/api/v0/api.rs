@@ -9,6 +9,7 @@ pub fn get() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         "resources": [
           {"endpoint": "/api/v0/sensors", "description": "Discover resources available for all sensors"},
           {"endpoint": "/api/v0/:id", "description": "Discover resources available for sensor with :id"},
+          {"endpoint": "/api/v0/changes?since=:lsn", "description": "GET changes to every sensor after log sequence number :lsn"},
         ],
         "description": "Discover resources available under API v0",
       }))
@@ -0,0 +1,169 @@
+use crate::api::helpers::{
+  content_type, encode_record, reply_document, to_raw_json, with_db, ReadQuery,
+};
+use rapiddb::traits::IAsyncDatabase;
+
+use warp::{Filter, Rejection, Reply};
+
+/// Number of changes returned if no `limit` is given
+const DEFAULT_LIMIT: usize = 1000;
+
+/// Maximum number of changes returned by one request
+const MAX_LIMIT: usize = 10000;
+
+/// Query options for the change feed
+///
+/// `since` is the log sequence number of the last change seen, zero by
+/// default, and `limit` caps the number of changes returned.
+/// `encoding=base64` encodes every record as a base64 JSON string.
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+pub struct ChangesQuery {
+  #[serde(default)]
+  pub since: usize,
+  pub limit: Option<usize>,
+  pub encoding: Option<String>,
+}
+
+/// GET /api/v0/changes
+pub fn get(
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  warp::path!("api" / "v0" / "changes")
+    .and(warp::get())
+    .and(warp::query::<ChangesQuery>())
+    .and(warp::header::optional::<String>("accept"))
+    .and(with_db(db))
+    .and_then(_get)
+}
+
+pub async fn _get(
+  query: ChangesQuery,
+  accept: Option<String>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+) -> Result<impl warp::Reply, std::convert::Infallible> {
+  let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+  let changes = db.changes_since(query.since, limit).await;
+
+  let content_types = db
+    .get_all_meta()
+    .await
+    .into_iter()
+    .map(|(key, value)| (key, content_type(&value)))
+    .collect::<std::collections::HashMap<_, _>>();
+  let read_query =
+    ReadQuery { encoding: query.encoding.clone(), ..Default::default() };
+  let base64 = |id: &str| {
+    read_query.base64(
+      content_types.get(id).map(|x| x.as_str()).unwrap_or("application/json"),
+    )
+  };
+
+  let result = serde_json::json!({
+    "changes": changes
+      .iter()
+      .map(|x| match x.rec_id {
+        Some(rec_id) => serde_json::json!({
+          "lsn": x.lsn,
+          "id": x.id,
+          "rec_id": rec_id,
+          "value": encode_record(&x.value, base64(&x.id)),
+        }),
+        None => serde_json::json!({
+          "lsn": x.lsn,
+          "id": x.id,
+          "meta": to_raw_json(&x.value),
+        }),
+      })
+      .collect::<Vec<_>>(),
+    "next": changes.last().map(|x| x.lsn).unwrap_or(query.since),
+  });
+
+  Ok(reply_document(&accept, result.to_string().into()))
+}
+
+#[tokio::test]
+async fn test_get() {
+  let database_test_factory =
+    rapiddb::db::DatabaseTestFactory::new(".temp/test/changes/test_get");
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+    let id1 = "test-1";
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path("/api/v0/changes")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!({ "changes": [], "next": 0 })
+    );
+
+    db.post(id, serde_json::json!({ "id": &id }).to_string().as_bytes())
+      .await
+      .unwrap();
+    db.post_meta(id1, serde_json::json!({ "name": &id1 }).to_string().into())
+      .await
+      .unwrap();
+    db.post(id1, serde_json::json!({ "id": &id1 }).to_string().as_bytes())
+      .await
+      .unwrap();
+    db.post(
+      id,
+      serde_json::json!({ "id": &id, "n": 1 }).to_string().as_bytes(),
+    )
+    .await
+    .unwrap();
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path("/api/v0/changes")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!({
+        "changes": [
+          { "lsn": 1, "id": &id, "rec_id": 0, "value": { "id": &id } },
+          { "lsn": 2, "id": &id1, "meta": { "name": &id1 } },
+          { "lsn": 3, "id": &id1, "rec_id": 0, "value": { "id": &id1 } },
+          { "lsn": 4, "id": &id, "rec_id": 1, "value": { "id": &id, "n": 1 } },
+        ],
+        "next": 4,
+      })
+    );
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path("/api/v0/changes?since=1&limit=2")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!({
+        "changes": [
+          { "lsn": 2, "id": &id1, "meta": { "name": &id1 } },
+          { "lsn": 3, "id": &id1, "rec_id": 0, "value": { "id": &id1 } },
+        ],
+        "next": 3,
+      })
+    );
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path("/api/v0/changes?since=4")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!({ "changes": [], "next": 4 })
+    );
+  }
+}
@@ -1,38 +1,69 @@
 mod api;
+mod changes;
 mod sensor;
+mod sensor_after;
 mod sensor_aggregates;
+mod sensor_consumer;
+mod sensor_events;
 mod sensor_latest;
 mod sensor_latest_limit;
 mod sensor_meta;
 mod sensor_range;
 mod sensor_single;
+mod sensor_ws;
 mod sensors;
 mod sensors_aggregates;
+mod sensors_batch;
 mod sensors_latest;
 mod sensors_latest_limit;
 mod sensors_meta;
+mod sensors_snapshot;
+mod sensors_ws;
 
 use warp::{Filter, Rejection, Reply};
 
 use rapiddb::traits::IAsyncDatabase;
 
+use crate::api::snapshots::Snapshots;
+use crate::api::ApiConfig;
+
 /// Sensor API Endpoints
 pub fn endpoints(
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized + 'static>,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  endpoints_with_all(db, Default::default())
+}
+
+/// Sensor API Endpoints with all
+pub fn endpoints_with_all(
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized + 'static>,
+  config: ApiConfig,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  let snapshots = Snapshots::default();
+
   api::get()
-    .or(sensors_latest_limit::get(db.clone()))
-    .or(sensors_latest::get(db.clone()))
+    .or(changes::get(db.clone()))
+    .or(sensors_latest_limit::get(db.clone(), snapshots.clone()))
+    .or(sensors_latest::get(db.clone(), snapshots.clone()))
     .or(sensors_meta::get(db.clone()))
     .or(sensors_aggregates::get(db.clone()))
+    .or(sensors_batch::post(db.clone()))
+    .or(sensors_snapshot::post(db.clone(), snapshots.clone()))
+    .or(sensors_ws::get(db.clone()))
     .or(sensors::get())
-    .or(sensor_range::get(db.clone()))
-    .or(sensor_latest_limit::get(db.clone()))
+    .or(sensor_consumer::poll(db.clone()))
+    .or(sensor_consumer::post(db.clone()))
+    .or(sensor_consumer::get(db.clone()))
+    .or(sensor_ws::get(db.clone()))
+    .or(sensor_events::get(db.clone()))
+    .or(sensor_after::get(db.clone()))
+    .or(sensor_range::get(db.clone(), snapshots.clone()))
+    .or(sensor_latest_limit::get(db.clone(), snapshots.clone()))
     .or(sensor_single::get(db.clone()))
-    .or(sensor_latest::get(db.clone()))
+    .or(sensor_latest::get(db.clone(), snapshots))
     .or(sensor_meta::post(db.clone()))
     .or(sensor_meta::get(db.clone()))
     .or(sensor_aggregates::get(db.clone()))
-    .or(sensor::post(db.clone()))
+    .or(sensor::post(db.clone(), config))
     .or(sensor::get(db))
 }
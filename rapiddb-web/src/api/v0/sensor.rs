@@ -1,11 +1,16 @@
-use crate::api::helpers::with_db;
+use crate::api::helpers::{
+  content_type, encode_path_segment, is_cbor, is_json, is_msgpack, transcode,
+  with_config, with_db,
+};
+use crate::api::{ApiConfig, IngestMode};
+use rapiddb::errors::Error;
 use rapiddb::traits::IAsyncDatabase;
 
 use warp::{Filter, Rejection, Reply};
 
 /// GET /api/v0/:String
 pub fn get(
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
   warp::path!("api" / "v0" / String)
     .and(warp::get())
@@ -15,21 +20,26 @@ pub fn get(
 
 pub async fn _get(
   id: String,
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
-  if db.write().await.contains(&id).await {
+  if db.contains(&id).await {
     return Ok(warp::hyper::Response::builder()
         .status(warp::http::StatusCode::OK)
         .body(format!("{}", &serde_json::json!({
           "resources": [
             {"endpoint": format!("/api/v0/{id}/latest"), "description": format!("GET latest measurment from {id}")},
-            {"endpoint": format!("/api/v0/{id}/latest/:count"), "description": format!("GET latest :count measurments from {id}")},
+            {"endpoint": format!("/api/v0/{id}/latest/:count"), "description": format!("GET latest :count measurments from {id}, paginated with ?page_size= and ?cursor=, as NDJSON, CSV, CBOR or MessagePack by Accept")},
             {"endpoint": format!("/api/v0/{id}/:id"), "description": format!("GET measurment by id from {id}")},
-            {"endpoint": format!("/api/v0/{id}/:start/:end"), "description": format!("GET measurment by id in range :start to :end from {id}")},
+            {"endpoint": format!("/api/v0/{id}/:start/:end"), "description": format!("GET measurment by id in range :start to :end from {id}, paginated with ?page_size= and ?cursor=, as NDJSON, CSV, CBOR or MessagePack by Accept")},
+            {"endpoint": format!("/api/v0/{id}/after/:index"), "description": format!("GET measurments after :index from {id}, waiting up to ?timeout= seconds for one, -1 waits for the first")},
             {"endpoint": format!("/api/v0/{id}/meta"), "description": format!("GET metadata from {id}")},
             {"endpoint": format!("/api/v0/{id}/aggregates"), "description": format!("GET aggregates from {id}")},
-            {"endpoint": format!("/api/v0/{id}"), "description": format!("POST data to {id}")},
+            {"endpoint": format!("/api/v0/{id}"), "description": format!("POST data to {id}, as CBOR or MessagePack by Content-Type")},
             {"endpoint": format!("/api/v0/{id}/meta"), "description": format!("POST metadata to {id}")},
+            {"endpoint": format!("/api/v0/{id}/ws"), "description": format!("WebSocket of new records from {id}")},
+            {"endpoint": format!("/api/v0/{id}/events"), "description": format!("Server-Sent Events of new records, metadata and aggregates from {id}")},
+            {"endpoint": format!("/api/v0/{id}/consumers/:consumer"), "description": format!("GET or POST the committed offset of :consumer of {id}")},
+            {"endpoint": format!("/api/v0/{id}/consumers/:consumer/records"), "description": format!("GET records after the committed offset of :consumer of {id}")},
           ],
           "description": format!("Discover resources available under {id}"),
         }))));
@@ -44,29 +54,93 @@ pub async fn _get(
 
 /// POST /api/v0/:String
 pub fn post(
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+  config: ApiConfig,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
   warp::path!("api" / "v0" / String)
     .and(warp::post())
     .and(warp::body::content_length_limit(1024 * 16))
     .and(warp::body::bytes())
+    .and(warp::header::optional::<String>("idempotency-key"))
+    .and(warp::header::optional::<String>("content-type"))
     .and(with_db(db))
+    .and(with_config(config))
     .and_then(_post)
 }
 
 /// POST /api/v0/:String
+///
+/// Records posted as CBOR or MessagePack are transcoded to the content
+/// type of the sensor before they are stored.
 pub async fn _post(
   id: String,
   data: warp::hyper::body::Bytes,
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  idempotency_key: Option<String>,
+  request_content_type: Option<String>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+  config: ApiConfig,
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
-  db.write().await.post(&id, &data).await;
+  let stored = content_type(&db.get_meta(&id).await);
 
-  Ok(
-    warp::hyper::Response::builder()
-      .status(warp::http::StatusCode::ACCEPTED)
-      .body(""),
-  )
+  let data = match request_content_type {
+    Some(x) if is_cbor(&x) || is_msgpack(&x) => {
+      match transcode(&data, &x, &stored) {
+        Ok(x) => warp::hyper::body::Bytes::from(x),
+        Err(error) => {
+          return Ok(
+            warp::hyper::Response::builder()
+              .status(warp::http::StatusCode::BAD_REQUEST)
+              .body(serde_json::json!({ "errors": [error] }).to_string()),
+          );
+        }
+      }
+    }
+    _ => data,
+  };
+
+  if config.ingest_mode == IngestMode::Json && is_json(&stored) {
+    if let Err(error) = serde_json::from_slice::<serde::de::IgnoredAny>(&data) {
+      return Ok(
+        warp::hyper::Response::builder()
+          .status(warp::http::StatusCode::BAD_REQUEST)
+          .body(
+            serde_json::json!({ "errors": [error.to_string()] }).to_string(),
+          ),
+      );
+    }
+  }
+
+  let result = match idempotency_key {
+    Some(key) => db.post_with_key(&id, &key, &data).await,
+    None => db.post(&id, &data).await,
+  };
+
+  match result {
+    Ok(rec_id) => Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::ACCEPTED)
+        .header(
+          warp::http::header::LOCATION,
+          format!("/api/v0/{}/{rec_id}", encode_path_segment(&id)),
+        )
+        .body(serde_json::json!({ "rec_id": rec_id }).to_string()),
+    ),
+    Err(Error::SchemaViolation(errors)) => Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::UNPROCESSABLE_ENTITY)
+        .body(serde_json::json!({ "errors": errors }).to_string()),
+    ),
+    Err(error @ Error::InvalidId(_)) => Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::BAD_REQUEST)
+        .body(serde_json::json!({ "errors": [error.to_string()] }).to_string()),
+    ),
+    Err(_) => Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::INTERNAL_SERVER_ERROR)
+        .body(Default::default()),
+    ),
+  }
 }
 
 #[tokio::test]
@@ -86,10 +160,9 @@ async fn test_get() {
       .await;
     assert_eq!(resp.status(), 404);
 
-    db.write()
+    db.post(id, serde_json::json!({ "id": &id }).to_string().as_bytes())
       .await
-      .post(id, serde_json::json!({ "id": &id }).to_string().as_bytes())
-      .await;
+      .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -123,7 +196,20 @@ async fn test_post() {
       .path(&format!("/api/v0/{id}"))
       .reply(&api)
       .await;
+    assert_eq!(resp.status(), 400);
+
+    let resp = warp::test::request()
+      .method("POST")
+      .json(&serde_json::json!({ "id": &id }))
+      .path(&format!("/api/v0/{id}"))
+      .reply(&api)
+      .await;
     assert_eq!(resp.status(), 202);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!({ "rec_id": 0 })
+    );
+    assert_eq!(resp.headers()["location"], format!("/api/v0/{id}/0"));
 
     let resp = warp::test::request()
       .method("POST")
@@ -132,12 +218,304 @@ async fn test_post() {
       .reply(&api)
       .await;
     assert_eq!(resp.status(), 202);
-    assert_eq!(resp.body().len(), 0);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!({ "rec_id": 1 })
+    );
+    assert_eq!(resp.headers()["location"], format!("/api/v0/{id}/1"));
+
+    let resp = warp::test::request()
+      .method("POST")
+      .json(&serde_json::json!({ "id": "test 1?" }))
+      .path("/api/v0/test%201%3F")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 202);
+    assert_eq!(resp.headers()["location"], "/api/v0/test%201%3F/0");
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(resp.headers()["location"].to_str().unwrap())
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!({ "id": "test 1?" })
+    );
 
-    let id_db = db.write().await.get_latest(id).await;
+    let id_db = db.get_latest(id).await;
     assert_eq!(
       id_db,
       serde_json::json!({ "id": &id }).to_string().as_bytes().to_vec()
     );
   }
 }
+
+#[tokio::test]
+async fn test_post_transcoded() {
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensor/test_post_transcoded",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+    let id1 = "test-1";
+    let value = serde_json::json!({ "n": 1 });
+
+    let mut data = vec![];
+    ciborium::into_writer(&value, &mut data).unwrap();
+
+    let resp = warp::test::request()
+      .method("POST")
+      .header("content-type", "application/cbor")
+      .body(&data)
+      .path(&format!("/api/v0/{id}"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 202);
+    assert_eq!(db.get_latest(id).await, b"{\"n\":1}");
+
+    let resp = warp::test::request()
+      .method("POST")
+      .header("content-type", "application/cbor")
+      .body([0xff])
+      .path(&format!("/api/v0/{id}"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 400);
+
+    db.post_meta(id1, br#"{"content_type":"application/cbor"}"#.to_vec())
+      .await
+      .unwrap();
+
+    let resp = warp::test::request()
+      .method("POST")
+      .header("content-type", "application/msgpack")
+      .body(rmp_serde::to_vec(&value).unwrap())
+      .path(&format!("/api/v0/{id1}"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 202);
+    assert_eq!(db.get_latest(id1).await, data);
+
+    let resp = warp::test::request()
+      .method("GET")
+      .header("accept", "application/msgpack")
+      .path(&format!("/api/v0/{id1}/0"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "application/msgpack");
+    assert_eq!(resp.body().to_vec(), rmp_serde::to_vec(&value).unwrap());
+  }
+}
+
+#[tokio::test]
+async fn test_post_invalid_id() {
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensor/test_post_invalid_id",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    for id in [".changes", ".commit", ".hidden"] {
+      let resp = warp::test::request()
+        .method("POST")
+        .json(&serde_json::json!({ "id": id }))
+        .path(&format!("/api/v0/{id}"))
+        .reply(&api)
+        .await;
+      assert_eq!(resp.status(), 400);
+
+      let resp = warp::test::request()
+        .method("POST")
+        .json(&serde_json::json!({}))
+        .path(&format!("/api/v0/{id}/meta"))
+        .reply(&api)
+        .await;
+      assert_eq!(resp.status(), 400);
+
+      let mut batch = rapiddb::db::WriteBatch::new();
+      batch.post("test-0", b"{}");
+      batch.post(id, b"{}");
+      assert!(db.commit(&batch).await.is_err());
+      assert!(db.post_many(&[("test-0", b"{}"), (id, b"{}")]).await.is_err());
+    }
+
+    assert!(!db.contains("test-0").await);
+    assert_eq!(db.changes_since(0, 10).await.len(), 0);
+  }
+}
+
+#[tokio::test]
+async fn test_post_schema() {
+  let database_test_factory =
+    rapiddb::db::DatabaseTestFactory::new(".temp/test/sensor/test_post_schema");
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+
+    db.post_meta(
+      id,
+      serde_json::json!({
+        "schema": {
+          "type": "object",
+          "properties": { "temp": { "type": "number" } },
+          "required": ["temp"],
+        }
+      })
+      .to_string()
+      .as_bytes()
+      .to_vec(),
+    )
+    .await
+    .unwrap();
+
+    let resp = warp::test::request()
+      .method("POST")
+      .json(&serde_json::json!({ "temp": 4.00 }))
+      .path(&format!("/api/v0/{id}"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 202);
+
+    let resp = warp::test::request()
+      .method("POST")
+      .json(&serde_json::json!({ "temp": "4.00" }))
+      .path(&format!("/api/v0/{id}"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 422);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap()
+        ["errors"]
+        .as_array()
+        .unwrap()
+        .len(),
+      1
+    );
+
+    let resp = warp::test::request()
+      .method("POST")
+      .json(&serde_json::json!([{ "temp": 4.00 }]))
+      .path(&format!("/api/v0/{id}"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 422);
+
+    assert_eq!(
+      db.get_latest_with_limit(id, 10).await,
+      vec![serde_json::json!({ "temp": 4.00 }).to_string().as_bytes().to_vec()]
+    );
+  }
+}
+
+#[tokio::test]
+async fn test_post_raw() {
+  let database_test_factory =
+    rapiddb::db::DatabaseTestFactory::new(".temp/test/sensor/test_post_raw");
+
+  for db in database_test_factory.get_instance().values() {
+    let api = crate::api::endpoints_with_all(
+      (*db).clone(),
+      ApiConfig { ingest_mode: IngestMode::Raw },
+    );
+
+    let id = "test-0";
+
+    let resp = warp::test::request()
+      .method("POST")
+      .body(id)
+      .path(&format!("/api/v0/{id}"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 202);
+
+    assert_eq!(db.get_latest(id).await, id.as_bytes());
+  }
+}
+
+#[tokio::test]
+async fn test_post_idempotency_key() {
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensor/test_post_idempotency_key",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+    let mut notifications = db.notifications();
+
+    for key in ["key-0", "key-0", "key-1", "key-0"] {
+      let resp = warp::test::request()
+        .method("POST")
+        .header("idempotency-key", key)
+        .json(&serde_json::json!({ "key": key }))
+        .path(&format!("/api/v0/{id}"))
+        .reply(&api)
+        .await;
+      assert_eq!(resp.status(), 202);
+      assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+        serde_json::json!({ "rec_id": if key == "key-0" { 0 } else { 1 } })
+      );
+    }
+
+    assert_eq!(db.get_latest_with_limit(id, 10).await.len(), 2);
+
+    for rec_id in [0, 1] {
+      assert_eq!(
+        notifications.try_recv().unwrap(),
+        rapiddb::db::Notification::Record { id: id.to_owned(), rec_id }
+      );
+    }
+    assert!(notifications.try_recv().is_err());
+  }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_post_concurrent() {
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensor/test_post_concurrent",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let n = 50;
+
+    let mut tasks = vec![];
+    for i in 0..4 {
+      let api = api.clone();
+      tasks.push(tokio::spawn(async move {
+        for _ in 0..n {
+          let resp = warp::test::request()
+            .method("POST")
+            .json(&serde_json::json!({ "temp": i }))
+            .path(&format!("/api/v0/test-{i}"))
+            .reply(&api)
+            .await;
+          assert_eq!(resp.status(), 202);
+        }
+      }));
+    }
+
+    for task in tasks {
+      task.await.unwrap();
+    }
+
+    for i in 0..4 {
+      assert_eq!(
+        db.get_latest_with_limit(&format!("test-{i}"), n * 2).await.len(),
+        n
+      );
+    }
+  }
+}
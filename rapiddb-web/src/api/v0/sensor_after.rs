@@ -0,0 +1,190 @@
+use crate::api::helpers::{
+  content_type, encode_record, to_csv, to_length_prefixed, to_ndjson, to_value,
+  with_db, Format, ReadQuery,
+};
+use rapiddb::traits::IAsyncDatabase;
+
+use warp::{Filter, Rejection, Reply};
+
+/// Seconds waited for a new record if no `timeout` is given
+const DEFAULT_TIMEOUT: u64 = 30;
+
+/// Maximum number of seconds waited by one request
+const MAX_TIMEOUT: u64 = 300;
+
+/// Maximum number of records returned by one request
+const MAX_RECORDS: usize = 1000;
+
+/// Query options for long-polling
+///
+/// `timeout` is the number of seconds to wait for a new record.
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+pub struct WaitQuery {
+  pub timeout: Option<u64>,
+}
+
+/// GET /api/v0/:String/after/:i64
+///
+/// An `index` of `-1` waits for the first record of the sensor.
+pub fn get(
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  warp::path!("api" / "v0" / String / "after" / i64)
+    .and(warp::get())
+    .and(warp::query::<WaitQuery>())
+    .and(warp::query::<ReadQuery>())
+    .and(warp::header::optional::<String>("accept"))
+    .and(with_db(db))
+    .and_then(_get)
+}
+
+pub async fn _get(
+  id: String,
+  index: i64,
+  wait_query: WaitQuery,
+  query: ReadQuery,
+  accept: Option<String>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+) -> Result<impl warp::Reply, std::convert::Infallible> {
+  let timeout = std::time::Duration::from_secs(
+    wait_query.timeout.unwrap_or(DEFAULT_TIMEOUT).min(MAX_TIMEOUT),
+  );
+
+  let Some(start) = index.checked_add(1).and_then(|x| usize::try_from(x).ok())
+  else {
+    return Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::BAD_REQUEST)
+        .body(
+          serde_json::json!({ "errors": ["invalid index"] }).to_string().into(),
+        ),
+    );
+  };
+
+  let Some(len) = db.wait_for(&id, start, timeout).await else {
+    return Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::NO_CONTENT)
+        .body(Default::default()),
+    );
+  };
+
+  let end = len.min(start + MAX_RECORDS) - 1;
+  let data = db.get_range(&id, start, end).await;
+
+  let format = Format::negotiate(&accept);
+  let stored = content_type(&db.get_meta(&id).await);
+  let base64 = query.base64(&stored);
+  let body = match format {
+    Format::OctetStream => to_length_prefixed(&data),
+    Format::NdJson => to_ndjson(&data, base64),
+    Format::Csv => {
+      let rows = data
+        .iter()
+        .enumerate()
+        .map(|(i, x)| (vec![(start + i).to_string()], x.as_slice(), base64));
+      to_csv(&["index"], rows)
+    }
+    Format::Cbor | Format::MsgPack => format.encode(
+      &data.iter().map(|x| to_value(x, &stored)).collect::<Vec<_>>().into(),
+    ),
+    Format::Json => {
+      let result = serde_json::to_vec(
+        &data.iter().map(|x| encode_record(x, base64)).collect::<Vec<_>>(),
+      )
+      .unwrap_or_default();
+
+      return Ok(
+        warp::hyper::Response::builder()
+          .status(warp::http::StatusCode::OK)
+          .body(result),
+      );
+    }
+  };
+
+  Ok(
+    warp::hyper::Response::builder()
+      .status(warp::http::StatusCode::OK)
+      .header(warp::http::header::CONTENT_TYPE, format.content_type())
+      .body(body),
+  )
+}
+
+#[tokio::test]
+async fn test_get() {
+  let database_test_factory =
+    rapiddb::db::DatabaseTestFactory::new(".temp/test/sensor_after/test_get");
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+
+    for i in 0..3 {
+      db.post(id, serde_json::json!({ "n": i }).to_string().as_bytes())
+        .await
+        .unwrap();
+    }
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/after/0"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!([{ "n": 1 }, { "n": 2 }])
+    );
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/after/2?timeout=0"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 204);
+
+    let request = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/after/2?timeout=10"))
+      .reply(&api);
+    let post = async {
+      tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+      db.post(id, serde_json::json!({ "n": 3 }).to_string().as_bytes())
+        .await
+        .unwrap();
+    };
+    let (resp, _) = tokio::join!(request, post);
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!([{ "n": 3 }])
+    );
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/after/-2"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 400);
+
+    let id = "test-1";
+
+    let request = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/after/-1?timeout=10"))
+      .reply(&api);
+    let post = async {
+      tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+      db.post(id, serde_json::json!({ "n": 0 }).to_string().as_bytes())
+        .await
+        .unwrap();
+    };
+    let (resp, _) = tokio::join!(request, post);
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!([{ "n": 0 }])
+    );
+  }
+}
@@ -1,31 +1,28 @@
-use crate::api::helpers::with_db;
+use crate::api::helpers::{reply_document, with_db};
 use rapiddb::traits::IAsyncDatabase;
 
 use warp::{Filter, Rejection, Reply};
 
 /// GET /api/v0/:String/aggregates
 pub fn get(
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
   warp::path!("api" / "v0" / String / "aggregates")
     .and(warp::get())
+    .and(warp::header::optional::<String>("accept"))
     .and(with_db(db))
     .and_then(_get)
 }
 
 pub async fn _get(
   id: String,
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  accept: Option<String>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
-  let lock = db.read().await;
-  let result = lock.get_aggregates(&id).await;
+  let result = db.get_aggregates(&id).await;
 
   if !result.is_empty() {
-    return Ok(
-      warp::hyper::Response::builder()
-        .status(warp::http::StatusCode::OK)
-        .body(result),
-    );
+    return Ok(reply_document(&accept, result));
   }
 
   Ok(
@@ -53,10 +50,9 @@ async fn test_get() {
       .await;
     assert_eq!(resp.status(), 404);
 
-    db.write()
+    db.post(id, serde_json::json!({"temp": 8.00}).to_string().as_bytes())
       .await
-      .post(id, serde_json::json!({"temp": 8.00}).to_string().as_bytes())
-      .await;
+      .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -69,10 +65,9 @@ async fn test_get() {
       serde_json::json!({"temp_avg": 8.0, "temp_sum": 8.0, "temp_sum_count": 1.0})
     );
 
-    db.write()
+    db.post(id, serde_json::json!({"temp": 4.00}).to_string().as_bytes())
       .await
-      .post(id, serde_json::json!({"temp": 4.00}).to_string().as_bytes())
-      .await;
+      .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
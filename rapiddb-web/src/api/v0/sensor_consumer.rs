@@ -0,0 +1,229 @@
+use crate::api::helpers::{
+  content_type, encode_record, reply_document, with_db, ReadQuery,
+};
+use rapiddb::errors::Error;
+use rapiddb::traits::IAsyncDatabase;
+
+use warp::{Filter, Rejection, Reply};
+
+/// Number of records polled if no `max` is given
+const DEFAULT_MAX: usize = 100;
+
+/// Maximum number of records polled by one request
+const MAX_MAX: usize = 10000;
+
+/// Query options for polling records
+///
+/// `max` caps the number of records returned, `encoding=base64` encodes
+/// every record as a base64 JSON string.
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+pub struct PollQuery {
+  pub max: Option<usize>,
+  pub encoding: Option<String>,
+}
+
+/// Body of an offset commit
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct CommitBody {
+  pub offset: usize,
+}
+
+/// GET /api/v0/:String/consumers/:String
+pub fn get(
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  warp::path!("api" / "v0" / String / "consumers" / String)
+    .and(warp::get())
+    .and(with_db(db))
+    .and_then(_get)
+}
+
+pub async fn _get(
+  id: String,
+  consumer: String,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+) -> Result<impl warp::Reply, std::convert::Infallible> {
+  let offset = db.get_offset(&consumer, &id).await;
+
+  Ok(
+    warp::hyper::Response::builder()
+      .status(warp::http::StatusCode::OK)
+      .body(serde_json::json!({ "offset": offset }).to_string()),
+  )
+}
+
+/// GET /api/v0/:String/consumers/:String/records
+pub fn poll(
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  warp::path!("api" / "v0" / String / "consumers" / String / "records")
+    .and(warp::get())
+    .and(warp::query::<PollQuery>())
+    .and(warp::header::optional::<String>("accept"))
+    .and(with_db(db))
+    .and_then(_poll)
+}
+
+pub async fn _poll(
+  id: String,
+  consumer: String,
+  query: PollQuery,
+  accept: Option<String>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+) -> Result<impl warp::Reply, std::convert::Infallible> {
+  let max = query.max.unwrap_or(DEFAULT_MAX).min(MAX_MAX);
+  let (offset, records) = db.poll(&consumer, &id, max).await;
+
+  let base64 = ReadQuery { encoding: query.encoding, ..Default::default() }
+    .base64(&content_type(&db.get_meta(&id).await));
+
+  let result = serde_json::json!({
+    "offset": offset,
+    "next": records.last().map(|(x, _)| x + 1).unwrap_or(offset),
+    "records": records
+      .iter()
+      .map(|(rec_id, value)| serde_json::json!({
+        "rec_id": rec_id,
+        "value": encode_record(value, base64),
+      }))
+      .collect::<Vec<_>>(),
+  });
+
+  Ok(reply_document(&accept, result.to_string().into()))
+}
+
+/// POST /api/v0/:String/consumers/:String
+pub fn post(
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  warp::path!("api" / "v0" / String / "consumers" / String)
+    .and(warp::post())
+    .and(warp::body::content_length_limit(1024 * 16))
+    .and(warp::body::json())
+    .and(with_db(db))
+    .and_then(_post)
+}
+
+pub async fn _post(
+  id: String,
+  consumer: String,
+  body: CommitBody,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+) -> Result<impl warp::Reply, std::convert::Infallible> {
+  match db.commit_offset(&consumer, &id, body.offset).await {
+    Ok(_) => Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::ACCEPTED)
+        .body(Default::default()),
+    ),
+    Err(Error::IndexOutOfRange) => Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::BAD_REQUEST)
+        .body(
+          serde_json::json!({ "errors": [Error::IndexOutOfRange.to_string()] })
+            .to_string(),
+        ),
+    ),
+    Err(_) => Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::INTERNAL_SERVER_ERROR)
+        .body(Default::default()),
+    ),
+  }
+}
+
+#[tokio::test]
+async fn test_poll() {
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensor_consumer/test_poll",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+
+    for i in 0..3 {
+      db.post(id, serde_json::json!({ "n": i }).to_string().as_bytes())
+        .await
+        .unwrap();
+    }
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/consumers/consumer-0"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!({ "offset": 0 })
+    );
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/consumers/consumer-0/records?max=2"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!({
+        "offset": 0,
+        "next": 2,
+        "records": [
+          { "rec_id": 0, "value": { "n": 0 } },
+          { "rec_id": 1, "value": { "n": 1 } },
+        ],
+      })
+    );
+
+    let resp = warp::test::request()
+      .method("POST")
+      .path(&format!("/api/v0/{id}/consumers/consumer-0"))
+      .json(&serde_json::json!({ "offset": 2 }))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 202);
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/consumers/consumer-0/records"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!({
+        "offset": 2,
+        "next": 3,
+        "records": [{ "rec_id": 2, "value": { "n": 2 } }],
+      })
+    );
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/consumers/consumer-1/records"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap()
+        ["records"]
+        .as_array()
+        .unwrap()
+        .len(),
+      3
+    );
+
+    let resp = warp::test::request()
+      .method("POST")
+      .path(&format!("/api/v0/{id}/consumers/consumer-0"))
+      .json(&serde_json::json!({ "offset": 4 }))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 400);
+
+    assert_eq!(db.get_offset("consumer-0", id).await, 2);
+  }
+}
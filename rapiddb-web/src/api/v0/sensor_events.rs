@@ -0,0 +1,158 @@
+use crate::api::follower::{Followed, Follower};
+use crate::api::helpers::{content_type, encode_record, with_db, ReadQuery};
+use crate::api::v0::sensor_ws::StreamQuery;
+use rapiddb::db::SubscriptionFilter;
+use rapiddb::traits::IAsyncDatabase;
+
+use warp::{Filter, Rejection, Reply};
+
+/// GET /api/v0/:String/events
+pub fn get(
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized + 'static>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  warp::path!("api" / "v0" / String / "events")
+    .and(warp::get())
+    .and(warp::query::<StreamQuery>())
+    .and(warp::sse::last_event_id::<usize>())
+    .and(with_db(db))
+    .and_then(_get)
+}
+
+/// State of an event stream
+struct Events<D: IAsyncDatabase + ?Sized> {
+  db: std::sync::Arc<D>,
+  follower: Follower<D>,
+  read_query: ReadQuery,
+  aggregates: Vec<u8>,
+  pending: Option<warp::sse::Event>,
+}
+
+pub async fn _get(
+  id: String,
+  mut query: StreamQuery,
+  last_event_id: Option<usize>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized + 'static>,
+) -> Result<impl warp::Reply, std::convert::Infallible> {
+  if let Some(x) = last_event_id {
+    let Some(start) = x.checked_add(1) else {
+      return Ok(warp::Reply::into_response(
+        warp::hyper::Response::builder()
+          .status(warp::http::StatusCode::BAD_REQUEST)
+          .body(
+            serde_json::json!({ "errors": ["invalid Last-Event-ID"] })
+              .to_string(),
+          ),
+      ));
+    };
+    query.start = Some(start);
+  }
+
+  let events = Events {
+    follower: Follower::new(
+      db.clone(),
+      SubscriptionFilter::Id(id.clone()),
+      query.start,
+    )
+    .await,
+    aggregates: db.get_aggregates(&id).await,
+    read_query: ReadQuery { encoding: query.encoding, ..Default::default() },
+    pending: None,
+    db,
+  };
+
+  let stream = futures_util::stream::unfold(events, |mut x| async move {
+    if let Some(event) = x.pending.take() {
+      return Some((Ok::<_, std::convert::Infallible>(event), x));
+    }
+
+    let event = match x.follower.next().await? {
+      Followed::Record { id, rec_id, value } => {
+        let base64 =
+          x.read_query.base64(&content_type(&x.db.get_meta(&id).await));
+
+        let aggregates = x.db.get_aggregates(&id).await;
+        if aggregates != x.aggregates {
+          x.pending = Some(
+            warp::sse::Event::default()
+              .event("aggregates")
+              .data(String::from_utf8_lossy(&aggregates)),
+          );
+          x.aggregates = aggregates;
+        }
+
+        warp::sse::Event::default()
+          .id(rec_id.to_string())
+          .event("record")
+          .data(encode_record(&value, base64).get())
+      }
+      Followed::Meta { value, .. } => warp::sse::Event::default()
+        .event("meta")
+        .data(String::from_utf8_lossy(&value)),
+    };
+
+    Some((Ok(event), x))
+  });
+
+  Ok(warp::Reply::into_response(warp::sse::reply(
+    warp::sse::keep_alive().stream(stream),
+  )))
+}
+
+#[tokio::test]
+async fn test_get() {
+  let database_test_factory =
+    rapiddb::db::DatabaseTestFactory::new(".temp/test/sensor_events/test_get");
+
+  for db in database_test_factory.get_instance().values() {
+    let id = "test-0";
+
+    for i in 0..3 {
+      db.post(id, serde_json::json!({ "n": i }).to_string().as_bytes())
+        .await
+        .unwrap();
+    }
+
+    let resp =
+      _get(id.to_owned(), Default::default(), Some(0), (*db).clone()).await;
+    let mut body = warp::Reply::into_response(resp.unwrap()).into_body();
+
+    let mut data = String::new();
+    while !data.contains("\"n\":2") {
+      let chunk =
+        warp::hyper::body::HttpBody::data(&mut body).await.unwrap().unwrap();
+      data += &String::from_utf8_lossy(&chunk);
+    }
+    assert!(!data.contains("\"n\":0"));
+    assert!(data.contains("event:record\ndata:{\"n\":1}\nid:1\n"));
+
+    db.post_meta(id, serde_json::json!({ "name": &id }).to_string().into())
+      .await
+      .unwrap();
+
+    while !data.contains("event:meta") {
+      let chunk =
+        warp::hyper::body::HttpBody::data(&mut body).await.unwrap().unwrap();
+      data += &String::from_utf8_lossy(&chunk);
+    }
+    assert!(data.contains("event:meta\ndata:{\"name\":\"test-0\"}\n"));
+  }
+}
+
+#[tokio::test]
+async fn test_get_invalid_last_event_id() {
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensor_events/test_get_invalid_last_event_id",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let resp = warp::test::request()
+      .method("GET")
+      .header("Last-Event-ID", usize::MAX.to_string())
+      .path("/api/v0/test-0/events")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 400);
+  }
+}
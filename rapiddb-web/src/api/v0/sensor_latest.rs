@@ -1,30 +1,65 @@
-use crate::api::helpers::with_db;
+use crate::api::helpers::{
+  content_type, transcode, with_db, with_snapshots, Format, ReadQuery,
+};
+use crate::api::snapshots::Snapshots;
 use rapiddb::traits::IAsyncDatabase;
 
 use warp::{Filter, Rejection, Reply};
 
 /// GET /api/v0/:String/latest
 pub fn get(
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+  snapshots: Snapshots,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
   warp::path!("api" / "v0" / String / "latest")
     .and(warp::get())
+    .and(warp::query::<ReadQuery>())
+    .and(warp::header::optional::<String>("accept"))
     .and(with_db(db))
+    .and(with_snapshots(snapshots))
     .and_then(_get)
 }
 
 pub async fn _get(
   id: String,
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  query: ReadQuery,
+  accept: Option<String>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+  snapshots: Snapshots,
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
-  let result = db.write().await.get_latest(&id).await;
-
-  if !result.is_empty() {
+  let Ok(snapshot) = snapshots.resolve(query.as_of) else {
     return Ok(
       warp::hyper::Response::builder()
-        .status(warp::http::StatusCode::OK)
-        .body(result),
+        .status(warp::http::StatusCode::NOT_FOUND)
+        .body(Default::default()),
     );
+  };
+  let result = match snapshot {
+    Some(snapshot) => db.get_latest_as_of(&snapshot, &id).await,
+    None => db.get_latest(&id).await,
+  };
+
+  if !result.is_empty() {
+    let stored = content_type(&db.get_meta(&id).await);
+    let format = Format::negotiate(&accept);
+    if !format.is_binary() {
+      return Ok(
+        warp::hyper::Response::builder()
+          .status(warp::http::StatusCode::OK)
+          .header(warp::http::header::CONTENT_TYPE, stored)
+          .body(result),
+      );
+    }
+
+    return Ok(match transcode(&result, &stored, format.content_type()) {
+      Ok(x) => warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::OK)
+        .header(warp::http::header::CONTENT_TYPE, format.content_type())
+        .body(x),
+      Err(error) => warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::NOT_ACCEPTABLE)
+        .body(serde_json::json!({ "errors": [error] }).to_string().into()),
+    });
   }
 
   Ok(
@@ -52,10 +87,9 @@ async fn test_get() {
       .await;
     assert_eq!(resp.status(), 404);
 
-    db.write()
+    db.post(id, serde_json::json!({ "id": &id }).to_string().as_bytes())
       .await
-      .post(id, serde_json::json!({ "id": &id }).to_string().as_bytes())
-      .await;
+      .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -68,10 +102,9 @@ async fn test_get() {
       serde_json::json!({ "id": &id })
     );
 
-    db.write()
+    db.post(id, serde_json::json!({ "id1": &id }).to_string().as_bytes())
       .await
-      .post(id, serde_json::json!({ "id1": &id }).to_string().as_bytes())
-      .await;
+      .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -84,13 +117,12 @@ async fn test_get() {
       serde_json::json!({ "id1": &id })
     );
 
-    db.write()
-      .await
-      .post_meta(
-        id1,
-        serde_json::json!({ "id1": &id1 }).to_string().as_bytes().to_vec(),
-      )
-      .await;
+    db.post_meta(
+      id1,
+      serde_json::json!({ "id1": &id1 }).to_string().as_bytes().to_vec(),
+    )
+    .await
+    .unwrap();
     let resp = warp::test::request()
       .method("GET")
       .path(&format!("/api/v0/{id1}/latest"))
@@ -1,46 +1,145 @@
-use crate::api::helpers::with_db;
+use crate::api::helpers::{
+  content_type, to_binary_array_body, to_csv, to_csv_body, to_json_array_body,
+  to_length_prefixed, to_length_prefixed_body, to_ndjson, to_ndjson_body,
+  to_page, to_value, with_db, with_snapshots, Cursor, Format, PageQuery,
+  ReadQuery,
+};
+use crate::api::snapshots::Snapshots;
 use rapiddb::traits::IAsyncDatabase;
 
 use warp::{Filter, Rejection, Reply};
 
 /// GET /api/v0/:String/latest/:usize
 pub fn get(
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized + 'static>,
+  snapshots: Snapshots,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
   warp::path!("api" / "v0" / String / "latest" / usize)
     .and(warp::get())
+    .and(warp::query::<ReadQuery>())
+    .and(warp::query::<PageQuery>())
+    .and(warp::header::optional::<String>("accept"))
     .and(with_db(db))
+    .and(with_snapshots(snapshots))
     .and_then(_get)
 }
 
+/// Get the latest `limit` records, oldest first
+///
+/// Pages are fixed to the records of the first page by their index, so
+/// the cursors of the following pages are not moved by new records.
 pub async fn _get(
   id: String,
   limit: usize,
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  query: ReadQuery,
+  page: PageQuery,
+  accept: Option<String>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized + 'static>,
+  snapshots: Snapshots,
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
-  let data = db.write().await.get_latest_with_limit(&id, limit).await;
+  let Ok(snapshot) = snapshots.resolve(query.as_of) else {
+    return Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::NOT_FOUND)
+        .body(Default::default()),
+    );
+  };
+  let len = match &snapshot {
+    Some(x) => x.len(&id),
+    None => db.len(&id).await,
+  };
+  if len == 0 || limit == 0 {
+    return Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::NOT_FOUND)
+        .body(Default::default()),
+    );
+  }
+
+  let format = Format::negotiate(&accept);
+  let stored = content_type(&db.get_meta(&id).await);
+  let base64 = query.base64(&stored);
+
+  if !page.is_paged() {
+    let records =
+      db.clone().stream_range(&id, len.saturating_sub(limit), len - 1);
+    let body = match format {
+      Format::OctetStream => to_length_prefixed_body(records),
+      Format::NdJson => to_ndjson_body(records, base64),
+      Format::Csv => to_csv_body(records, len.saturating_sub(limit), base64),
+      Format::Json => to_json_array_body(records, base64),
+      Format::Cbor | Format::MsgPack => {
+        to_binary_array_body(records, format, stored)
+      }
+    };
 
-  if !data.is_empty() {
-    let mut result: String = Default::default();
-    result += "[";
-    for item in data {
-      result += &format!("{},", std::str::from_utf8(&item).unwrap_or_default());
+    let mut result =
+      warp::hyper::Response::builder().status(warp::http::StatusCode::OK);
+    if format != Format::Json {
+      result =
+        result.header(warp::http::header::CONTENT_TYPE, format.content_type());
     }
-    result.pop();
-    result += "]";
 
+    return Ok(result.body(body));
+  }
+
+  let cursor = match page.cursor.as_deref().map(Cursor::decode) {
+    Some(Some(x)) if x.end - x.next < limit && x.end < len => x,
+    Some(_) => {
+      return Ok(
+        warp::hyper::Response::builder()
+          .status(warp::http::StatusCode::BAD_REQUEST)
+          .body(
+            serde_json::json!({ "errors": ["invalid cursor"] })
+              .to_string()
+              .into(),
+          ),
+      );
+    }
+    None => Cursor { next: len.saturating_sub(limit), end: len - 1 },
+  };
+  let (start, end, next_cursor) = cursor.page(page.page_size());
+
+  let data = db.get_range(&id, start, end).await;
+  if data.is_empty() {
     return Ok(
       warp::hyper::Response::builder()
-        .status(warp::http::StatusCode::OK)
-        .body(result),
+        .status(warp::http::StatusCode::NOT_FOUND)
+        .body(Default::default()),
     );
   }
 
-  Ok(
-    warp::hyper::Response::builder()
-      .status(warp::http::StatusCode::NOT_FOUND)
-      .body(Default::default()),
-  )
+  let body = match format {
+    Format::OctetStream => to_length_prefixed(&data),
+    Format::NdJson => to_ndjson(&data, base64),
+    Format::Csv => {
+      let rows = data
+        .iter()
+        .enumerate()
+        .map(|(i, x)| (vec![(start + i).to_string()], x.as_slice(), base64));
+      to_csv(&["index"], rows)
+    }
+    Format::Cbor | Format::MsgPack => format.encode(&serde_json::json!({
+      "records": data.iter().map(|x| to_value(x, &stored)).collect::<Vec<_>>(),
+      "next_cursor": next_cursor.map(|x| x.encode()),
+    })),
+    Format::Json => {
+      return Ok(
+        warp::hyper::Response::builder()
+          .status(warp::http::StatusCode::OK)
+          .body(to_page(&data, next_cursor, base64).into()),
+      );
+    }
+  };
+
+  let mut result = warp::hyper::Response::builder()
+    .status(warp::http::StatusCode::OK)
+    .header(warp::http::header::CONTENT_TYPE, format.content_type());
+  if let Some(x) = next_cursor {
+    result = result.header("x-next-cursor", x.encode());
+  }
+
+  Ok(result.body(body.into()))
 }
 
 #[tokio::test]
@@ -63,10 +162,9 @@ async fn test_get() {
       .await;
     assert_eq!(resp.status(), 404);
 
-    db.write()
+    db.post(id, serde_json::json!({ "id": &id }).to_string().as_bytes())
       .await
-      .post(id, serde_json::json!({ "id": &id }).to_string().as_bytes())
-      .await;
+      .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -87,10 +185,9 @@ async fn test_get() {
     );
 
     for _ in 0..limit - 2 {
-      db.write()
+      db.post(id, serde_json::json!({ "id2": &id }).to_string().as_bytes())
         .await
-        .post(id, serde_json::json!({ "id2": &id }).to_string().as_bytes())
-        .await;
+        .unwrap();
     }
 
     let resp = warp::test::request()
@@ -108,10 +205,9 @@ async fn test_get() {
       limit - 1
     );
 
-    db.write()
+    db.post(id, serde_json::json!({ "id3": &id }).to_string().as_bytes())
       .await
-      .post(id, serde_json::json!({ "id3": &id }).to_string().as_bytes())
-      .await;
+      .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -129,10 +225,9 @@ async fn test_get() {
     );
 
     for _ in 0..8 {
-      db.write()
+      db.post(id, serde_json::json!({ "id4": &id }).to_string().as_bytes())
         .await
-        .post(id, serde_json::json!({ "id4": &id }).to_string().as_bytes())
-        .await;
+        .unwrap();
     }
 
     let resp = warp::test::request()
@@ -150,13 +245,12 @@ async fn test_get() {
       limit
     );
 
-    db.write()
-      .await
-      .post_meta(
-        id1,
-        serde_json::json!({ "id1": &id1 }).to_string().as_bytes().to_vec(),
-      )
-      .await;
+    db.post_meta(
+      id1,
+      serde_json::json!({ "id1": &id1 }).to_string().as_bytes().to_vec(),
+    )
+    .await
+    .unwrap();
     let resp = warp::test::request()
       .method("GET")
       .path(&format!("/api/v0/{id1}/latest/{limit}"))
@@ -165,3 +259,98 @@ async fn test_get() {
     assert_eq!(resp.status(), 404);
   }
 }
+
+#[tokio::test]
+async fn test_get_paged() {
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensor_latest_limit/test_get_paged",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+
+    for i in 0..5 {
+      db.post(id, serde_json::json!({ "n": i }).to_string().as_bytes())
+        .await
+        .unwrap();
+    }
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/latest/4?page_size=3"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    let page =
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap();
+    assert_eq!(
+      page["records"],
+      serde_json::json!([{ "n": 1 }, { "n": 2 }, { "n": 3 }])
+    );
+
+    db.post(id, serde_json::json!({ "n": 5 }).to_string().as_bytes())
+      .await
+      .unwrap();
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!(
+        "/api/v0/{id}/latest/4?page_size=3&cursor={}",
+        page["next_cursor"].as_str().unwrap()
+      ))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!({ "records": [{ "n": 4 }], "next_cursor": null })
+    );
+  }
+}
+
+#[tokio::test]
+async fn test_get_streamed() {
+  use crate::api::helpers::MAX_PAGE_SIZE;
+
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensor_latest_limit/test_get_streamed",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+    let n = 1500;
+
+    let values = (0..n)
+      .map(|i| serde_json::json!({ "n": i }).to_string().into_bytes())
+      .collect::<Vec<_>>();
+    db.post_batch(id, &values.iter().map(|x| &x[..]).collect::<Vec<_>>())
+      .await
+      .unwrap();
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/latest/{}", n - 1))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    let records =
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap();
+    assert_eq!(records.as_array().unwrap().len(), n - 1);
+    assert_eq!(records[0], serde_json::json!({ "n": 1 }));
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/latest/{}?page_size={n}", n - 1))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    let page =
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap();
+    assert_eq!(page["records"].as_array().unwrap().len(), MAX_PAGE_SIZE);
+    assert!(page["next_cursor"].is_string());
+  }
+}
@@ -1,30 +1,29 @@
-use crate::api::helpers::with_db;
+use crate::api::helpers::{reply_document, with_db};
+use rapiddb::errors::Error;
 use rapiddb::traits::IAsyncDatabase;
 
 use warp::{Filter, Rejection, Reply};
 
 /// GET /api/v0/:String/meta
 pub fn get(
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
   warp::path!("api" / "v0" / String / "meta")
     .and(warp::get())
+    .and(warp::header::optional::<String>("accept"))
     .and(with_db(db))
     .and_then(_get)
 }
 
 pub async fn _get(
   id: String,
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  accept: Option<String>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
-  let result = db.write().await.get_meta(&id).await;
+  let result = db.get_meta(&id).await;
 
   if !result.is_empty() {
-    return Ok(
-      warp::hyper::Response::builder()
-        .status(warp::http::StatusCode::OK)
-        .body(result),
-    );
+    return Ok(reply_document(&accept, result));
   }
 
   Ok(
@@ -36,7 +35,7 @@ pub async fn _get(
 
 /// POST /api/v0/:String/meta
 pub fn post(
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
   warp::path!("api" / "v0" / String / "meta")
     .and(warp::post())
@@ -49,15 +48,30 @@ pub fn post(
 pub async fn _post(
   id: String,
   data: serde_json::Value,
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
-  db.write().await.post_meta(&id, data.to_string().as_bytes().to_vec()).await;
-
-  Ok(
-    warp::hyper::Response::builder()
-      .status(warp::http::StatusCode::ACCEPTED)
-      .body(""),
-  )
+  match db.post_meta(&id, data.to_string().as_bytes().to_vec()).await {
+    Ok(_) => Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::ACCEPTED)
+        .body(Default::default()),
+    ),
+    Err(Error::InvalidSchema(error)) => Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::UNPROCESSABLE_ENTITY)
+        .body(serde_json::json!({ "errors": [error] }).to_string()),
+    ),
+    Err(error @ Error::InvalidId(_)) => Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::BAD_REQUEST)
+        .body(serde_json::json!({ "errors": [error.to_string()] }).to_string()),
+    ),
+    Err(_) => Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::INTERNAL_SERVER_ERROR)
+        .body(Default::default()),
+    ),
+  }
 }
 
 #[tokio::test]
@@ -77,13 +91,12 @@ async fn test_get() {
       .await;
     assert_eq!(resp.status(), 404);
 
-    db.write()
-      .await
-      .post_meta(
-        id,
-        serde_json::json!({ "id": &id }).to_string().as_bytes().to_vec(),
-      )
-      .await;
+    db.post_meta(
+      id,
+      serde_json::json!({ "id": &id }).to_string().as_bytes().to_vec(),
+    )
+    .await
+    .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -96,13 +109,12 @@ async fn test_get() {
       serde_json::json!({ "id": &id })
     );
 
-    db.write()
-      .await
-      .post_meta(
-        id,
-        serde_json::json!({ "id0": &id }).to_string().as_bytes().to_vec(),
-      )
-      .await;
+    db.post_meta(
+      id,
+      serde_json::json!({ "id0": &id }).to_string().as_bytes().to_vec(),
+    )
+    .await
+    .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -151,7 +163,15 @@ async fn test_post() {
     assert_eq!(resp.status(), 202);
     assert_eq!(resp.body().len(), 0);
 
-    let id_db = db.write().await.get_meta(id).await;
+    let resp = warp::test::request()
+      .method("POST")
+      .json(&serde_json::json!({ "schema": { "type": 4 } }))
+      .path(&format!("/api/v0/{id}/meta"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 422);
+
+    let id_db = db.get_meta(id).await;
 
     assert_eq!(
       id_db,
@@ -1,47 +1,150 @@
-use crate::api::helpers::with_db;
+use crate::api::helpers::{
+  content_type, to_binary_array_body, to_csv, to_csv_body, to_json_array_body,
+  to_length_prefixed, to_length_prefixed_body, to_ndjson, to_ndjson_body,
+  to_page, to_value, with_db, with_snapshots, Cursor, Format, PageQuery,
+  ReadQuery,
+};
+use crate::api::snapshots::Snapshots;
 use rapiddb::traits::IAsyncDatabase;
 
 use warp::{Filter, Rejection, Reply};
 
 /// GET /api/v0/:String/:usize/:usize
 pub fn get(
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized + 'static>,
+  snapshots: Snapshots,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
   warp::path!("api" / "v0" / String / usize / usize)
     .and(warp::get())
+    .and(warp::query::<ReadQuery>())
+    .and(warp::query::<PageQuery>())
+    .and(warp::header::optional::<String>("accept"))
     .and(with_db(db))
+    .and(with_snapshots(snapshots))
     .and_then(_get)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn _get(
   id: String,
   start: usize,
   end: usize,
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  query: ReadQuery,
+  page: PageQuery,
+  accept: Option<String>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized + 'static>,
+  snapshots: Snapshots,
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
-  let data = db.write().await.get_range(&id, start, end).await;
+  let Ok(snapshot) = snapshots.resolve(query.as_of) else {
+    return Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::NOT_FOUND)
+        .body(Default::default()),
+    );
+  };
+  let len = match &snapshot {
+    Some(x) => x.len(&id),
+    None => db.len(&id).await,
+  };
+  if start > end || start >= len {
+    return Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::NOT_FOUND)
+        .body(Default::default()),
+    );
+  }
+
+  let format = Format::negotiate(&accept);
+  let stored = content_type(&db.get_meta(&id).await);
+  let base64 = query.base64(&stored);
 
-  if !data.is_empty() {
-    let mut result: String = Default::default();
-    result += "[";
-    for item in data {
-      result += &format!("{},", std::str::from_utf8(&item).unwrap_or_default());
+  if !page.is_paged() {
+    let records = db.clone().stream_range(&id, start, end.min(len - 1));
+    let body = match format {
+      Format::OctetStream => to_length_prefixed_body(records),
+      Format::NdJson => to_ndjson_body(records, base64),
+      Format::Csv => to_csv_body(records, start, base64),
+      Format::Json => to_json_array_body(records, base64),
+      Format::Cbor | Format::MsgPack => {
+        to_binary_array_body(records, format, stored.clone())
+      }
+    };
+
+    let mut result =
+      warp::hyper::Response::builder().status(warp::http::StatusCode::OK);
+    if format != Format::Json {
+      result =
+        result.header(warp::http::header::CONTENT_TYPE, format.content_type());
+    }
+
+    return Ok(result.body(body));
+  }
+
+  let cursor = match page.cursor.as_deref().map(Cursor::decode) {
+    Some(Some(x)) if x.next >= start && x.end == end => x,
+    Some(_) => {
+      return Ok(
+        warp::hyper::Response::builder()
+          .status(warp::http::StatusCode::BAD_REQUEST)
+          .body(
+            serde_json::json!({ "errors": ["invalid cursor"] })
+              .to_string()
+              .into(),
+          ),
+      );
     }
-    result.pop();
-    result += "]";
+    None => Cursor { next: start, end },
+  };
+  let (start, mut end, mut next_cursor) = cursor.page(page.page_size());
+  if end >= len {
+    end = len - 1;
+    next_cursor = None;
+  }
 
+  let data = if start <= end {
+    db.get_range(&id, start, end).await
+  } else {
+    Default::default()
+  };
+  if data.is_empty() {
     return Ok(
       warp::hyper::Response::builder()
-        .status(warp::http::StatusCode::OK)
-        .body(result),
+        .status(warp::http::StatusCode::NOT_FOUND)
+        .body(Default::default()),
     );
   }
 
-  Ok(
-    warp::hyper::Response::builder()
-      .status(warp::http::StatusCode::NOT_FOUND)
-      .body(Default::default()),
-  )
+  let body = match format {
+    Format::OctetStream => to_length_prefixed(&data),
+    Format::NdJson => to_ndjson(&data, base64),
+    Format::Csv => {
+      let rows = data
+        .iter()
+        .enumerate()
+        .map(|(i, x)| (vec![(start + i).to_string()], x.as_slice(), base64));
+      to_csv(&["index"], rows)
+    }
+    Format::Cbor | Format::MsgPack => format.encode(&serde_json::json!({
+      "records": data.iter().map(|x| to_value(x, &stored)).collect::<Vec<_>>(),
+      "next_cursor": next_cursor.map(|x| x.encode()),
+    })),
+    Format::Json => {
+      return Ok(
+        warp::hyper::Response::builder()
+          .status(warp::http::StatusCode::OK)
+          .body(to_page(&data, next_cursor, base64).into()),
+      );
+    }
+  };
+
+  let mut result = warp::hyper::Response::builder()
+    .status(warp::http::StatusCode::OK)
+    .header(warp::http::header::CONTENT_TYPE, format.content_type());
+  if let Some(x) = next_cursor {
+    result = result.header("x-next-cursor", x.encode());
+  }
+
+  Ok(result.body(body.into()))
 }
 
 #[tokio::test]
@@ -62,10 +165,9 @@ async fn test_get() {
       .await;
     assert_eq!(resp.status(), 404);
 
-    db.write()
+    db.post(id, serde_json::json!({ "id": &id }).to_string().as_bytes())
       .await
-      .post(id, serde_json::json!({ "id": &id }).to_string().as_bytes())
-      .await;
+      .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -108,10 +210,9 @@ async fn test_get() {
     assert_eq!(resp.status(), 404);
 
     for _ in 0..n - 1 {
-      db.write()
+      db.post(id, serde_json::json!({ "id0": &id }).to_string().as_bytes())
         .await
-        .post(id, serde_json::json!({ "id0": &id }).to_string().as_bytes())
-        .await;
+        .unwrap();
     }
 
     let resp = warp::test::request()
@@ -137,3 +238,377 @@ async fn test_get() {
     assert_eq!(resp.status(), 404);
   }
 }
+
+#[tokio::test]
+async fn test_get_non_json() {
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensor_range/test_get_non_json",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+
+    db.post(id, serde_json::json!({ "id": &id }).to_string().as_bytes())
+      .await
+      .unwrap();
+    db.post(id, b"value").await.unwrap();
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/0/1"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!([{ "id": &id }, "value"])
+    );
+  }
+}
+
+#[tokio::test]
+async fn test_get_binary() {
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensor_range/test_get_binary",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+    let value = [0xff, 0x00, 0x01];
+
+    db.post_meta(
+      id,
+      serde_json::json!({ "content_type": "application/octet-stream" })
+        .to_string()
+        .as_bytes()
+        .to_vec(),
+    )
+    .await
+    .unwrap();
+
+    let resp = warp::test::request()
+      .method("POST")
+      .body(value)
+      .path(&format!("/api/v0/{id}"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 202);
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/0/0"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!(["/wAB"])
+    );
+
+    let resp = warp::test::request()
+      .method("GET")
+      .header("accept", "application/octet-stream")
+      .path(&format!("/api/v0/{id}/0/0"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.body().to_vec(), vec![0, 0, 0, 3, 0xff, 0x00, 0x01]);
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path("/api/v0/sensors/latest/1?encoding=base64")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!({ id: ["/wAB"] })
+    );
+  }
+}
+
+#[tokio::test]
+async fn test_get_paged() {
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensor_range/test_get_paged",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+
+    for i in 0..5 {
+      db.post(id, serde_json::json!({ "n": i }).to_string().as_bytes())
+        .await
+        .unwrap();
+    }
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/0/9?page_size=2"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    let page =
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap();
+    assert_eq!(page["records"], serde_json::json!([{ "n": 0 }, { "n": 1 }]));
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!(
+        "/api/v0/{id}/0/9?page_size=2&cursor={}",
+        page["next_cursor"].as_str().unwrap()
+      ))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    let page =
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap();
+    assert_eq!(page["records"], serde_json::json!([{ "n": 2 }, { "n": 3 }]));
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!(
+        "/api/v0/{id}/0/9?cursor={}",
+        page["next_cursor"].as_str().unwrap()
+      ))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!({ "records": [{ "n": 4 }], "next_cursor": null })
+    );
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/0/9?cursor=invalid"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 400);
+  }
+}
+
+#[tokio::test]
+async fn test_get_streamed() {
+  use crate::api::helpers::MAX_PAGE_SIZE;
+
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensor_range/test_get_streamed",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+    let n = 1500;
+
+    let values = (0..n)
+      .map(|i| serde_json::json!({ "n": i }).to_string().into_bytes())
+      .collect::<Vec<_>>();
+    db.post_batch(id, &values.iter().map(|x| &x[..]).collect::<Vec<_>>())
+      .await
+      .unwrap();
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/1/{}", n * 2))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    let records =
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap();
+    assert_eq!(records.as_array().unwrap().len(), n - 1);
+    assert_eq!(records[0], serde_json::json!({ "n": 1 }));
+    assert_eq!(records[n - 2], serde_json::json!({ "n": n - 1 }));
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/1/{}?page_size={n}", n * 2))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    let page =
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap();
+    assert_eq!(page["records"].as_array().unwrap().len(), MAX_PAGE_SIZE);
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!(
+        "/api/v0/{id}/1/{}?cursor={}",
+        n * 2,
+        page["next_cursor"].as_str().unwrap()
+      ))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    let page =
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap();
+    assert_eq!(
+      page["records"].as_array().unwrap().len(),
+      n - 1 - MAX_PAGE_SIZE
+    );
+    assert_eq!(page["next_cursor"], serde_json::Value::Null);
+  }
+}
+
+#[tokio::test]
+async fn test_get_formats() {
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensor_range/test_get_formats",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+
+    db.post(id, br#"{"n":0,"pos":{"x":1,"y":2}}"#).await.unwrap();
+    db.post(id, br#"{"n":1,"name":"a, \"b\""}"#).await.unwrap();
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/0/9"))
+      .header("accept", "application/x-ndjson")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "application/x-ndjson");
+    assert_eq!(
+      resp.body(),
+      "{\"n\":0,\"pos\":{\"x\":1,\"y\":2}}\n{\"n\":1,\"name\":\"a, \\\"b\\\"\"}\n"
+    );
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/0/9"))
+      .header("accept", "text/csv")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "text/csv");
+    assert_eq!(
+      resp.body(),
+      "index,n,pos.x,pos.y,name\r\n0,0,1,2,\r\n1,1,,,\"a, \"\"b\"\"\"\r\n"
+    );
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/0/9?page_size=1&cursor="))
+      .header("accept", "text/csv")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 400);
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/0/9?page_size=1"))
+      .header("accept", "text/csv")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert!(resp.headers().contains_key("x-next-cursor"));
+    assert_eq!(resp.body(), "index,n,pos.x,pos.y\r\n0,0,1,2\r\n");
+
+    let id = "test-1";
+    for n in 0..600 {
+      db.post(id, format!("{{\"n\":{n}}}").as_bytes()).await.unwrap();
+    }
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/0/599"))
+      .header("accept", "text/csv")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    let body = std::str::from_utf8(resp.body()).unwrap();
+    assert_eq!(body.lines().count(), 601);
+    assert!(body.starts_with("index,n\r\n0,0\r\n"));
+    assert!(body.ends_with("\r\n599,599\r\n"));
+  }
+}
+
+#[tokio::test]
+async fn test_get_transcoded() {
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensor_range/test_get_transcoded",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+
+    for i in 0..3 {
+      db.post(id, serde_json::json!({ "n": i }).to_string().as_bytes())
+        .await
+        .unwrap();
+    }
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/0/9"))
+      .header("accept", "application/cbor")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "application/cbor");
+    assert_eq!(
+      ciborium::from_reader::<serde_json::Value, _>(resp.body().as_ref())
+        .unwrap(),
+      serde_json::json!([{ "n": 0 }, { "n": 1 }, { "n": 2 }])
+    );
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/0/9?page_size=2"))
+      .header("accept", "application/msgpack")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "application/msgpack");
+    let page = rmp_serde::from_slice::<serde_json::Value>(resp.body()).unwrap();
+    assert_eq!(page["records"], serde_json::json!([{ "n": 0 }, { "n": 1 }]));
+    assert_eq!(
+      page["next_cursor"].as_str(),
+      resp.headers()["x-next-cursor"].to_str().ok()
+    );
+
+    let id = "test-1";
+    for n in 0..600 {
+      db.post(id, format!("{{\"n\":{n}}}").as_bytes()).await.unwrap();
+    }
+    let records = (0..600).map(|n| serde_json::json!({ "n": n }));
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/0/599"))
+      .header("accept", "application/cbor")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      ciborium::from_reader::<serde_json::Value, _>(resp.body().as_ref())
+        .unwrap(),
+      serde_json::Value::Array(records.clone().collect())
+    );
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/0/599"))
+      .header("accept", "application/msgpack")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      rmp_serde::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::Value::Array(records.collect())
+    );
+  }
+}
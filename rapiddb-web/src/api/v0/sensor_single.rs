@@ -1,14 +1,15 @@
-use crate::api::helpers::with_db;
+use crate::api::helpers::{content_type, transcode, with_db, Format};
 use rapiddb::traits::IAsyncDatabase;
 
 use warp::{Filter, Rejection, Reply};
 
 /// GET /api/v0/:String/:usize
 pub fn get(
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
   warp::path!("api" / "v0" / String / usize)
     .and(warp::get())
+    .and(warp::header::optional::<String>("accept"))
     .and(with_db(db))
     .and_then(_get)
 }
@@ -16,16 +17,32 @@ pub fn get(
 pub async fn _get(
   id: String,
   rec_id: usize,
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  accept: Option<String>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
-  let result = db.write().await.get(&id, rec_id).await;
+  let result = db.get(&id, rec_id).await;
 
   if !result.is_empty() {
-    return Ok(
-      warp::hyper::Response::builder()
+    let stored = content_type(&db.get_meta(&id).await);
+    let format = Format::negotiate(&accept);
+    if !format.is_binary() {
+      return Ok(
+        warp::hyper::Response::builder()
+          .status(warp::http::StatusCode::OK)
+          .header(warp::http::header::CONTENT_TYPE, stored)
+          .body(result),
+      );
+    }
+
+    return Ok(match transcode(&result, &stored, format.content_type()) {
+      Ok(x) => warp::hyper::Response::builder()
         .status(warp::http::StatusCode::OK)
-        .body(result),
-    );
+        .header(warp::http::header::CONTENT_TYPE, format.content_type())
+        .body(x),
+      Err(error) => warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::NOT_ACCEPTABLE)
+        .body(serde_json::json!({ "errors": [error] }).to_string().into()),
+    });
   }
 
   Ok(
@@ -60,10 +77,9 @@ async fn test_get() {
       .await;
     assert_eq!(resp.status(), 404);
 
-    db.write()
+    db.post(id, serde_json::json!({ "id": &id }).to_string().as_bytes())
       .await
-      .post(id, serde_json::json!({ "id": &id }).to_string().as_bytes())
-      .await;
+      .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -83,10 +99,9 @@ async fn test_get() {
       .await;
     assert_eq!(resp.status(), 404);
 
-    db.write()
+    db.post(id, serde_json::json!({ "id0": &id }).to_string().as_bytes())
       .await
-      .post(id, serde_json::json!({ "id0": &id }).to_string().as_bytes())
-      .await;
+      .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -110,13 +125,12 @@ async fn test_get() {
       serde_json::json!({ "id0": &id })
     );
 
-    db.write()
-      .await
-      .post_meta(
-        id1,
-        serde_json::json!({ "id1": &id1 }).to_string().as_bytes().to_vec(),
-      )
-      .await;
+    db.post_meta(
+      id1,
+      serde_json::json!({ "id1": &id1 }).to_string().as_bytes().to_vec(),
+    )
+    .await
+    .unwrap();
     let resp = warp::test::request()
       .method("GET")
       .path(&format!("/api/v0/{id1}/0"))
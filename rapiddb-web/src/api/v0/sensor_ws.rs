@@ -0,0 +1,142 @@
+use crate::api::follower::{Followed, Follower};
+use crate::api::helpers::{content_type, encode_record, with_db, ReadQuery};
+use futures_util::{SinkExt, StreamExt};
+use rapiddb::db::SubscriptionFilter;
+use rapiddb::traits::IAsyncDatabase;
+
+use warp::{Filter, Rejection, Reply};
+
+/// Query options for live streams
+///
+/// `start` replays the records from that index before switching to
+/// live records, by default only live records are sent.
+/// `encoding=base64` encodes every record as a base64 JSON string.
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+pub struct StreamQuery {
+  pub start: Option<usize>,
+  pub encoding: Option<String>,
+}
+
+/// GET /api/v0/:String/ws
+pub fn get(
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized + 'static>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  warp::path!("api" / "v0" / String / "ws")
+    .and(warp::ws())
+    .and(warp::query::<StreamQuery>())
+    .and(with_db(db))
+    .map(|id: String, ws: warp::ws::Ws, query: StreamQuery, db| {
+      ws.on_upgrade(move |socket| {
+        stream(socket, db, SubscriptionFilter::Id(id), query)
+      })
+    })
+}
+
+/// Send the records of the sensors selected by `filter` over
+/// `socket`, one JSON text message per record, until either side
+/// closes
+pub async fn stream(
+  socket: warp::ws::WebSocket,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+  filter: SubscriptionFilter,
+  query: StreamQuery,
+) {
+  let (mut sender, mut receiver) = socket.split();
+  let mut follower = Follower::new(db.clone(), filter, query.start).await;
+  let read_query =
+    ReadQuery { encoding: query.encoding.clone(), ..Default::default() };
+
+  loop {
+    tokio::select! {
+      record = follower.next() => {
+        let (id, rec_id, value) = match record {
+          Some(Followed::Record { id, rec_id, value }) => (id, rec_id, value),
+          Some(Followed::Meta { .. }) => continue,
+          None => break,
+        };
+
+        let base64 = read_query.base64(&content_type(&db.get_meta(&id).await));
+        let message = serde_json::json!({
+          "id": id,
+          "rec_id": rec_id,
+          "value": encode_record(&value, base64),
+        });
+
+        if sender.send(warp::ws::Message::text(message.to_string())).await.is_err() {
+          break;
+        }
+      }
+      message = receiver.next() => {
+        match message {
+          Some(Ok(x)) if !x.is_close() => {}
+          _ => break,
+        }
+      }
+    }
+  }
+
+  sender.close().await.unwrap_or_default();
+}
+
+#[tokio::test]
+async fn test_get() {
+  let database_test_factory =
+    rapiddb::db::DatabaseTestFactory::new(".temp/test/sensor_ws/test_get");
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+    let id1 = "test-1";
+
+    for i in 0..2 {
+      db.post(id, serde_json::json!({ "n": i }).to_string().as_bytes())
+        .await
+        .unwrap();
+    }
+
+    let mut client = warp::test::ws()
+      .path(&format!("/api/v0/{id}/ws?start=1"))
+      .handshake(api.clone())
+      .await
+      .unwrap();
+
+    let message = client.recv().await.unwrap();
+    assert_eq!(
+      serde_json::from_str::<serde_json::Value>(message.to_str().unwrap())
+        .unwrap(),
+      serde_json::json!({ "id": &id, "rec_id": 1, "value": { "n": 1 } })
+    );
+
+    db.post(id1, serde_json::json!({ "n": 0 }).to_string().as_bytes())
+      .await
+      .unwrap();
+    db.post(id, serde_json::json!({ "n": 2 }).to_string().as_bytes())
+      .await
+      .unwrap();
+
+    let message = client.recv().await.unwrap();
+    assert_eq!(
+      serde_json::from_str::<serde_json::Value>(message.to_str().unwrap())
+        .unwrap(),
+      serde_json::json!({ "id": &id, "rec_id": 2, "value": { "n": 2 } })
+    );
+
+    let mut client = warp::test::ws()
+      .path(&format!("/api/v0/{id}/ws?start=3"))
+      .handshake(api)
+      .await
+      .unwrap();
+
+    db.post(id, serde_json::json!({ "n": 3 }).to_string().as_bytes())
+      .await
+      .unwrap();
+
+    let message = client.recv().await.unwrap();
+    assert_eq!(
+      serde_json::from_str::<serde_json::Value>(message.to_str().unwrap())
+        .unwrap(),
+      serde_json::json!({ "id": &id, "rec_id": 3, "value": { "n": 3 } })
+    );
+  }
+}
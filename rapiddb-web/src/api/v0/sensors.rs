@@ -9,10 +9,13 @@ pub fn get() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     .map(|| {
       warp::reply::json(&serde_json::json!({
         "resources": [
-          {"endpoint": "/api/v0/sensors/latest", "description": "GET latest measurment from every sensor"},
-          {"endpoint": "/api/v0/sensors/latest/:count", "description": "GET latest :count measurments from every sensor"},
+          {"endpoint": "/api/v0/sensors/latest", "description": "GET latest measurment from every sensor, as NDJSON, CSV, CBOR or MessagePack by Accept"},
+          {"endpoint": "/api/v0/sensors/latest/:count", "description": "GET latest :count measurments from every sensor, as NDJSON, CSV, CBOR or MessagePack by Accept"},
           {"endpoint": "/api/v0/sensors/meta", "description": "GET metadata from every sensor"},
           {"endpoint": "/api/v0/sensors/aggregates", "description": "GET aggregates from every sensor"},
+          {"endpoint": "/api/v0/sensors/batch", "description": "POST newline-delimited JSON records to multiple sensors"},
+          {"endpoint": "/api/v0/sensors/ws?prefix=:prefix", "description": "WebSocket of new records from every sensor with an id starting with :prefix"},
+          {"endpoint": "/api/v0/sensors/snapshot", "description": "POST to take a snapshot for consistent reads with ?as_of="},
         ],
         "description": "Discover resources available for all sensors",
       }))
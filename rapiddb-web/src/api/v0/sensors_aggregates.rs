@@ -1,47 +1,41 @@
-use crate::api::helpers::with_db;
+use crate::api::helpers::{reply_document, to_raw_json, with_db};
 use rapiddb::traits::IAsyncDatabase;
 
 use warp::{Filter, Rejection, Reply};
 
 /// GET /api/v0/sensors/aggregates
 pub fn get(
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
   warp::path!("api" / "v0" / "sensors" / "aggregates")
     .and(warp::get())
+    .and(warp::header::optional::<String>("accept"))
     .and(with_db(db))
     .and_then(_get)
 }
 
 pub async fn _get(
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  accept: Option<String>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
-  let lock = db.read().await;
-  let data = lock.get_all_aggregates().await;
+  let data = db.get_all_aggregates().await;
 
   if !data.is_empty() {
-    let mut result: String = Default::default();
-    result += "{";
-    for (key, value) in data {
-      result += &format!(
-        "\"{key}\":{},",
-        std::str::from_utf8(&value).unwrap_or_default()
-      );
-    }
-    result.pop();
-    result += "}";
+    let result = serde_json::to_string(
+      &data
+        .iter()
+        .map(|(key, value)| (key, to_raw_json(value)))
+        .collect::<std::collections::HashMap<_, _>>(),
+    )
+    .unwrap_or_default();
 
-    return Ok(
-      warp::hyper::Response::builder()
-        .status(warp::http::StatusCode::OK)
-        .body(result),
-    );
+    return Ok(reply_document(&accept, result.into()));
   }
 
   Ok(
     warp::hyper::Response::builder()
       .status(warp::http::StatusCode::NOT_FOUND)
-      .body(String::new()),
+      .body(Default::default()),
   )
 }
 
@@ -64,10 +58,9 @@ async fn test_get() {
       .await;
     assert_eq!(resp.status(), 404);
 
-    db.write()
+    db.post(id, serde_json::json!({"temp": 8.00}).to_string().as_bytes())
       .await
-      .post(id, serde_json::json!({"temp": 8.00}).to_string().as_bytes())
-      .await;
+      .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -81,10 +74,9 @@ async fn test_get() {
       serde_json::json!({id: {"temp_avg": 8.0, "temp_sum": 8.0, "temp_sum_count": 1.0}})
     );
 
-    db.write()
+    db.post(id0, serde_json::json!({"temp": 4.00}).to_string().as_bytes())
       .await
-      .post(id0, serde_json::json!({"temp": 4.00}).to_string().as_bytes())
-      .await;
+      .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -0,0 +1,793 @@
+use crate::api::helpers::{
+  content_type, is_cbor, is_json, is_msgpack, transcode, with_db,
+};
+use rapiddb::errors::Error;
+use rapiddb::traits::IAsyncDatabase;
+
+use futures_util::StreamExt;
+use warp::{Buf, Filter, Rejection, Reply};
+
+/// Number of records posted to the database at once
+const BATCH_SIZE: usize = 1024;
+
+/// Maximum length of a line of NDJSON, the same as the body limit of
+/// POST /api/v0/:String
+const LINE_LENGTH_LIMIT: usize = 1024 * 16;
+
+/// Maximum length of a body which is buffered as a whole, a JSON object
+/// body or the records of an atomic batch
+const BUFFER_LENGTH_LIMIT: usize = 1024 * 1024 * 16;
+
+/// Maximum number of results of a batch, the rest of the body is not
+/// posted once it is reached
+const RESULTS_LIMIT: usize = 1024 * 64;
+
+/// Query options for batches
+///
+/// `atomic=true` commits all records of the batch atomically, either
+/// all of them are written or none of them are.
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+pub struct BatchQuery {
+  #[serde(default)]
+  pub atomic: bool,
+}
+
+/// A line of NDJSON
+#[derive(serde::Deserialize)]
+struct BatchLine {
+  id: String,
+  value: Box<serde_json::value::RawValue>,
+}
+
+/// Records parsed from the body, waiting to be posted to the database
+struct Pending {
+  /// Position of the record in the results, line, sensor id and value
+  records: Vec<(usize, Option<usize>, String, Vec<u8>)>,
+  /// Content type of the sensors of the records, by sensor id
+  stored: std::collections::HashMap<String, String>,
+  atomic: bool,
+  size: usize,
+}
+impl Pending {
+  fn new(atomic: bool) -> Self {
+    Self {
+      records: Default::default(),
+      stored: Default::default(),
+      atomic,
+      size: Default::default(),
+    }
+  }
+
+  /// Add a record with `id` and `value`, as JSON text, and reserve its
+  /// result
+  ///
+  /// Like POST /api/v0/:String, the value is transcoded to the content
+  /// type of the sensor, if it stores CBOR or MessagePack.
+  async fn push(
+    &mut self,
+    db: &(impl IAsyncDatabase + ?Sized),
+    results: &mut Vec<serde_json::Value>,
+    line: Option<usize>,
+    id: String,
+    value: &[u8],
+  ) {
+    if !self.stored.contains_key(&id) {
+      self.stored.insert(id.clone(), content_type(&db.get_meta(&id).await));
+    }
+
+    let stored = &self.stored[&id];
+    let value = match is_cbor(stored) || is_msgpack(stored) {
+      true => match transcode(value, "application/json", stored) {
+        Ok(x) => x,
+        Err(error) => return results.push(to_errors(line, &id, vec![error])),
+      },
+      false => value.to_vec(),
+    };
+
+    self.size += value.len();
+    self.records.push((results.len(), line, id, value));
+    results.push(serde_json::Value::Null);
+  }
+
+  /// Checks if the pending records should be flushed before parsing
+  /// more of the body
+  fn full(&self) -> Result<bool, (warp::http::StatusCode, Vec<String>)> {
+    if !self.atomic {
+      return Ok(self.records.len() >= BATCH_SIZE);
+    }
+
+    if self.size > BUFFER_LENGTH_LIMIT {
+      return Err((
+        warp::http::StatusCode::PAYLOAD_TOO_LARGE,
+        vec!["body too large".to_owned()],
+      ));
+    }
+
+    Ok(false)
+  }
+
+  /// Post the remaining records to the database, atomic batches are
+  /// committed as a whole, unless a line of the body was rejected
+  async fn finish(
+    &mut self,
+    db: &(impl IAsyncDatabase + ?Sized),
+    results: &mut [serde_json::Value],
+  ) -> Result<(), (warp::http::StatusCode, Vec<String>)> {
+    if !self.atomic {
+      self.flush(db, results).await;
+      return Ok(());
+    }
+
+    let errors = results
+      .iter()
+      .filter(|x| x["errors"].is_array())
+      .map(|x| {
+        format!(
+          "line {}: {}",
+          x["line"],
+          x["errors"][0].as_str().unwrap_or_default()
+        )
+      })
+      .collect::<Vec<_>>();
+    if !errors.is_empty() {
+      return Err((warp::http::StatusCode::BAD_REQUEST, errors));
+    }
+
+    let mut batch = rapiddb::db::WriteBatch::new();
+    for (_, _, id, value) in &self.records {
+      batch.post(id, value);
+    }
+
+    match db.commit(&batch).await {
+      Ok(rec_ids) => {
+        for ((position, line, id, _), rec_id) in
+          self.records.iter().zip(rec_ids)
+        {
+          results[*position] = to_result(*line, id, Ok(rec_id));
+        }
+
+        Ok(())
+      }
+      Err(Error::SchemaViolation(errors)) => {
+        Err((warp::http::StatusCode::UNPROCESSABLE_ENTITY, errors))
+      }
+      Err(error @ Error::InvalidId(_)) => {
+        Err((warp::http::StatusCode::BAD_REQUEST, vec![error.to_string()]))
+      }
+      Err(Error::NotSupported) => Err((
+        warp::http::StatusCode::NOT_IMPLEMENTED,
+        vec![Error::NotSupported.to_string()],
+      )),
+      Err(error) => Err((
+        warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        vec![error.to_string()],
+      )),
+    }
+  }
+
+  /// Post the remaining records once the batch has `RESULTS_LIMIT`
+  /// results, and report the rest of the body, from `line`, as too many
+  /// records, atomic batches are rejected as a whole instead
+  async fn finish_limited(
+    &mut self,
+    db: &(impl IAsyncDatabase + ?Sized),
+    mut results: Vec<serde_json::Value>,
+    line: Option<usize>,
+  ) -> Result<Vec<serde_json::Value>, (warp::http::StatusCode, Vec<String>)> {
+    let error = "too many records".to_owned();
+    if self.atomic {
+      return Err((warp::http::StatusCode::PAYLOAD_TOO_LARGE, vec![error]));
+    }
+
+    self.finish(db, &mut results).await?;
+
+    let mut value = serde_json::json!({ "errors": [error] });
+    if let Some(line) = line {
+      value["line"] = serde_json::json!(line);
+    }
+    results.push(value);
+
+    Ok(results)
+  }
+
+  /// Post all pending records to the database and fill in their results
+  ///
+  /// If the database rejects the batch before writing it, because an id
+  /// or a value is invalid, the records are posted one by one, so every
+  /// record gets its own result. Any other error may leave the batch
+  /// partially written, so it is reported for every record instead of
+  /// posting the records again.
+  async fn flush(
+    &mut self,
+    db: &(impl IAsyncDatabase + ?Sized),
+    results: &mut [serde_json::Value],
+  ) {
+    let records = self
+      .records
+      .iter()
+      .map(|(_, _, id, value)| (id.as_str(), value.as_slice()))
+      .collect::<Vec<_>>();
+
+    match db.post_many(&records).await {
+      Ok(rec_ids) => {
+        for ((position, line, id, _), rec_id) in
+          self.records.iter().zip(rec_ids)
+        {
+          results[*position] = to_result(*line, id, Ok(rec_id));
+        }
+      }
+      Err(Error::SchemaViolation(_) | Error::InvalidId(_)) => {
+        for (position, line, id, value) in &self.records {
+          results[*position] = to_result(*line, id, db.post(id, value).await);
+        }
+      }
+      Err(error) => {
+        let errors = vec![error.to_string()];
+        for (position, line, id, _) in &self.records {
+          results[*position] = to_errors(*line, id, errors.clone());
+        }
+      }
+    }
+
+    self.records.clear();
+  }
+}
+
+/// Result of posting a record with `id`, from `line` of the body
+fn to_result(
+  line: Option<usize>,
+  id: &str,
+  result: Result<usize, Error>,
+) -> serde_json::Value {
+  let mut value = match result {
+    Ok(rec_id) => serde_json::json!({ "id": id, "rec_id": rec_id }),
+    Err(Error::SchemaViolation(errors)) => return to_errors(line, id, errors),
+    Err(error) => return to_errors(line, id, vec![error.to_string()]),
+  };
+
+  if let Some(line) = line {
+    value["line"] = serde_json::json!(line);
+  }
+
+  value
+}
+
+/// Result of a record with `id`, from `line` of the body, which was
+/// rejected with `errors`
+fn to_errors(
+  line: Option<usize>,
+  id: &str,
+  errors: Vec<String>,
+) -> serde_json::Value {
+  let mut value = serde_json::json!({ "id": id, "errors": errors });
+
+  if let Some(line) = line {
+    value["line"] = serde_json::json!(line);
+  }
+
+  value
+}
+
+/// Result of a `line` of the body which could not be parsed
+fn to_line_error(line: usize, error: impl ToString) -> serde_json::Value {
+  serde_json::json!({ "line": line, "errors": [error.to_string()] })
+}
+
+/// POST /api/v0/sensors/batch
+///
+/// Accepts newline-delimited JSON, with a `{"id": ..., "value": ...}`
+/// object per line, which is streamed into the database. With a
+/// `Content-Type` of `application/json` it accepts a JSON object of
+/// arrays of values, keyed by sensor id, instead.
+///
+/// Responds with a result per record, in order, with the assigned
+/// `rec_id` or the `errors` of the record. At most `RESULTS_LIMIT`
+/// records are posted, the last result reports the rest of the body as
+/// too many records.
+///
+/// With `atomic=true` the records are committed atomically, and the
+/// batch is rejected as a whole if a line or a record is invalid.
+pub fn post(
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  warp::path!("api" / "v0" / "sensors" / "batch")
+    .and(warp::post())
+    .and(warp::query::<BatchQuery>())
+    .and(warp::header::optional::<String>("content-type"))
+    .and(warp::body::stream())
+    .and(with_db(db))
+    .and_then(_post)
+}
+
+pub async fn _post(
+  query: BatchQuery,
+  content_type: Option<String>,
+  data: impl futures_util::Stream<Item = Result<impl Buf, warp::Error>>
+    + Send
+    + 'static,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+) -> Result<impl warp::Reply, std::convert::Infallible> {
+  let result = match content_type {
+    Some(x) if is_json(&x) => post_object(data, &*db, query.atomic).await,
+    _ => post_ndjson(data, &*db, query.atomic).await,
+  };
+
+  match result {
+    Ok(results) => Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::ACCEPTED)
+        .body(serde_json::json!({ "results": results }).to_string()),
+    ),
+    Err((status, errors)) => Ok(
+      warp::hyper::Response::builder()
+        .status(status)
+        .body(serde_json::json!({ "errors": errors }).to_string()),
+    ),
+  }
+}
+
+/// Post the records of a body of newline-delimited JSON, one chunk of
+/// the body at a time
+async fn post_ndjson(
+  data: impl futures_util::Stream<Item = Result<impl Buf, warp::Error>>,
+  db: &(impl IAsyncDatabase + ?Sized),
+  atomic: bool,
+) -> Result<Vec<serde_json::Value>, (warp::http::StatusCode, Vec<String>)> {
+  let mut data = Box::pin(data);
+  let mut results = vec![];
+  let mut pending = Pending::new(atomic);
+  let mut buffer: Vec<u8> = vec![];
+  let mut line = 0;
+  let mut overflow = false;
+  // the complete lines are read from `start`, and removed from the
+  // buffer once per chunk
+  let mut start = 0;
+
+  loop {
+    let chunk = data.next().await;
+    let done = chunk.is_none();
+
+    if let Some(chunk) = chunk {
+      let mut chunk = chunk.map_err(|error| {
+        (warp::http::StatusCode::BAD_REQUEST, vec![error.to_string()])
+      })?;
+
+      while chunk.has_remaining() {
+        let len = chunk.chunk().len();
+        buffer.extend_from_slice(chunk.chunk());
+        chunk.advance(len);
+      }
+    }
+
+    while let Some(end) = buffer[start..].iter().position(|x| *x == b'\n') {
+      let data = &buffer[start..start + end];
+      start += end + 1;
+      line += 1;
+
+      if overflow {
+        overflow = false;
+        results.push(to_line_error(line, "line too long"));
+        continue;
+      }
+
+      if results.len() >= RESULTS_LIMIT && !is_blank(data) {
+        return pending.finish_limited(db, results, Some(line)).await;
+      }
+
+      post_line(&mut pending, db, &mut results, line, data).await;
+    }
+
+    buffer.drain(..start);
+    start = 0;
+
+    if buffer.len() > LINE_LENGTH_LIMIT {
+      buffer.clear();
+      overflow = true;
+    }
+
+    if done {
+      if overflow {
+        results.push(to_line_error(line + 1, "line too long"));
+      } else if results.len() >= RESULTS_LIMIT && !is_blank(&buffer) {
+        return pending.finish_limited(db, results, Some(line + 1)).await;
+      } else {
+        post_line(&mut pending, db, &mut results, line + 1, &buffer).await;
+      }
+
+      pending.finish(db, &mut results).await?;
+      break;
+    }
+
+    if pending.full()? {
+      pending.flush(db, &mut results).await;
+    }
+  }
+
+  Ok(results)
+}
+
+/// Parse a `line` of newline-delimited JSON into `pending`, blank lines
+/// are skipped
+async fn post_line(
+  pending: &mut Pending,
+  db: &(impl IAsyncDatabase + ?Sized),
+  results: &mut Vec<serde_json::Value>,
+  line: usize,
+  data: &[u8],
+) {
+  if is_blank(data) {
+    return;
+  }
+
+  match serde_json::from_slice::<BatchLine>(data) {
+    Ok(x) => {
+      let value = x.value.get().as_bytes();
+      pending.push(db, results, Some(line), x.id, value).await
+    }
+    Err(error) => results.push(to_line_error(line, error)),
+  }
+}
+
+/// Checks if a line of newline-delimited JSON is blank
+fn is_blank(data: &[u8]) -> bool {
+  data.iter().all(|x| x.is_ascii_whitespace())
+}
+
+/// Post the records of a body with a JSON object of arrays of values,
+/// keyed by sensor id
+async fn post_object(
+  data: impl futures_util::Stream<Item = Result<impl Buf, warp::Error>>,
+  db: &(impl IAsyncDatabase + ?Sized),
+  atomic: bool,
+) -> Result<Vec<serde_json::Value>, (warp::http::StatusCode, Vec<String>)> {
+  let mut data = Box::pin(data);
+  let mut buffer: Vec<u8> = vec![];
+
+  while let Some(chunk) = data.next().await {
+    let mut chunk = chunk.map_err(|error| {
+      (warp::http::StatusCode::BAD_REQUEST, vec![error.to_string()])
+    })?;
+
+    while chunk.has_remaining() {
+      let len = chunk.chunk().len();
+      buffer.extend_from_slice(chunk.chunk());
+      chunk.advance(len);
+    }
+
+    if buffer.len() > BUFFER_LENGTH_LIMIT {
+      return Err((
+        warp::http::StatusCode::PAYLOAD_TOO_LARGE,
+        vec!["body too large".to_owned()],
+      ));
+    }
+  }
+
+  let object = serde_json::from_slice::<
+    std::collections::BTreeMap<String, Vec<Box<serde_json::value::RawValue>>>,
+  >(&buffer)
+  .map_err(|error| {
+    (warp::http::StatusCode::BAD_REQUEST, vec![error.to_string()])
+  })?;
+
+  let mut results = vec![];
+  let mut pending = Pending::new(atomic);
+
+  for (id, values) in object {
+    for value in values {
+      if results.len() >= RESULTS_LIMIT {
+        return pending.finish_limited(db, results, None).await;
+      }
+
+      pending
+        .push(db, &mut results, None, id.clone(), value.get().as_bytes())
+        .await;
+
+      if pending.full()? {
+        pending.flush(db, &mut results).await;
+      }
+    }
+  }
+
+  pending.finish(db, &mut results).await?;
+
+  Ok(results)
+}
+
+#[tokio::test]
+async fn test_post_ndjson() {
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensors_batch/test_post_ndjson",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+    let id0 = "test-1";
+
+    let resp = warp::test::request()
+      .method("POST")
+      .body(format!(
+        "{}\n{}\n\nnot json\n{}",
+        serde_json::json!({ "id": id, "value": { "temp": 1 } }),
+        serde_json::json!({ "id": id0, "value": { "temp": 2 } }),
+        serde_json::json!({ "id": id, "value": { "temp": 3 } }),
+      ))
+      .path("/api/v0/sensors/batch")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 202);
+
+    let results =
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap();
+    assert_eq!(results["results"][0]["rec_id"], 0);
+    assert_eq!(results["results"][0]["line"], 1);
+    assert_eq!(results["results"][1]["id"], id0);
+    assert_eq!(results["results"][2]["line"], 4);
+    assert!(results["results"][2]["errors"].is_array());
+    assert_eq!(results["results"][3]["rec_id"], 1);
+    assert_eq!(results["results"][3]["line"], 5);
+
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(&db.get(id, 1).await)
+        .unwrap(),
+      serde_json::json!({ "temp": 3 })
+    );
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(&db.get_aggregates(id).await)
+        .unwrap()["temp_sum"],
+      4.
+    );
+
+    db.post_meta(
+      id0,
+      serde_json::json!({ "schema": { "type": "object", "required": ["temp"] } })
+        .to_string()
+        .as_bytes()
+        .to_vec(),
+    )
+    .await
+    .unwrap();
+
+    let resp = warp::test::request()
+      .method("POST")
+      .body(format!(
+        "{}\n{}\n",
+        serde_json::json!({ "id": id0, "value": { "temp": 4 } }),
+        serde_json::json!({ "id": id0, "value": { "hum": 4 } }),
+      ))
+      .path("/api/v0/sensors/batch")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 202);
+
+    let results =
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap();
+    assert_eq!(results["results"][0]["rec_id"], 1);
+    assert!(results["results"][1]["errors"].is_array());
+    assert_eq!(db.get_latest_with_limit(id0, 10).await.len(), 2);
+
+    let resp = warp::test::request()
+      .method("POST")
+      .body(format!(
+        "{}\n{}\n",
+        serde_json::json!({ "id": id0, "value": { "hum": 5 } }),
+        serde_json::json!({ "id": id0, "value": { "temp": 5 } }),
+      ))
+      .path("/api/v0/sensors/batch")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 202);
+
+    let results =
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap();
+    assert!(results["results"][0]["errors"].is_array());
+    assert_eq!(results["results"][1]["rec_id"], 2);
+    assert_eq!(db.get_latest_with_limit(id0, 10).await.len(), 3);
+  }
+}
+
+#[tokio::test]
+async fn test_post_object() {
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensors_batch/test_post_object",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+    let id0 = "test-1";
+
+    let resp = warp::test::request()
+      .method("POST")
+      .header("content-type", "application/json")
+      .body("not json")
+      .path("/api/v0/sensors/batch")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 400);
+
+    let resp = warp::test::request()
+      .method("POST")
+      .json(&serde_json::json!({
+        id: [{ "temp": 1 }, { "temp": 2 }],
+        id0: [{ "temp": 3 }],
+      }))
+      .path("/api/v0/sensors/batch")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 202);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!({ "results": [
+        { "id": id, "rec_id": 0 },
+        { "id": id, "rec_id": 1 },
+        { "id": id0, "rec_id": 0 },
+      ] })
+    );
+
+    assert_eq!(db.get_latest_with_limit(id, 10).await.len(), 2);
+    assert_eq!(db.get_latest_with_limit(id0, 10).await.len(), 1);
+  }
+}
+
+#[tokio::test]
+async fn test_post_atomic() {
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensors_batch/test_post_atomic",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+    let id0 = "test-1";
+
+    let resp = warp::test::request()
+      .method("POST")
+      .body(format!(
+        "{}\n{}\n",
+        serde_json::json!({ "id": id, "value": { "temp": 1 } }),
+        serde_json::json!({ "id": id0, "value": { "temp": 2 } }),
+      ))
+      .path("/api/v0/sensors/batch?atomic=true")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 202);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!({ "results": [
+        { "id": id, "rec_id": 0, "line": 1 },
+        { "id": id0, "rec_id": 0, "line": 2 },
+      ] })
+    );
+
+    let resp = warp::test::request()
+      .method("POST")
+      .body(format!(
+        "{}\nnot json\n",
+        serde_json::json!({ "id": id, "value": { "temp": 3 } }),
+      ))
+      .path("/api/v0/sensors/batch?atomic=true")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 400);
+    assert_eq!(db.get_latest_with_limit(id, 10).await.len(), 1);
+
+    db.post_meta(
+      id0,
+      serde_json::json!({ "schema": { "type": "object", "required": ["temp"] } })
+        .to_string()
+        .as_bytes()
+        .to_vec(),
+    )
+    .await
+    .unwrap();
+
+    let resp = warp::test::request()
+      .method("POST")
+      .json(&serde_json::json!({
+        id: [{ "temp": 4 }],
+        id0: [{ "hum": 5 }],
+      }))
+      .path("/api/v0/sensors/batch?atomic=true")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 422);
+    assert_eq!(db.get_latest_with_limit(id, 10).await.len(), 1);
+    assert_eq!(db.get_latest_with_limit(id0, 10).await.len(), 1);
+  }
+}
+
+#[tokio::test]
+async fn test_post_transcoded() {
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensors_batch/test_post_transcoded",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+    let id0 = "test-1";
+    let value = serde_json::json!({ "n": 1 });
+
+    db.post_meta(id, br#"{"content_type":"application/cbor"}"#.to_vec())
+      .await
+      .unwrap();
+    db.post_meta(id0, br#"{"content_type":"application/msgpack"}"#.to_vec())
+      .await
+      .unwrap();
+
+    let resp = warp::test::request()
+      .method("POST")
+      .body(format!(
+        "{}\n{}\n",
+        serde_json::json!({ "id": id, "value": value }),
+        serde_json::json!({ "id": id0, "value": value }),
+      ))
+      .path("/api/v0/sensors/batch")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 202);
+
+    let resp = warp::test::request()
+      .method("POST")
+      .json(&serde_json::json!({ id: [value], id0: [value] }))
+      .path("/api/v0/sensors/batch?atomic=true")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 202);
+
+    let mut data = vec![];
+    ciborium::into_writer(&value, &mut data).unwrap();
+    assert_eq!(db.get_range(id, 0, 1).await, [data.clone(), data]);
+
+    let data = rmp_serde::to_vec(&value).unwrap();
+    assert_eq!(db.get_range(id0, 0, 1).await, [data.clone(), data]);
+  }
+}
+
+#[tokio::test]
+async fn test_post_limit() {
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensors_batch/test_post_limit",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+    let body = format!("{}\n", serde_json::json!({ "id": id, "value": 0 }))
+      .repeat(RESULTS_LIMIT + 2);
+
+    let resp = warp::test::request()
+      .method("POST")
+      .body(&body)
+      .path("/api/v0/sensors/batch?atomic=true")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 413);
+    assert_eq!(db.len(id).await, 0);
+
+    let resp = warp::test::request()
+      .method("POST")
+      .body(&body)
+      .path("/api/v0/sensors/batch")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 202);
+
+    let result =
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap();
+    let results = result["results"].as_array().unwrap();
+    assert_eq!(results.len(), RESULTS_LIMIT + 1);
+    assert_eq!(results[RESULTS_LIMIT - 1]["rec_id"], RESULTS_LIMIT - 1);
+    assert_eq!(
+      results[RESULTS_LIMIT],
+      serde_json::json!({ "line": RESULTS_LIMIT + 1, "errors": ["too many records"] })
+    );
+    assert_eq!(db.len(id).await, RESULTS_LIMIT);
+  }
+}
@@ -1,51 +1,126 @@
-use crate::api::helpers::with_db;
+use crate::api::helpers::{
+  content_type, encode_record, to_csv, to_sensors_ndjson, to_value, with_db,
+  with_snapshots, Format, ReadQuery,
+};
+use crate::api::snapshots::Snapshots;
 use rapiddb::traits::IAsyncDatabase;
 
 use warp::{Filter, Rejection, Reply};
 
 /// GET /api/v0/sensors/latest
 pub fn get(
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+  snapshots: Snapshots,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
   warp::path!("api" / "v0" / "sensors" / "latest")
     .and(warp::get())
+    .and(warp::query::<ReadQuery>())
+    .and(warp::header::optional::<String>("accept"))
     .and(with_db(db))
+    .and(with_snapshots(snapshots))
     .and_then(_get)
 }
 
 pub async fn _get(
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  query: ReadQuery,
+  accept: Option<String>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+  snapshots: Snapshots,
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
-  let mut lock = db.write().await;
-  let data = lock.get_all_latest().await;
-
-  if !data.is_empty() {
-    let mut result: String = Default::default();
-    result += "{";
-    for (key, value) in data {
-      if value.is_empty() {
-        continue;
-      }
-
-      result += &format!(
-        "\"{key}\":{},",
-        std::str::from_utf8(&value).unwrap_or_default()
-      );
+  let Ok(snapshot) = snapshots.resolve(query.as_of) else {
+    return Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::NOT_FOUND)
+        .body(Default::default()),
+    );
+  };
+  let format = Format::negotiate(&accept);
+  // Record indexes are read from a snapshot, so they match the records
+  let snapshot = match (snapshot, format) {
+    (None, Format::NdJson | Format::Csv) => {
+      Some(std::sync::Arc::new(db.snapshot().await))
     }
-    result.pop();
-    result += "}";
+    (x, _) => x,
+  };
+
+  let content_types = db
+    .get_all_meta()
+    .await
+    .into_iter()
+    .map(|(key, value)| (key, content_type(&value)))
+    .collect::<std::collections::HashMap<_, _>>();
+  let stored = |id: &str| {
+    content_types.get(id).map(|x| x.as_str()).unwrap_or("application/json")
+  };
+  let base64 = |id: &str| query.base64(stored(id));
+  let data = match &snapshot {
+    Some(snapshot) => db.get_all_latest_as_of(snapshot).await,
+    None => db.get_all_latest().await,
+  };
+
+  if data.is_empty() {
+    return Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::NOT_FOUND)
+        .body(Default::default()),
+    );
+  }
+
+  if let (Format::NdJson | Format::Csv, Some(snapshot)) = (format, snapshot) {
+    let mut data =
+      data.iter().filter(|(_, value)| !value.is_empty()).collect::<Vec<_>>();
+    data.sort_by_key(|(key, _)| *key);
+
+    let rows = data.into_iter().map(|(key, value)| {
+      let index = snapshot.len(key).saturating_sub(1);
+      (key.as_str(), index, value.as_slice(), base64(key))
+    });
+    let body = match format {
+      Format::NdJson => to_sensors_ndjson(rows),
+      _ => to_csv(
+        &["id", "index"],
+        rows.map(|(id, index, x, base64)| {
+          (vec![id.to_owned(), index.to_string()], x, base64)
+        }),
+      ),
+    };
+
+    return Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::OK)
+        .header(warp::http::header::CONTENT_TYPE, format.content_type())
+        .body(body),
+    );
+  }
+
+  if format.is_binary() {
+    let result = data
+      .iter()
+      .filter(|(_, value)| !value.is_empty())
+      .map(|(key, value)| (key.clone(), to_value(value, stored(key))))
+      .collect::<serde_json::Map<_, _>>();
 
     return Ok(
       warp::hyper::Response::builder()
         .status(warp::http::StatusCode::OK)
-        .body(result),
+        .header(warp::http::header::CONTENT_TYPE, format.content_type())
+        .body(format.encode(&result.into())),
     );
   }
 
+  let result = serde_json::to_vec(
+    &data
+      .iter()
+      .filter(|(_, value)| !value.is_empty())
+      .map(|(key, value)| (key, encode_record(value, base64(key))))
+      .collect::<std::collections::HashMap<_, _>>(),
+  )
+  .unwrap_or_default();
+
   Ok(
     warp::hyper::Response::builder()
-      .status(warp::http::StatusCode::NOT_FOUND)
-      .body(Default::default()),
+      .status(warp::http::StatusCode::OK)
+      .body(result),
   )
 }
 
@@ -69,10 +144,9 @@ async fn test_get() {
       .await;
     assert_eq!(resp.status(), 404);
 
-    db.write()
+    db.post(id, serde_json::json!({ "id": &id }).to_string().as_bytes())
       .await
-      .post(id, serde_json::json!({ "id": &id }).to_string().as_bytes())
-      .await;
+      .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -85,10 +159,9 @@ async fn test_get() {
       serde_json::json!({id: { "id": &id }})
     );
 
-    db.write()
+    db.post(id, serde_json::json!({ "id1": &id }).to_string().as_bytes())
       .await
-      .post(id, serde_json::json!({ "id1": &id }).to_string().as_bytes())
-      .await;
+      .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -101,10 +174,9 @@ async fn test_get() {
       serde_json::json!({id: { "id1": &id }})
     );
 
-    db.write()
+    db.post(id0, serde_json::json!({ "id2": &id2 }).to_string().as_bytes())
       .await
-      .post(id0, serde_json::json!({ "id2": &id2 }).to_string().as_bytes())
-      .await;
+      .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -121,13 +193,12 @@ async fn test_get() {
       2
     );
 
-    db.write()
-      .await
-      .post_meta(
-        id1,
-        serde_json::json!({ "id1": &id1 }).to_string().as_bytes().to_vec(),
-      )
-      .await;
+    db.post_meta(
+      id1,
+      serde_json::json!({ "id1": &id1 }).to_string().as_bytes().to_vec(),
+    )
+    .await
+    .unwrap();
     let resp = warp::test::request()
       .method("GET")
       .path("/api/v0/sensors/latest")
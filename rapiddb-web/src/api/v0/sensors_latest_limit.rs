@@ -1,51 +1,148 @@
-use crate::api::helpers::with_db;
+use crate::api::helpers::{
+  content_type, encode_record, to_csv, to_sensors_ndjson, to_value, with_db,
+  with_snapshots, Format, ReadQuery,
+};
+use crate::api::snapshots::Snapshots;
 use rapiddb::traits::IAsyncDatabase;
 
 use warp::{Filter, Rejection, Reply};
 
 /// GET /api/v0/sensors/latest/:usize
 pub fn get(
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+  snapshots: Snapshots,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
   warp::path!("api" / "v0" / "sensors" / "latest" / usize)
     .and(warp::get())
+    .and(warp::query::<ReadQuery>())
+    .and(warp::header::optional::<String>("accept"))
     .and(with_db(db))
+    .and(with_snapshots(snapshots))
     .and_then(_get)
 }
 
 pub async fn _get(
   limit: usize,
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  query: ReadQuery,
+  accept: Option<String>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+  snapshots: Snapshots,
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
-  let mut lock = db.write().await;
-  let data = lock.get_all_latest_with_limit(limit).await;
-
-  if !data.is_empty() {
-    let mut result: String = Default::default();
-    result += "{";
-    for (key, value_arr) in data {
-      result += &format!("\"{key}\":[");
-      for value in value_arr {
-        result +=
-          &format!("{},", std::str::from_utf8(&value).unwrap_or_default());
-      }
-      result.pop();
-      result += "],";
+  let Ok(snapshot) = snapshots.resolve(query.as_of) else {
+    return Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::NOT_FOUND)
+        .body(Default::default()),
+    );
+  };
+  let format = Format::negotiate(&accept);
+  // Record indexes are read from a snapshot, so they match the records
+  let snapshot = match (snapshot, format) {
+    (None, Format::NdJson | Format::Csv) => {
+      Some(std::sync::Arc::new(db.snapshot().await))
     }
-    result.pop();
-    result += "}";
+    (x, _) => x,
+  };
+
+  let content_types = db
+    .get_all_meta()
+    .await
+    .into_iter()
+    .map(|(key, value)| (key, content_type(&value)))
+    .collect::<std::collections::HashMap<_, _>>();
+  let stored = |id: &str| {
+    content_types.get(id).map(|x| x.as_str()).unwrap_or("application/json")
+  };
+  let base64 = |id: &str| query.base64(stored(id));
+  let data = match &snapshot {
+    Some(snapshot) => db.get_all_latest_with_limit_as_of(snapshot, limit).await,
+    None => db.get_all_latest_with_limit(limit).await,
+  };
+
+  if data.is_empty() {
+    return Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::NOT_FOUND)
+        .body(Default::default()),
+    );
+  }
+
+  if let (Format::NdJson | Format::Csv, Some(snapshot)) = (format, snapshot) {
+    let mut data =
+      data.iter().filter(|(_, value)| !value.is_empty()).collect::<Vec<_>>();
+    data.sort_by_key(|(key, _)| *key);
+
+    let rows = data.into_iter().flat_map(|(key, value_arr)| {
+      let base64 = base64(key);
+      let first = snapshot.len(key).saturating_sub(value_arr.len());
+      value_arr
+        .iter()
+        .enumerate()
+        .map(move |(i, x)| (key.as_str(), first + i, x.as_slice(), base64))
+    });
+    let body = match format {
+      Format::NdJson => to_sensors_ndjson(rows),
+      _ => to_csv(
+        &["id", "index"],
+        rows.map(|(id, index, x, base64)| {
+          (vec![id.to_owned(), index.to_string()], x, base64)
+        }),
+      ),
+    };
+
+    return Ok(
+      warp::hyper::Response::builder()
+        .status(warp::http::StatusCode::OK)
+        .header(warp::http::header::CONTENT_TYPE, format.content_type())
+        .body(body),
+    );
+  }
+
+  if format.is_binary() {
+    let result = data
+      .iter()
+      .filter(|(_, value_arr)| !value_arr.is_empty())
+      .map(|(key, value_arr)| {
+        (
+          key.clone(),
+          value_arr
+            .iter()
+            .map(|x| to_value(x, stored(key)))
+            .collect::<Vec<_>>()
+            .into(),
+        )
+      })
+      .collect::<serde_json::Map<_, _>>();
 
     return Ok(
       warp::hyper::Response::builder()
         .status(warp::http::StatusCode::OK)
-        .body(result),
+        .header(warp::http::header::CONTENT_TYPE, format.content_type())
+        .body(format.encode(&result.into())),
     );
   }
 
+  let result = serde_json::to_vec(
+    &data
+      .iter()
+      .map(|(key, value_arr)| {
+        let base64 = base64(key);
+        (
+          key,
+          value_arr
+            .iter()
+            .map(|x| encode_record(x, base64))
+            .collect::<Vec<_>>(),
+        )
+      })
+      .collect::<std::collections::HashMap<_, _>>(),
+  )
+  .unwrap_or_default();
+
   Ok(
     warp::hyper::Response::builder()
-      .status(warp::http::StatusCode::NOT_FOUND)
-      .body(Default::default()),
+      .status(warp::http::StatusCode::OK)
+      .body(result),
   )
 }
 
@@ -70,10 +167,9 @@ async fn test_get() {
       .await;
     assert_eq!(resp.status(), 404);
 
-    db.write()
+    db.post(id, serde_json::json!({ "id": &id }).to_string().as_bytes())
       .await
-      .post(id, serde_json::json!({ "id": &id }).to_string().as_bytes())
-      .await;
+      .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -93,10 +189,9 @@ async fn test_get() {
       .await;
     assert_eq!(resp.status(), 404);
 
-    db.write()
+    db.post(id, serde_json::json!({ "id1": &id }).to_string().as_bytes())
       .await
-      .post(id, serde_json::json!({ "id1": &id }).to_string().as_bytes())
-      .await;
+      .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -126,10 +221,9 @@ async fn test_get() {
     );
 
     for _ in 0..8 {
-      db.write()
+      db.post(id0, serde_json::json!({ "id2": &id }).to_string().as_bytes())
         .await
-        .post(id0, serde_json::json!({ "id2": &id }).to_string().as_bytes())
-        .await;
+        .unwrap();
     }
 
     let resp = warp::test::request()
@@ -169,17 +263,15 @@ async fn test_get() {
     );
 
     for _ in 0..8 {
-      db.write()
+      db.post(id0, serde_json::json!({ "id2": &id }).to_string().as_bytes())
         .await
-        .post(id0, serde_json::json!({ "id2": &id }).to_string().as_bytes())
-        .await;
+        .unwrap();
     }
 
     for _ in 0..7 {
-      db.write()
+      db.post(id, serde_json::json!({ "id2": &id }).to_string().as_bytes())
         .await
-        .post(id, serde_json::json!({ "id2": &id }).to_string().as_bytes())
-        .await;
+        .unwrap();
     }
 
     let resp = warp::test::request()
@@ -218,10 +310,9 @@ async fn test_get() {
       19
     );
 
-    db.write()
+    db.post(id, serde_json::json!({ "id2": &id }).to_string().as_bytes())
       .await
-      .post(id, serde_json::json!({ "id2": &id }).to_string().as_bytes())
-      .await;
+      .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -260,17 +351,15 @@ async fn test_get() {
     );
 
     for _ in 0..8 {
-      db.write()
+      db.post(id, serde_json::json!({ "id2": &id }).to_string().as_bytes())
         .await
-        .post(id, serde_json::json!({ "id2": &id }).to_string().as_bytes())
-        .await;
+        .unwrap();
     }
 
     for _ in 0..8 {
-      db.write()
+      db.post(id0, serde_json::json!({ "id2": &id }).to_string().as_bytes())
         .await
-        .post(id0, serde_json::json!({ "id2": &id }).to_string().as_bytes())
-        .await;
+        .unwrap();
     }
 
     let resp = warp::test::request()
@@ -309,13 +398,12 @@ async fn test_get() {
       20
     );
 
-    db.write()
-      .await
-      .post_meta(
-        id1,
-        serde_json::json!({ "id1": &id1 }).to_string().as_bytes().to_vec(),
-      )
-      .await;
+    db.post_meta(
+      id1,
+      serde_json::json!({ "id1": &id1 }).to_string().as_bytes().to_vec(),
+    )
+    .await
+    .unwrap();
     let resp = warp::test::request()
       .method("GET")
       .path(&format!("/api/v0/sensors/latest/{limit}"))
@@ -324,3 +412,49 @@ async fn test_get() {
     assert_eq!(resp.status(), 200);
   }
 }
+
+#[tokio::test]
+async fn test_get_formats() {
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensors_latest_limit/test_get_formats",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    for i in 0..3 {
+      db.post("test-0", serde_json::json!({ "n": i }).to_string().as_bytes())
+        .await
+        .unwrap();
+    }
+    db.post("test-1", br#"{"t":20.5}"#).await.unwrap();
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path("/api/v0/sensors/latest/2")
+      .header("accept", "application/x-ndjson")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      resp.body(),
+      concat!(
+        "{\"id\":\"test-0\",\"index\":1,\"value\":{\"n\":1}}\n",
+        "{\"id\":\"test-0\",\"index\":2,\"value\":{\"n\":2}}\n",
+        "{\"id\":\"test-1\",\"index\":0,\"value\":{\"t\":20.5}}\n",
+      )
+    );
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path("/api/v0/sensors/latest/2")
+      .header("accept", "text/csv")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      resp.body(),
+      "id,index,n,t\r\ntest-0,1,1,\r\ntest-0,2,2,\r\ntest-1,0,,20.5\r\n"
+    );
+  }
+}
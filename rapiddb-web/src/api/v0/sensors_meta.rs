@@ -1,47 +1,41 @@
-use crate::api::helpers::with_db;
+use crate::api::helpers::{reply_document, to_raw_json, with_db};
 use rapiddb::traits::IAsyncDatabase;
 use warp::Filter;
 
 /// GET /api/v0/sensors/meta
 pub fn get(
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
 {
   warp::path!("api" / "v0" / "sensors" / "meta")
     .and(warp::get())
+    .and(warp::header::optional::<String>("accept"))
     .and(with_db(db))
     .and_then(_get)
 }
 
 pub async fn _get(
-  db: std::sync::Arc<tokio::sync::RwLock<impl IAsyncDatabase + ?Sized>>,
+  accept: Option<String>,
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
-  let mut lock = db.write().await;
-  let data = lock.get_all_meta().await;
+  let data = db.get_all_meta().await;
 
   if !data.is_empty() {
-    let mut result: String = Default::default();
-    result += "{";
-    for (key, value) in data {
-      result += &format!(
-        "\"{key}\":{},",
-        std::str::from_utf8(&value).unwrap_or_default()
-      );
-    }
-    result.pop();
-    result += "}";
+    let result = serde_json::to_string(
+      &data
+        .iter()
+        .map(|(key, value)| (key, to_raw_json(value)))
+        .collect::<std::collections::HashMap<_, _>>(),
+    )
+    .unwrap_or_default();
 
-    return Ok(
-      warp::hyper::Response::builder()
-        .status(warp::http::StatusCode::OK)
-        .body(result),
-    );
+    return Ok(reply_document(&accept, result.into()));
   }
 
   Ok(
     warp::hyper::Response::builder()
       .status(warp::http::StatusCode::NOT_FOUND)
-      .body(String::new()),
+      .body(Default::default()),
   )
 }
 
@@ -63,13 +57,12 @@ async fn test_get() {
       .await;
     assert_eq!(resp.status(), 404);
 
-    db.write()
-      .await
-      .post_meta(
-        id,
-        serde_json::json!({ "id": &id }).to_string().as_bytes().to_vec(),
-      )
-      .await;
+    db.post_meta(
+      id,
+      serde_json::json!({ "id": &id }).to_string().as_bytes().to_vec(),
+    )
+    .await
+    .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
@@ -82,13 +75,12 @@ async fn test_get() {
       serde_json::json!({id: {"id": &id}})
     );
 
-    db.write()
-      .await
-      .post_meta(
-        id0,
-        serde_json::json!({ "id0": &id0 }).to_string().as_bytes().to_vec(),
-      )
-      .await;
+    db.post_meta(
+      id0,
+      serde_json::json!({ "id0": &id0 }).to_string().as_bytes().to_vec(),
+    )
+    .await
+    .unwrap();
 
     let resp = warp::test::request()
       .method("GET")
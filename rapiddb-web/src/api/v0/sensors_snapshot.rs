@@ -0,0 +1,144 @@
+use crate::api::helpers::{with_db, with_snapshots};
+use crate::api::snapshots::Snapshots;
+use rapiddb::traits::IAsyncDatabase;
+
+use warp::{Filter, Rejection, Reply};
+
+/// POST /api/v0/sensors/snapshot
+pub fn post(
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+  snapshots: Snapshots,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  warp::path!("api" / "v0" / "sensors" / "snapshot")
+    .and(warp::post())
+    .and(with_db(db))
+    .and(with_snapshots(snapshots))
+    .and_then(_post)
+}
+
+pub async fn _post(
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized>,
+  snapshots: Snapshots,
+) -> Result<impl warp::Reply, std::convert::Infallible> {
+  let as_of = snapshots.insert(db.snapshot().await);
+
+  Ok(
+    warp::hyper::Response::builder()
+      .status(warp::http::StatusCode::CREATED)
+      .body(serde_json::json!({ "as_of": as_of }).to_string()),
+  )
+}
+
+#[tokio::test]
+async fn test_post() {
+  let database_test_factory = rapiddb::db::DatabaseTestFactory::new(
+    ".temp/test/sensors_snapshot/test_post",
+  );
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "test-0";
+    let id1 = "test-1";
+
+    db.post(
+      id,
+      serde_json::json!({ "id": &id, "n": 0 }).to_string().as_bytes(),
+    )
+    .await
+    .unwrap();
+
+    let resp = warp::test::request()
+      .method("POST")
+      .path("/api/v0/sensors/snapshot")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 201);
+    let as_of = serde_json::from_slice::<serde_json::Value>(resp.body())
+      .unwrap()["as_of"]
+      .as_u64()
+      .unwrap();
+
+    db.post(
+      id,
+      serde_json::json!({ "id": &id, "n": 1 }).to_string().as_bytes(),
+    )
+    .await
+    .unwrap();
+    db.post(
+      id1,
+      serde_json::json!({ "id": &id1, "n": 0 }).to_string().as_bytes(),
+    )
+    .await
+    .unwrap();
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/latest?as_of={as_of}"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!({ "id": &id, "n": 0 })
+    );
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id1}/latest?as_of={as_of}"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 404);
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/latest/10?as_of={as_of}"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!([{ "id": &id, "n": 0 }])
+    );
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/{id}/0/10?as_of={as_of}"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!([{ "id": &id, "n": 0 }])
+    );
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/sensors/latest?as_of={as_of}"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!({ id: { "id": &id, "n": 0 } })
+    );
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path(&format!("/api/v0/sensors/latest/10?as_of={as_of}"))
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+      serde_json::json!({ id: [{ "id": &id, "n": 0 }] })
+    );
+
+    let resp = warp::test::request()
+      .method("GET")
+      .path("/api/v0/sensors/latest?as_of=0")
+      .reply(&api)
+      .await;
+    assert_eq!(resp.status(), 404);
+  }
+}
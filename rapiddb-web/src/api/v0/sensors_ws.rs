@@ -0,0 +1,99 @@
+use crate::api::helpers::with_db;
+use crate::api::v0::sensor_ws::{stream, StreamQuery};
+use rapiddb::db::SubscriptionFilter;
+use rapiddb::traits::IAsyncDatabase;
+
+use warp::{Filter, Rejection, Reply};
+
+/// Query options for live streams of multiple sensors
+///
+/// `prefix` selects every sensor with an id starting with it, by
+/// default every sensor.
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+pub struct PrefixQuery {
+  #[serde(default)]
+  pub prefix: String,
+}
+
+/// GET /api/v0/sensors/ws
+pub fn get(
+  db: std::sync::Arc<impl IAsyncDatabase + ?Sized + 'static>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  warp::path!("api" / "v0" / "sensors" / "ws")
+    .and(warp::ws())
+    .and(warp::query::<PrefixQuery>())
+    .and(warp::query::<StreamQuery>())
+    .and(with_db(db))
+    .map(|ws: warp::ws::Ws, prefix: PrefixQuery, query: StreamQuery, db| {
+      ws.on_upgrade(move |socket| {
+        stream(socket, db, SubscriptionFilter::Prefix(prefix.prefix), query)
+      })
+    })
+}
+
+#[tokio::test]
+async fn test_get() {
+  let database_test_factory =
+    rapiddb::db::DatabaseTestFactory::new(".temp/test/sensors_ws/test_get");
+
+  for db in database_test_factory.get_instance().values() {
+    let api = super::endpoints((*db).clone());
+
+    let id = "phase-0";
+    let id1 = "phase-1";
+    let id2 = "test-0";
+
+    db.post(id, serde_json::json!({ "n": 0 }).to_string().as_bytes())
+      .await
+      .unwrap();
+    db.post(id2, serde_json::json!({ "n": 0 }).to_string().as_bytes())
+      .await
+      .unwrap();
+
+    let mut client = warp::test::ws()
+      .path("/api/v0/sensors/ws?prefix=phase-&start=0")
+      .handshake(api)
+      .await
+      .unwrap();
+
+    let message = client.recv().await.unwrap();
+    assert_eq!(
+      serde_json::from_str::<serde_json::Value>(message.to_str().unwrap())
+        .unwrap(),
+      serde_json::json!({ "id": &id, "rec_id": 0, "value": { "n": 0 } })
+    );
+
+    db.post(id2, serde_json::json!({ "n": 1 }).to_string().as_bytes())
+      .await
+      .unwrap();
+    db.post(id1, serde_json::json!({ "n": 0 }).to_string().as_bytes())
+      .await
+      .unwrap();
+
+    let message = client.recv().await.unwrap();
+    assert_eq!(
+      serde_json::from_str::<serde_json::Value>(message.to_str().unwrap())
+        .unwrap(),
+      serde_json::json!({ "id": &id1, "rec_id": 0, "value": { "n": 0 } })
+    );
+
+    let id3 = "phase-2";
+
+    let mut client = warp::test::ws()
+      .path("/api/v0/sensors/ws?prefix=phase-&start=1")
+      .handshake(super::endpoints((*db).clone()))
+      .await
+      .unwrap();
+
+    db.post(id3, serde_json::json!({ "n": 0 }).to_string().as_bytes())
+      .await
+      .unwrap();
+
+    let message = client.recv().await.unwrap();
+    assert_eq!(
+      serde_json::from_str::<serde_json::Value>(message.to_str().unwrap())
+        .unwrap(),
+      serde_json::json!({ "id": &id3, "rec_id": 0, "value": { "n": 0 } })
+    );
+  }
+}
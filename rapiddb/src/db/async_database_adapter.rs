@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::db::BlockingPool;
+use crate::db::Change;
+use crate::db::Notification;
+use crate::db::Notifier;
+use crate::db::Snapshot;
+use crate::db::Subscription;
+use crate::db::SubscriptionFilter;
+use crate::db::WriteBatch;
+use crate::errors::Error;
+use crate::traits::{IAsyncDatabase, IDatabase};
+
+/// Async Database Adapter
+///
+/// Wraps any `IDatabase` and implements `IAsyncDatabase` for it, so a
+/// custom backend only has to implement the sync trait to be used with
+/// the async API, such as `rapiddb_web::api::endpoints`.
+///
+/// The database is guarded by a `RwLock`, reads take a read lock and
+/// writes take a write lock. Every operation runs on a bounded
+/// `BlockingPool`, so the database may block on disk IO without stalling
+/// the async executor.
+///
+/// ## Examples
+/// ```no_run
+/// use crate::rapiddb::traits::IAsyncDatabase;
+///
+/// # tokio_test::block_on(async {
+/// let db = rapiddb::db::AsyncDatabaseAdapter::new(
+///   rapiddb::db::MMAVDatabase::new()
+/// );
+///
+/// let value = b"{\"key\": \"value\"}";
+/// db.post("test-0", value).await.unwrap();
+/// assert_eq!(db.get_latest("test-0").await, value);
+/// # })
+/// ```
+pub struct AsyncDatabaseAdapter<D: IDatabase + 'static> {
+  db: Arc<RwLock<D>>,
+  pool: BlockingPool,
+  notifier: Notifier,
+}
+impl<D: IDatabase + 'static> AsyncDatabaseAdapter<D> {
+  /// Async Database Adapter Constructor
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// let db = rapiddb::db::AsyncDatabaseAdapter::new(
+  ///   rapiddb::db::MMAVDatabase::new()
+  /// );
+  /// ```
+  pub fn new(db: D) -> Self {
+    Self::new_with_arc(Arc::new(RwLock::new(db)))
+  }
+
+  /// Async Database Adapter Constructor with a shared database
+  ///
+  /// The database can still be used through the sync API by other
+  /// holders of `db`.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// let db = std::sync::Arc::new(
+  ///   std::sync::RwLock::new(
+  ///     rapiddb::db::MMAVDatabase::new()
+  ///   )
+  /// );
+  ///
+  /// let async_db = rapiddb::db::AsyncDatabaseAdapter::new_with_arc(db.clone());
+  /// ```
+  pub fn new_with_arc(db: Arc<RwLock<D>>) -> Self {
+    Self { db, pool: Default::default(), notifier: Default::default() }
+  }
+
+  /// Use `pool` to run the operations of the database
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// let db = rapiddb::db::AsyncDatabaseAdapter::new(
+  ///   rapiddb::db::MMAVDatabase::new()
+  /// )
+  /// .with_blocking_pool(rapiddb::db::BlockingPool::new(64));
+  /// ```
+  pub fn with_blocking_pool(mut self, pool: BlockingPool) -> Self {
+    self.pool = pool;
+    self
+  }
+
+  /// Run `f` with a read lock on the database on the blocking pool
+  async fn read<F, T>(&self, f: F) -> T
+  where
+    F: FnOnce(&D) -> T + Send + 'static,
+    T: Send + 'static,
+  {
+    let db = self.db.clone();
+    self.pool.run(move || f(&db.read().unwrap())).await
+  }
+
+  /// Run `f` with a write lock on the database on the blocking pool
+  async fn write<F, T>(&self, f: F) -> T
+  where
+    F: FnOnce(&mut D) -> T + Send + 'static,
+    T: Send + 'static,
+  {
+    let db = self.db.clone();
+    self.pool.run(move || f(&mut db.write().unwrap())).await
+  }
+}
+
+/// Convert a map borrowing its keys from the database to an owned map
+fn to_owned_keys<T>(map: HashMap<&str, T>) -> HashMap<String, T> {
+  map.into_iter().map(|(key, value)| (key.to_owned(), value)).collect()
+}
+
+fn to_notified<'a>(
+  records: &[(&'a str, &'a [u8])],
+  rec_ids: &[usize],
+) -> Vec<(&'a str, usize, &'a [u8])> {
+  records
+    .iter()
+    .zip(rec_ids)
+    .map(|((id, value), x)| (*id, *x, *value))
+    .collect()
+}
+
+#[async_trait::async_trait]
+impl<D: IDatabase + 'static> IAsyncDatabase for AsyncDatabaseAdapter<D> {
+  async fn contains(&self, id: &str) -> bool {
+    let id = id.to_owned();
+    self.read(move |x| x.contains(&id)).await
+  }
+
+  async fn len(&self, id: &str) -> usize {
+    let id = id.to_owned();
+    self.read(move |x| x.len(&id)).await
+  }
+
+  async fn get(&self, id: &str, rec_id: usize) -> Vec<u8> {
+    let id = id.to_owned();
+    self.read(move |x| x.get(&id, rec_id)).await
+  }
+
+  async fn post(&self, id: &str, value: &[u8]) -> Result<usize, Error> {
+    let id = id.to_owned();
+    let value = value.to_owned();
+    let notifier = self.notifier.clone();
+    self
+      .write(move |x| {
+        let result = x.post(&id, &value);
+        if let Ok(rec_id) = result {
+          notifier.records(&[(&id, rec_id, &value)]);
+        }
+        result
+      })
+      .await
+  }
+
+  async fn post_with_key(
+    &self,
+    id: &str,
+    key: &str,
+    value: &[u8],
+  ) -> Result<usize, Error> {
+    let id = id.to_owned();
+    let key = key.to_owned();
+    let value = value.to_owned();
+    let notifier = self.notifier.clone();
+    self
+      .write(move |x| {
+        // a replayed key returns the index of the earlier record without
+        // appending, which must not be announced again
+        let len = x.len(&id);
+        let result = x.post_with_key(&id, &key, &value);
+        match result {
+          Ok(rec_id) if rec_id >= len => {
+            notifier.records(&[(&id, rec_id, &value)]);
+          }
+          _ => (),
+        }
+        result
+      })
+      .await
+  }
+
+  async fn post_batch(
+    &self,
+    id: &str,
+    values: &[&[u8]],
+  ) -> Result<Vec<usize>, Error> {
+    let id = id.to_owned();
+    let values = values.iter().map(|x| x.to_vec()).collect::<Vec<_>>();
+    let notifier = self.notifier.clone();
+    self
+      .write(move |x| {
+        let result =
+          x.post_batch(&id, &values.iter().map(|x| &x[..]).collect::<Vec<_>>());
+        if let Ok(rec_ids) = &result {
+          notifier.records(
+            &rec_ids
+              .iter()
+              .zip(&values)
+              .map(|(x, value)| (&id[..], *x, &value[..]))
+              .collect::<Vec<_>>(),
+          );
+        }
+        result
+      })
+      .await
+  }
+
+  async fn post_many(
+    &self,
+    records: &[(&str, &[u8])],
+  ) -> Result<Vec<usize>, Error> {
+    let records = records
+      .iter()
+      .map(|(id, value)| (id.to_string(), value.to_vec()))
+      .collect::<Vec<_>>();
+    let notifier = self.notifier.clone();
+    self
+      .write(move |x| {
+        let records = records
+          .iter()
+          .map(|(id, value)| (&id[..], &value[..]))
+          .collect::<Vec<_>>();
+        let result = x.post_many(&records);
+        if let Ok(rec_ids) = &result {
+          notifier.records(&to_notified(&records, rec_ids));
+        }
+        result
+      })
+      .await
+  }
+
+  async fn commit(&self, batch: &WriteBatch) -> Result<Vec<usize>, Error> {
+    let batch = batch.clone();
+    let notifier = self.notifier.clone();
+    self
+      .write(move |x| {
+        let result = x.commit(&batch);
+        if let Ok(rec_ids) = &result {
+          notifier.records(&to_notified(&batch.records(), rec_ids));
+        }
+        result
+      })
+      .await
+  }
+
+  async fn get_meta(&self, id: &str) -> Vec<u8> {
+    let id = id.to_owned();
+    self.read(move |x| x.get_meta(&id)).await
+  }
+
+  async fn post_meta(&self, id: &str, data: Vec<u8>) -> Result<(), Error> {
+    let id = id.to_owned();
+    let notifier = self.notifier.clone();
+    self
+      .write(move |x| {
+        let result = x.post_meta(&id, data);
+        if result.is_ok() {
+          notifier.meta(&id);
+        }
+        result
+      })
+      .await
+  }
+
+  async fn get_aggregates(&self, id: &str) -> Vec<u8> {
+    let id = id.to_owned();
+    self.read(move |x| x.get_aggregates(&id)).await
+  }
+
+  async fn get_latest(&self, id: &str) -> Vec<u8> {
+    let id = id.to_owned();
+    self.read(move |x| x.get_latest(&id)).await
+  }
+
+  async fn get_latest_with_limit(
+    &self,
+    id: &str,
+    limit: usize,
+  ) -> Vec<Vec<u8>> {
+    let id = id.to_owned();
+    self.read(move |x| x.get_latest_with_limit(&id, limit)).await
+  }
+
+  async fn get_range(
+    &self,
+    id: &str,
+    start: usize,
+    end: usize,
+  ) -> Vec<Vec<u8>> {
+    let id = id.to_owned();
+    self.read(move |x| x.get_range(&id, start, end)).await
+  }
+
+  async fn get_all_meta(&self) -> HashMap<String, Vec<u8>> {
+    self.read(|x| to_owned_keys(x.get_all_meta())).await
+  }
+
+  async fn get_all_aggregates(&self) -> HashMap<String, Vec<u8>> {
+    self.read(|x| to_owned_keys(x.get_all_aggregates())).await
+  }
+
+  async fn get_all_latest(&self) -> HashMap<String, Vec<u8>> {
+    self.read(|x| to_owned_keys(x.get_all_latest())).await
+  }
+
+  async fn get_all_latest_with_limit(
+    &self,
+    limit: usize,
+  ) -> HashMap<String, Vec<Vec<u8>>> {
+    self.read(move |x| to_owned_keys(x.get_all_latest_with_limit(limit))).await
+  }
+
+  async fn snapshot(&self) -> Snapshot {
+    self.read(|x| x.snapshot()).await
+  }
+
+  async fn changes_since(&self, lsn: usize, limit: usize) -> Vec<Change> {
+    self.read(move |x| x.changes_since(lsn, limit)).await
+  }
+
+  async fn get_offset(&self, consumer: &str, id: &str) -> usize {
+    let consumer = consumer.to_owned();
+    let id = id.to_owned();
+    self.read(move |x| x.get_offset(&consumer, &id)).await
+  }
+
+  async fn poll(
+    &self,
+    consumer: &str,
+    id: &str,
+    max: usize,
+  ) -> (usize, Vec<(usize, Vec<u8>)>) {
+    let consumer = consumer.to_owned();
+    let id = id.to_owned();
+    self.read(move |x| x.poll(&consumer, &id, max)).await
+  }
+
+  async fn commit_offset(
+    &self,
+    consumer: &str,
+    id: &str,
+    offset: usize,
+  ) -> Result<(), Error> {
+    let consumer = consumer.to_owned();
+    let id = id.to_owned();
+    self.write(move |x| x.commit_offset(&consumer, &id, offset)).await
+  }
+
+  fn notifications(&self) -> tokio::sync::broadcast::Receiver<Notification> {
+    self.notifier.subscribe()
+  }
+
+  fn subscribe(&self, filter: SubscriptionFilter) -> Subscription {
+    self.notifier.subscription(filter)
+  }
+}
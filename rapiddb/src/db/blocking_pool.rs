@@ -0,0 +1,75 @@
+/// Blocking Pool
+///
+/// Runs blocking operations, such as disk IO on memory mapped files, on
+/// the tokio blocking thread pool, so they do not stall the async
+/// executor. The number of operations queued or running at once is
+/// bounded, callers wait for a free slot when the pool is full, which
+/// applies backpressure to the async callers.
+///
+/// Must be used from within a tokio runtime.
+///
+/// ## Examples
+/// ```no_run
+/// # tokio_test::block_on(async {
+/// let pool = rapiddb::db::BlockingPool::new(64);
+///
+/// let data = pool.run(|| std::fs::read("Cargo.toml")).await;
+/// # })
+/// ```
+#[derive(Clone)]
+pub struct BlockingPool {
+  permits: std::sync::Arc<tokio::sync::Semaphore>,
+}
+impl BlockingPool {
+  /// Blocking Pool Constructor
+  ///
+  /// `size` is the maximum number of operations queued or running at
+  /// once.
+  ///
+  /// ## Panics
+  /// if `size` is zero
+  ///
+  /// ## Examples
+  /// ```
+  /// let pool = rapiddb::db::BlockingPool::new(64);
+  /// ```
+  pub fn new(size: usize) -> Self {
+    assert!(size > 0, "BlockingPool size must be greater than zero");
+
+    Self { permits: std::sync::Arc::new(tokio::sync::Semaphore::new(size)) }
+  }
+
+  /// Run `f` on the blocking thread pool, waiting for a free slot first
+  ///
+  /// ## Panics
+  /// if `f` panics, the panic is resumed on the caller
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// # tokio_test::block_on(async {
+  /// let pool = rapiddb::db::BlockingPool::new(64);
+  ///
+  /// assert_eq!(pool.run(|| 1 + 1).await, 2);
+  /// # })
+  /// ```
+  pub async fn run<F, T>(&self, f: F) -> T
+  where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+  {
+    let permit = self.permits.clone().acquire_owned().await.unwrap();
+
+    tokio::task::spawn_blocking(move || {
+      let _permit = permit;
+      f()
+    })
+    .await
+    .unwrap_or_else(|error| std::panic::resume_unwind(error.into_panic()))
+  }
+}
+
+impl Default for BlockingPool {
+  fn default() -> Self {
+    Self::new(512)
+  }
+}
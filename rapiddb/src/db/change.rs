@@ -0,0 +1,32 @@
+/// Change
+///
+/// A write to the database, as returned by the change feed. Every write
+/// is assigned a database-wide log sequence number `lsn`, which starts
+/// at one and increases in commit order.
+///
+/// ## Examples
+/// ```no_run
+/// use crate::rapiddb::traits::IAsyncDatabase;
+///
+/// # tokio_test::block_on(async {
+/// let db = rapiddb::db::MMAVAsyncDatabase::new();
+///
+/// db.post("test-0", b"{\"key\": \"value\"}").await.unwrap();
+///
+/// for change in db.changes_since(0, 100).await {
+///   println!("{} {} {:?}", change.lsn, change.id, change.rec_id);
+/// }
+/// # })
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Change {
+  /// Log sequence number of the change
+  pub lsn: usize,
+  /// Id of the sensor which was written
+  pub id: String,
+  /// Index of the record which was posted, or `None` if the metadata of
+  /// the sensor was posted
+  pub rec_id: Option<usize>,
+  /// The posted record or metadata
+  pub value: Vec<u8>,
+}
@@ -5,7 +5,9 @@ use std::sync::Mutex;
 use crate::traits::IAsyncDatabase;
 use crate::types::AggregateFn;
 
+use super::AsyncDatabaseAdapter;
 use super::MMAVAsyncDatabase;
+use super::MMAVDatabase;
 
 /// Database test factory, stores a hashmap with all databases for
 /// testing
@@ -18,17 +20,15 @@ use super::MMAVAsyncDatabase;
 ///
 ///   for db in database_test_factory.get_instance().values() {
 ///     let value = b"{\"key\": \"value\"}";
-///     db.write().await.post("test-0", value).await;
-///     assert_eq!(db.write().await.get_latest("test-0").await, value);
+///     db.post("test-0", value).await.unwrap();
+///     assert_eq!(db.get_latest("test-0").await, value);
 ///   }
 /// # })
 /// ```
 pub struct DatabaseTestFactory {
   db_path: String,
-  databases: std::collections::HashMap<
-    String,
-    std::sync::Arc<tokio::sync::RwLock<dyn IAsyncDatabase>>,
-  >,
+  databases:
+    std::collections::HashMap<String, std::sync::Arc<dyn IAsyncDatabase>>,
 }
 impl Drop for DatabaseTestFactory {
   fn drop(&mut self) {
@@ -49,15 +49,15 @@ impl DatabaseTestFactory {
   ///
   ///   for db in database_test_factory.get_instance().values() {
   ///     let value = b"{\"key\": \"value\"}";
-  ///     db.write().await.post("test-0", value).await;
-  ///     assert_eq!(db.write().await.get_latest("test-0").await, value);
+  ///     db.post("test-0", value).await.unwrap();
+  ///     assert_eq!(db.get_latest("test-0").await, value);
   ///   }
   /// # })
   /// ```
   pub fn new(db_path: &str) -> Self {
     let mut databases: std::collections::HashMap<
       String,
-      std::sync::Arc<tokio::sync::RwLock<dyn IAsyncDatabase>>,
+      std::sync::Arc<dyn IAsyncDatabase>,
     > = Default::default();
 
     // type AggregateFn = Arc<Mutex<dyn Fn(&str, &[u8], &Arc<Mutex<Vec<u8>>>) +
@@ -106,8 +106,17 @@ impl DatabaseTestFactory {
     let mmav_db_path = format!("{db_path}_mmav");
     databases.insert(
       mmav_db_path.clone(),
-      std::sync::Arc::new(tokio::sync::RwLock::new(
-        MMAVAsyncDatabase::new_with_all(&mmav_db_path, aggregates_fn.clone()),
+      std::sync::Arc::new(MMAVAsyncDatabase::new_with_all(
+        &mmav_db_path,
+        aggregates_fn.clone(),
+      )),
+    );
+
+    let mmav_adapter_db_path = format!("{db_path}_mmav_adapter");
+    databases.insert(
+      mmav_adapter_db_path.clone(),
+      std::sync::Arc::new(AsyncDatabaseAdapter::new(
+        MMAVDatabase::new_with_all(&mmav_adapter_db_path, aggregates_fn),
       )),
     );
 
@@ -117,10 +126,8 @@ impl DatabaseTestFactory {
   /// Get all databases for testing
   pub fn get_instance(
     &self,
-  ) -> &std::collections::HashMap<
-    String,
-    std::sync::Arc<tokio::sync::RwLock<dyn IAsyncDatabase>>,
-  > {
+  ) -> &std::collections::HashMap<String, std::sync::Arc<dyn IAsyncDatabase>>
+  {
     &self.databases
   }
 }
@@ -0,0 +1,202 @@
+use crate::db::mmav_db::mmav::MMAV;
+use crate::db::Change;
+use crate::errors::Error;
+
+/// Directory of the change log in the database path
+pub const CHANGE_LOG_ID: &str = ".changes";
+
+/// Tag of a change entry of a posted record
+const RECORD: u8 = 0;
+
+/// Tag of a change entry of posted metadata
+const META: u8 = 1;
+
+/// Length of the tag, record index and sensor id length of an entry
+const HEADER_LEN: usize = 13;
+
+/// Change Log
+///
+/// An append-only log of every write to the database in commit order,
+/// where the index of an entry plus one is its log sequence number.
+/// Entries of records only hold the sensor id and record index, the
+/// record itself is read from the sensor. Entries of metadata hold the
+/// metadata, as it is overwritten by later posts.
+///
+/// Each entry is encoded as a tag byte, the record index as a
+/// little-endian `u64`, the length of the sensor id as a little-endian
+/// `u32`, the sensor id and the metadata.
+///
+/// ## Examples
+/// ```ignore
+/// let mut log = ChangeLog::new(".db");
+///
+/// log.push_records("test-0", 0, 2);
+/// assert_eq!(log.len(), 2);
+/// ```
+pub struct ChangeLog {
+  mmav: MMAV,
+}
+impl ChangeLog {
+  /// Change Log Constructor
+  ///
+  /// Loads the change log of the database in `db_path`
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let log = ChangeLog::new(".db");
+  /// ```
+  pub fn new(db_path: &str) -> Self {
+    Self { mmav: MMAV::new(&format!("{db_path}/{CHANGE_LOG_ID}")) }
+  }
+
+  /// Log `count` records posted to the sensor with `id`, starting at
+  /// record index `rec_id`
+  ///
+  /// Returns the log sequence number of the first entry
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let mut log = ChangeLog::new(".db");
+  ///
+  /// assert_eq!(log.push_records("test-0", 0, 2).unwrap(), 1);
+  /// ```
+  pub fn push_records(
+    &mut self,
+    id: &str,
+    rec_id: usize,
+    count: usize,
+  ) -> Result<usize, Error> {
+    let entries = (rec_id..rec_id + count)
+      .map(|x| ChangeLog::encode(RECORD, x, id, &[]))
+      .collect::<Vec<_>>();
+
+    self
+      .mmav
+      .push_batch(&entries.iter().map(|x| x.as_slice()).collect::<Vec<_>>())
+      .map(|x| x + 1)
+  }
+
+  /// Log `value` posted as the metadata of the sensor with `id`
+  ///
+  /// Returns the log sequence number of the entry
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let mut log = ChangeLog::new(".db");
+  ///
+  /// assert_eq!(log.push_meta("test-0", b"{}").unwrap(), 1);
+  /// ```
+  pub fn push_meta(&mut self, id: &str, value: &[u8]) -> Result<usize, Error> {
+    self
+      .mmav
+      .push_batch(&[&ChangeLog::encode(META, 0, id, value)])
+      .map(|x| x + 1)
+  }
+
+  /// Check that `value` can be logged as the metadata of the sensor
+  /// with `id`
+  ///
+  /// ## Errors
+  /// `Error::FileFull` if the entry does not fit in the log
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let log = ChangeLog::new(".db");
+  ///
+  /// log.check_meta("test-0", b"{}")?;
+  /// ```
+  pub fn check_meta(&self, id: &str, value: &[u8]) -> Result<(), Error> {
+    if HEADER_LEN + id.len() + value.len() > self.mmav.capacity() {
+      return Err(Error::FileFull);
+    }
+
+    Ok(())
+  }
+
+  /// Get at most `limit` changes after log sequence number `lsn`
+  ///
+  /// The `value` of changes of records is left empty.
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let mut log = ChangeLog::new(".db");
+  ///
+  /// log.push_records("test-0", 0, 2);
+  /// assert_eq!(log.since(1, 10)[0].lsn, 2);
+  /// ```
+  pub fn since(&self, lsn: usize, limit: usize) -> Vec<Change> {
+    let end = self.len().min(lsn.saturating_add(limit));
+
+    (lsn..end)
+      .filter_map(|x| ChangeLog::decode(x + 1, &self.mmav.get(x)))
+      .collect()
+  }
+
+  /// Get the number of entries, which is the log sequence number of
+  /// the last change
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let log = ChangeLog::new(".db");
+  ///
+  /// assert_eq!(log.len(), 0);
+  /// ```
+  pub fn len(&self) -> usize {
+    self.mmav.len()
+  }
+
+  /// Truncate the log to `len` entries
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let mut log = ChangeLog::new(".db");
+  ///
+  /// log.push_records("test-0", 0, 2);
+  /// log.truncate(1);
+  /// assert_eq!(log.len(), 1);
+  /// ```
+  pub fn truncate(&mut self, len: usize) {
+    self.mmav.truncate(len);
+  }
+
+  /// Flush outstanding entries to disk
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let log = ChangeLog::new(".db");
+  ///
+  /// log.flush().unwrap();
+  /// ```
+  pub fn flush(&self) -> Result<(), Error> {
+    self.mmav.flush()
+  }
+
+  fn encode(tag: u8, rec_id: usize, id: &str, value: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(HEADER_LEN + id.len() + value.len());
+
+    result.push(tag);
+    result.extend_from_slice(&(rec_id as u64).to_le_bytes());
+    result.extend_from_slice(&(id.len() as u32).to_le_bytes());
+    result.extend_from_slice(id.as_bytes());
+    result.extend_from_slice(value);
+
+    result
+  }
+
+  fn decode(lsn: usize, entry: &[u8]) -> Option<Change> {
+    let tag = *entry.first()?;
+    let rec_id = u64::from_le_bytes(entry.get(1..9)?.try_into().ok()?);
+    let id_len = u32::from_le_bytes(entry.get(9..13)?.try_into().ok()?);
+    let id = entry.get(HEADER_LEN..HEADER_LEN + id_len as usize)?;
+
+    Some(Change {
+      lsn,
+      id: String::from_utf8_lossy(id).into_owned(),
+      rec_id: (tag == RECORD).then_some(rec_id as usize),
+      value: match tag {
+        META => entry[HEADER_LEN + id.len()..].to_vec(),
+        _ => Default::default(),
+      },
+    })
+  }
+}
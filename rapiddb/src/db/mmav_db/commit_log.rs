@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::db::mmav_db::mmav::MMAV;
+use crate::errors::Error;
+
+/// Commit Log
+///
+/// Records the length of every sensor in a write batch, and the sensors
+/// the batch creates, before the batch is written, and is removed once
+/// the batch is flushed to disk. If the log still exists when the
+/// database is loaded, the batch did not complete, and the sensors are
+/// rolled back to the recorded lengths, and the created sensors removed.
+///
+/// ## Examples
+/// ```ignore
+/// let log = CommitLog::new(".db/.commit");
+///
+/// log.begin(&[("test-0".to_owned(), 0)].into(), &[])?;
+/// // write the batch
+/// log.end()?;
+/// ```
+pub struct CommitLog {
+  file_name: String,
+}
+impl CommitLog {
+  /// Commit Log Constructor
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let log = CommitLog::new(".db/.commit");
+  /// ```
+  pub fn new(file_name: &str) -> Self {
+    Self { file_name: file_name.to_owned() }
+  }
+
+  /// Durably record the `lens` of the sensors of a batch, and the ids of
+  /// the sensors it `created`, before it is written
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let log = CommitLog::new(".db/.commit");
+  ///
+  /// log.begin(&[("test-0".to_owned(), 0)].into(), &["test-0".to_owned()])?;
+  /// ```
+  pub fn begin(
+    &self,
+    lens: &HashMap<String, usize>,
+    created: &[String],
+  ) -> Result<(), Error> {
+    let temp_file_name = format!("{}.tmp", self.file_name);
+
+    let mut file = std::fs::File::create(&temp_file_name)?;
+    file.write_all(
+      serde_json::json!({ "lens": lens, "created": created })
+        .to_string()
+        .as_bytes(),
+    )?;
+    file.sync_all()?;
+
+    std::fs::rename(&temp_file_name, &self.file_name)?;
+
+    Ok(())
+  }
+
+  /// Mark the batch as committed, after it is flushed to disk
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let log = CommitLog::new(".db/.commit");
+  ///
+  /// log.end()?;
+  /// ```
+  pub fn end(&self) -> Result<(), Error> {
+    match std::fs::remove_file(&self.file_name) {
+      Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+        Err(error.into())
+      }
+      _ => Ok(()),
+    }
+  }
+
+  /// Get the lengths and the created sensors recorded by a batch that
+  /// did not complete
+  ///
+  /// Fails if the log cannot be read or parsed, and keeps the log, as
+  /// the batch cannot be rolled back without it.
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let log = CommitLog::new(".db/.commit");
+  ///
+  /// if let Some((lens, created)) = log.pending()? {
+  ///   // roll back to lens and remove created
+  ///   log.end()?;
+  /// }
+  /// ```
+  #[allow(clippy::type_complexity)]
+  pub fn pending(
+    &self,
+  ) -> Result<Option<(HashMap<String, usize>, Vec<String>)>, Error> {
+    std::fs::remove_file(format!("{}.tmp", self.file_name)).unwrap_or_default();
+
+    let data = match std::fs::read(&self.file_name) {
+      Ok(x) => x,
+      Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+        return Ok(None)
+      }
+      Err(error) => return Err(error.into()),
+    };
+
+    let mut value = serde_json::from_slice::<serde_json::Value>(&data)
+      .map_err(std::io::Error::from)?;
+    let lens = serde_json::from_value(value["lens"].take())
+      .map_err(std::io::Error::from)?;
+    let created = serde_json::from_value(value["created"].take())
+      .map_err(std::io::Error::from)?;
+
+    Ok(Some((lens, created)))
+  }
+
+  /// Roll back the sensors in `db_path` of a batch that did not
+  /// complete
+  ///
+  /// A created sensor is removed, unless metadata was posted to it
+  /// while the batch was written. The log is only removed once the
+  /// sensors are rolled back.
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let log = CommitLog::new(".db/.commit");
+  ///
+  /// log.recover(".db")?;
+  /// ```
+  pub fn recover(&self, db_path: &str) -> Result<(), Error> {
+    let Some((lens, created)) = self.pending()? else {
+      return Ok(());
+    };
+
+    for (id, len) in lens {
+      let mut mmav = MMAV::new(&format!("{db_path}/{id}"));
+      mmav.truncate(len);
+      mmav.flush()?;
+    }
+
+    for id in created {
+      let has_meta = std::fs::metadata(format!("{db_path}/{id}/meta"))
+        .map(|x| x.len() > 0)
+        .unwrap_or_default();
+      if !has_meta {
+        remove_sensor_dir(&format!("{db_path}/{id}"))?;
+      }
+    }
+
+    self.end()
+  }
+}
+
+/// Remove the directory of a sensor, if it exists
+pub fn remove_sensor_dir(path: &str) -> Result<(), Error> {
+  match std::fs::remove_dir_all(path) {
+    Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+      Err(error.into())
+    }
+    _ => Ok(()),
+  }
+}
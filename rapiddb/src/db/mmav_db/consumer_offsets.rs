@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::errors::Error;
+
+/// Consumer Offsets
+///
+/// The committed offsets of the named consumers of one sensor, where
+/// the offset of a consumer is the index of the next record it has not
+/// processed yet. Offsets are stored as a JSON object in `file_name`,
+/// which is replaced atomically on every commit.
+///
+/// ## Examples
+/// ```ignore
+/// let mut offsets = ConsumerOffsets::new(".db/test-0/offsets");
+///
+/// offsets.commit("consumer-0", 2)?;
+/// assert_eq!(offsets.get("consumer-0"), 2);
+/// ```
+pub struct ConsumerOffsets {
+  file_name: String,
+  offsets: HashMap<String, usize>,
+}
+impl ConsumerOffsets {
+  /// Consumer Offsets Constructor
+  ///
+  /// Loads the committed offsets from `file_name`, if it exists
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let offsets = ConsumerOffsets::new(".db/test-0/offsets");
+  /// ```
+  pub fn new(file_name: &str) -> Self {
+    Self {
+      file_name: file_name.to_owned(),
+      offsets: std::fs::read(file_name)
+        .ok()
+        .and_then(|x| serde_json::from_slice(&x).ok())
+        .unwrap_or_default(),
+    }
+  }
+
+  /// Get the committed offset of `consumer`, zero if it never committed
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let offsets = ConsumerOffsets::new(".db/test-0/offsets");
+  ///
+  /// assert_eq!(offsets.get("consumer-0"), 0);
+  /// ```
+  pub fn get(&self, consumer: &str) -> usize {
+    self.offsets.get(consumer).copied().unwrap_or_default()
+  }
+
+  /// Durably commit `offset` for `consumer`
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let mut offsets = ConsumerOffsets::new(".db/test-0/offsets");
+  ///
+  /// offsets.commit("consumer-0", 2)?;
+  /// ```
+  pub fn commit(&mut self, consumer: &str, offset: usize) -> Result<(), Error> {
+    let mut offsets = self.offsets.clone();
+    offsets.insert(consumer.to_owned(), offset);
+
+    let temp_file_name = format!("{}.tmp", self.file_name);
+
+    let mut file = std::fs::File::create(&temp_file_name)?;
+    file.write_all(serde_json::json!(offsets).to_string().as_bytes())?;
+    file.sync_all()?;
+
+    std::fs::rename(&temp_file_name, &self.file_name)?;
+
+    self.offsets = offsets;
+
+    Ok(())
+  }
+}
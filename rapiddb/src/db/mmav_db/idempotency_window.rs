@@ -0,0 +1,131 @@
+use std::io::{BufRead, Write};
+
+/// Idempotency Window
+///
+/// A bounded window of the most recent idempotency keys posted to a
+/// sensor, with the record index each key was assigned. The window is
+/// persisted as an append-only log of JSON lines, which is compacted
+/// once it grows to twice the window size.
+///
+/// ## Examples
+/// ```ignore
+/// let mut window = IdempotencyWindow::new(".db/test-0/idempotency", 1024);
+///
+/// window.insert("key-0", 0);
+/// assert_eq!(window.get("key-0"), Some(0));
+/// ```
+pub struct IdempotencyWindow {
+  file_name: String,
+  size: usize,
+  lines: usize,
+  keys: std::collections::VecDeque<String>,
+  rec_ids: std::collections::HashMap<String, usize>,
+}
+impl IdempotencyWindow {
+  /// Idempotency Window Constructor
+  ///
+  /// Loads the window persisted at `file_name`, keeping the last `size`
+  /// keys.
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let window = IdempotencyWindow::new(".db/test-0/idempotency", 1024);
+  /// ```
+  pub fn new(file_name: &str, size: usize) -> Self {
+    let mut result = Self {
+      file_name: file_name.to_owned(),
+      size,
+      lines: Default::default(),
+      keys: Default::default(),
+      rec_ids: Default::default(),
+    };
+
+    if let Ok(file) = std::fs::File::open(file_name) {
+      for line in std::io::BufReader::new(file).lines() {
+        let Ok(line) = line else {
+          break;
+        };
+
+        if let Ok((rec_id, key)) =
+          serde_json::from_str::<(usize, String)>(&line)
+        {
+          result.remember(key, rec_id);
+        }
+
+        result.lines += 1;
+      }
+    }
+
+    result
+  }
+
+  /// Get the record index assigned to `key`, if it is in the window
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let window = IdempotencyWindow::new(".db/test-0/idempotency", 1024);
+  /// window.get("key-0");
+  /// ```
+  pub fn get(&self, key: &str) -> Option<usize> {
+    self.rec_ids.get(key).copied()
+  }
+
+  /// Insert `key` with the assigned `rec_id` into the window and
+  /// persist it
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let mut window = IdempotencyWindow::new(".db/test-0/idempotency", 1024);
+  ///
+  /// window.insert("key-0", 0);
+  /// assert_eq!(window.get("key-0"), Some(0));
+  /// ```
+  pub fn insert(&mut self, key: &str, rec_id: usize) {
+    self.remember(key.to_owned(), rec_id);
+
+    if self.lines + 1 >= self.size * 2 {
+      self.compact();
+      return;
+    }
+
+    std::fs::OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(&self.file_name)
+      .and_then(|mut file| {
+        writeln!(file, "{}", serde_json::json!([rec_id, key]))
+      })
+      .unwrap_or_default();
+
+    self.lines += 1;
+  }
+
+  /// Add `key` to the in-memory window, evicting the oldest key if the
+  /// window is full
+  fn remember(&mut self, key: String, rec_id: usize) {
+    if self.rec_ids.insert(key.clone(), rec_id).is_none() {
+      self.keys.push_back(key);
+    }
+
+    while self.keys.len() > self.size {
+      if let Some(x) = self.keys.pop_front() {
+        self.rec_ids.remove(&x);
+      }
+    }
+  }
+
+  /// Rewrite the persisted log with only the keys in the window
+  fn compact(&mut self) {
+    let mut data = String::new();
+    for key in &self.keys {
+      data += &format!("{}\n", serde_json::json!([self.rec_ids[key], key]));
+    }
+
+    let temp_file_name = format!("{}.tmp", self.file_name);
+    std::fs::write(&temp_file_name, data)
+      .and_then(|_| std::fs::rename(&temp_file_name, &self.file_name))
+      .unwrap_or_default();
+
+    self.lines = self.keys.len();
+  }
+}
@@ -1,4 +1,5 @@
 use crate::db::mmav_db::mmav_unit::MMAVUnit;
+use crate::db::RecordRef;
 use crate::errors::Error;
 
 /// Memory Mapped Append-only Vector
@@ -13,7 +14,7 @@ use crate::errors::Error;
 /// let mut mmav = MMAV::new(".db/test-0");
 ///
 /// let data = b"{\"key\":\"value\"}";
-/// mmav.push(data).unwrap_or_default();
+/// mmav.push_batch(&[data]);
 /// assert_eq!(mmav.last(), data);
 /// ```
 #[allow(clippy::upper_case_acronyms)]
@@ -23,7 +24,7 @@ pub struct MMAV {
   mmav_size: usize,
   indices: Vec<usize>,
   mmav_data_start_index: usize,
-  unit_map: std::collections::HashMap<usize, MMAVUnit>,
+  unit_map: std::sync::RwLock<std::collections::HashMap<usize, MMAVUnit>>,
 }
 impl MMAV {
   /// Memory Mapped Append-only Vector Constructor
@@ -35,7 +36,7 @@ impl MMAV {
   /// let mut mmav = MMAV::new(".db/test-0");
   ///
   /// let data = b"{\"key\":\"value\"}";
-  /// mmav.push(data).unwrap_or_default();
+  /// mmav.push_batch(&[data]);
   /// assert_eq!(mmav.last(), data);
   /// ```
   pub fn new(id: &str) -> Self {
@@ -93,7 +94,7 @@ impl MMAV {
 
     Self {
       index,
-      unit_map,
+      unit_map: std::sync::RwLock::new(unit_map),
       indices,
       mmav_size,
       mmav_data_start_index,
@@ -161,15 +162,25 @@ impl MMAV {
 
   /// Load unit that contains `index`
   ///
+  /// The unit cache is behind a lock, so units can be loaded through a
+  /// shared reference, and only takes the write lock if the unit is not
+  /// already loaded.
+  ///
   /// ## Examples
   /// ```ignore
   /// self.load(0);
   /// ```
-  fn load(&mut self, index: usize) -> usize {
+  fn load(&self, index: usize) -> usize {
+    let closest = MMAV::bisect_left(index, &self.indices);
+
+    if let Some(x) = self.unit_map.read().unwrap().get(&closest) {
+      return x.len();
+    }
+
     MMAV::load_unchecked(
       &self.id,
-      &mut self.unit_map,
-      MMAV::bisect_left(index, &self.indices),
+      &mut self.unit_map.write().unwrap(),
+      closest,
       self.mmav_size,
       self.mmav_data_start_index,
     )
@@ -186,15 +197,17 @@ impl MMAV {
       panic!("index out of range");
     }
 
+    let unit_map = self.unit_map.get_mut().unwrap();
+
     let mut to_remove = vec![];
-    for key in self.unit_map.keys() {
+    for key in unit_map.keys() {
       if !self.indices[self.indices.len() - keep..].contains(key) {
         to_remove.push(*key);
       }
     }
 
     for key in to_remove {
-      self.unit_map.remove(&key);
+      unit_map.remove(&key);
     }
   }
 
@@ -207,13 +220,13 @@ impl MMAV {
   fn expand(&mut self) {
     self.unload(1);
 
-    self.index += self.unit_map[&self.index].len();
+    self.index += self.unit_map.get_mut().unwrap()[&self.index].len();
     self.indices.push(self.index);
 
     if let Some(x) = self.indices.last() {
       MMAV::load_unchecked(
         &self.id,
-        &mut self.unit_map,
+        self.unit_map.get_mut().unwrap(),
         *x,
         self.mmav_size,
         self.mmav_data_start_index,
@@ -221,65 +234,179 @@ impl MMAV {
     }
   }
 
-  /// Push `value` to vector
+  /// Get the size of the largest value, which fits in an empty unit
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let mmav = MMAV::new(".db/test-0");
+  ///
+  /// assert!(mmav.capacity() > 0);
+  /// ```
+  pub fn capacity(&self) -> usize {
+    self.mmav_size - self.mmav_data_start_index
+  }
+
+  /// Push `values` to vector, in order
+  ///
+  /// Each unit is written once per batch, expanding the vector when a
+  /// unit is full.
+  ///
+  /// Returns the index of the first pushed value
+  ///
+  /// ## Errors
+  /// `Error::FileFull` if a value does not fit in an empty unit, in which
+  /// case none of `values` are pushed
   ///
   /// ## Examples
   /// ```ignore
   /// let mut mmav = MMAV::new(".db/test-0");
   ///
   /// let data = b"{\"key\":\"value\"}";
-  /// mmav.push(data).unwrap_or_default();
-  /// assert_eq!(mmav.last(), data);
+  /// assert_eq!(mmav.push_batch(&[data, data]).unwrap(), 0);
+  /// assert_eq!(mmav.len(), 2);
+  /// ```
+  pub fn push_batch(&mut self, values: &[&[u8]]) -> Result<usize, Error> {
+    let capacity = self.capacity();
+    if values.iter().any(|x| x.len() > capacity) {
+      return Err(Error::FileFull);
+    }
+
+    let index = self.len();
+    let mut values = values;
+
+    while !values.is_empty() {
+      let unit = self.unit_map.get_mut().unwrap().get_mut(&self.index).unwrap();
+
+      let count = unit.push_batch(values);
+      if count == 0 && unit.len() == 0 {
+        return Err(Error::FileFull);
+      }
+
+      values = &values[count..];
+
+      if !values.is_empty() {
+        self.expand();
+      }
+    }
+
+    Ok(index)
+  }
+
+  /// Truncate vector to `len` items
+  ///
+  /// Removes the units after `len` from disk, `RecordRef` handles to
+  /// their records keep the mappings of the removed files.
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let mut mmav = MMAV::new(".db/test-0");
+  ///
+  /// let data = b"{\"key\":\"value\"}";
+  /// mmav.push_batch(&[data, data]);
+  /// mmav.truncate(1);
+  /// assert_eq!(mmav.len(), 1);
   /// ```
-  pub fn push(&mut self, value: &[u8]) {
-    self.unit_map.get_mut(&self.index).unwrap().push(value).unwrap_or_else(
-      |error| match error {
-        Error::ArrayFull => {
-          self.expand();
-          self.push(value);
-        }
-        Error::FileFull => {
-          self.expand();
-          self.push(value);
-        }
-        _ => (),
-      },
+  pub fn truncate(&mut self, len: usize) {
+    if len >= self.len() {
+      return;
+    }
+
+    let unit_map = self.unit_map.get_mut().unwrap();
+
+    while self.indices.len() > 1 && self.indices[self.indices.len() - 1] >= len
+    {
+      if let Some(x) = self.indices.pop() {
+        unit_map.remove(&x);
+        std::fs::remove_file(format!("{}/{x}", self.id)).unwrap_or_default();
+      }
+    }
+
+    self.index = self.indices[self.indices.len() - 1];
+
+    MMAV::load_unchecked(
+      &self.id,
+      unit_map,
+      self.index,
+      self.mmav_size,
+      self.mmav_data_start_index,
     );
+
+    if let Some(x) = unit_map.get_mut(&self.index) {
+      x.truncate(len - self.index);
+    }
   }
 
-  /// Get `value` at `index` immutably
+  /// Flush outstanding changes of the loaded units to disk
   ///
   /// ## Examples
   /// ```ignore
-  /// self._get(0)
+  /// let mut mmav = MMAV::new(".db/test-0");
+  ///
+  /// mmav.push_batch(&[b"{\"key\":\"value\"}"]);
+  /// mmav.flush().unwrap();
   /// ```
-  fn _get(&self, index: usize) -> Vec<u8> {
+  pub fn flush(&self) -> Result<(), Error> {
+    for x in self.unit_map.read().unwrap().values() {
+      x.flush()?;
+    }
+
+    Ok(())
+  }
+
+  /// Get `value` at `index` immutably, without copying it out of the
+  /// memory map
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// self._get_ref(0)
+  /// ```
+  fn _get_ref(&self, index: usize) -> Option<RecordRef> {
     if self.len() == 0 || index > self.len() - 1 {
-      return Default::default();
+      return None;
     }
 
     let closest = MMAV::bisect_left(index, &self.indices);
 
-    self.unit_map[&closest].get(index - closest).unwrap_or_default()
+    self
+      .unit_map
+      .read()
+      .unwrap()
+      .get(&closest)
+      .and_then(|x| x.get_ref(index - closest).ok())
   }
 
   /// Get `value` at `index`
   ///
-  /// May load data from disk, if it is not in-memory,
-  /// as such it is mutable, even though a get normaly is immutable.
+  /// May load data from disk, if it is not in-memory.
   ///
   /// ## Examples
   /// ```ignore
   /// let mut mmav = MMAV::new(".db/test-0");
   ///
   /// let data = b"{\"key\":\"value\"}";
-  /// mmav.push(data).unwrap_or_default();
+  /// mmav.push_batch(&[data]);
   /// assert_eq!(mmav.get(0), data);
   /// ```
-  pub fn get(&mut self, index: usize) -> Vec<u8> {
+  pub fn get(&self, index: usize) -> Vec<u8> {
+    self.get_ref(index).map(|x| x.to_vec()).unwrap_or_default()
+  }
+
+  /// Get `value` at `index`, without copying it out of the memory map
+  ///
+  /// May load data from disk, if it is not in-memory.
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let mut mmav = MMAV::new(".db/test-0");
+  ///
+  /// let data = b"{\"key\":\"value\"}";
+  /// mmav.push_batch(&[data]);
+  /// assert_eq!(&*mmav.get_ref(0).unwrap(), data);
+  /// ```
+  pub fn get_ref(&self, index: usize) -> Option<RecordRef> {
     self.load(index);
 
-    self._get(index)
+    self._get_ref(index)
   }
 
   /// Get last item in vector
@@ -289,14 +416,14 @@ impl MMAV {
   /// let mut mmav = MMAV::new(".db/test-0");
   ///
   /// let data = b"{\"key\":\"value\"}";
-  /// mmav.push(data).unwrap_or_default();
+  /// mmav.push_batch(&[data]);
   /// assert_eq!(mmav.last(), data);
   /// ```
   pub fn last(&self) -> Vec<u8> {
     self
       .indices
       .last()
-      .map(|x| self.unit_map.get(x).map(|y| y.last()))
+      .map(|x| self.unit_map.read().unwrap().get(x).map(|y| y.last()))
       .unwrap_or_default()
       .unwrap_or_default()
   }
@@ -308,103 +435,184 @@ impl MMAV {
   /// let mut mmav = MMAV::new(".db/test-0");
   ///
   /// let data = b"{\"key\":\"value\"}";
-  /// mmav.push(data).unwrap_or_default();
+  /// mmav.push_batch(&[data]);
   /// assert_eq!(mmav.len(), 1);
   /// ```
   pub fn len(&self) -> usize {
     return self
       .indices
       .last()
-      .map(|x| self.index + self.unit_map[x].len())
+      .map(|x| self.index + self.unit_map.read().unwrap()[x].len())
       .unwrap_or_default();
   }
 
-  /// Get range from `start` to `end` immutably
+  /// Iterate over the items in `range`, clamped to the length of the
+  /// vector
+  ///
+  /// Units are loaded on demand, as the iterator reaches them, and items
+  /// are not copied out of the memory map.
   ///
   /// ## Examples
   /// ```ignore
   /// let mut mmav = MMAV::new(".db/test-0");
   ///
-  /// let data = b"{\"key\":\"value\"}";
-  /// mmav.push(data).unwrap_or_default();
-  /// mmav.push(data).unwrap_or_default();
-  /// assert_eq!(mmav._range(0, 1), vec![data, data]);
+  /// mmav.push_batch(&[b"0", b"1", b"2"]);
+  /// assert_eq!(mmav.iter_range(1..).count(), 2);
+  /// assert_eq!(&*mmav.iter_range(..2).rev().next().unwrap(), b"1");
   /// ```
-  fn _range(&self, start: usize, end: usize) -> Vec<Vec<u8>> {
-    let mut result = vec![];
-
-    if end > self.len() {
-      return result;
+  pub fn iter_range(
+    &self,
+    range: impl std::ops::RangeBounds<usize>,
+  ) -> Iter<'_> {
+    let len = self.len();
+
+    let front = match range.start_bound() {
+      std::ops::Bound::Included(x) => *x,
+      std::ops::Bound::Excluded(x) => x.saturating_add(1),
+      std::ops::Bound::Unbounded => 0,
     }
-
-    for i in start..=end {
-      let item = self._get(i);
-
-      if !item.is_empty() {
-        result.push(item);
-      }
+    .min(len);
+    let back = match range.end_bound() {
+      std::ops::Bound::Included(x) => x.saturating_add(1),
+      std::ops::Bound::Excluded(x) => *x,
+      std::ops::Bound::Unbounded => len,
     }
+    .clamp(front, len);
 
-    result
+    Iter { mmav: self, front, back }
   }
 
-  /// Get range from `start` to `end`
+  /// Get range from `start` to `end`, clamped to the length of the vector
   ///
-  /// May load data from disk, if it is not in-memory,
-  /// as such it is mutable, even though a range scan normaly is
-  /// immutable.
+  /// May load data from disk, if it is not in-memory.
   ///
   /// ## Examples
   /// ```ignore
   /// let mut mmav = MMAV::new(".db/test-0");
   ///
   /// let data = b"{\"key\":\"value\"}";
-  /// mmav.push(data).unwrap_or_default();
-  /// mmav.push(data).unwrap_or_default();
+  /// mmav.push_batch(&[data]);
+  /// mmav.push_batch(&[data]);
   /// assert_eq!(mmav.range(0, 1), vec![data, data]);
   /// ```
-  pub fn range(&mut self, start: usize, end: usize) -> Vec<Vec<u8>> {
-    let start_size = self.load(start);
-    let end_size = self.load(end);
-
-    let size = (|| {
-      if start_size != end_size {
-        return start_size + end_size;
-      }
-      start_size
-    })();
-
-    if std::cmp::max(start, end) - std::cmp::min(start, end) > size {
-      return self._range(start, start + size - 1);
-    }
-
-    self._range(start, end)
+  pub fn range(&self, start: usize, end: usize) -> Vec<Vec<u8>> {
+    self.iter_range(start..=end).map(|x| x.to_vec()).collect()
   }
 
   /// Get last `limit` number of items from vector
   ///
-  /// May load data from disk, if it is not in-memory,
-  /// as such it is mutable, even though a get last item normaly is
-  /// immutable.
+  /// May load data from disk, if it is not in-memory.
   ///
   /// ## Examples
   /// ```ignore
   /// let mut mmav = MMAV::new(".db/test-0");
   ///
   /// let data = b"{\"key\":\"value\"}";
-  /// mmav.push(data).unwrap_or_default();
-  /// mmav.push(data).unwrap_or_default();
+  /// mmav.push_batch(&[data]);
+  /// mmav.push_batch(&[data]);
   /// assert_eq!(mmav.last_limit(2), vec![data, data]);
   /// ```
-  pub fn last_limit(&mut self, limit: usize) -> Vec<Vec<u8>> {
-    if self.len() == 0 {
-      return Default::default();
+  pub fn last_limit(&self, limit: usize) -> Vec<Vec<u8>> {
+    self
+      .iter_range(self.len().saturating_sub(limit)..)
+      .map(|x| x.to_vec())
+      .collect()
+  }
+}
+
+/// Iterator over the items of a `MMAV`, from the front or the back
+///
+/// Iteration ends at the first item which can not be read, such as an
+/// item of a unit which fails to load, so the items yielded from either
+/// end are always consecutive.
+///
+/// ## Examples
+/// ```ignore
+/// let mut mmav = MMAV::new(".db/test-0");
+///
+/// mmav.push_batch(&[b"0", b"1"]);
+/// assert_eq!(&*mmav.iter_range(..).rev().next().unwrap(), b"1");
+/// ```
+pub struct Iter<'a> {
+  mmav: &'a MMAV,
+  front: usize,
+  back: usize,
+}
+impl Iterator for Iter<'_> {
+  type Item = RecordRef;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.front >= self.back {
+      return None;
     }
 
-    if limit > self.len() {
-      return self.range(0, self.len() - 1);
+    let result = self.mmav.get_ref(self.front);
+    match result {
+      Some(_) => self.front += 1,
+      None => self.back = self.front,
     }
 
-    self.range(self.len() - limit, self.len() - 1)
+    result
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (0, Some(self.back - self.front))
   }
 }
+impl DoubleEndedIterator for Iter<'_> {
+  fn next_back(&mut self) -> Option<Self::Item> {
+    if self.front >= self.back {
+      return None;
+    }
+
+    let result = self.mmav.get_ref(self.back - 1);
+    match result {
+      Some(_) => self.back -= 1,
+      None => self.front = self.back,
+    }
+
+    result
+  }
+}
+
+#[test]
+fn test_iter_range() {
+  let id = ".temp/test/mmav/test_iter_range";
+  std::fs::create_dir_all(id).unwrap();
+
+  let values =
+    (0..20005).map(|x| x.to_string().into_bytes()).collect::<Vec<_>>();
+  let mut mmav = MMAV::new(id);
+  mmav.push_batch(&values.iter().map(|x| &x[..]).collect::<Vec<_>>()).unwrap();
+  drop(mmav);
+
+  // only the last two units are loaded, the first is loaded on demand
+  let mmav = MMAV::new(id);
+  assert_eq!(mmav.len(), 20005);
+
+  let range = mmav.iter_range(9995..10005).collect::<Vec<_>>();
+  assert_eq!(
+    range,
+    values[9995..10005]
+      .iter()
+      .cloned()
+      .map(RecordRef::from)
+      .collect::<Vec<_>>()
+  );
+
+  let range = mmav.iter_range(..).rev().collect::<Vec<_>>();
+  assert_eq!(range.len(), 20005);
+  assert_eq!(&*range[0], b"20004");
+  assert_eq!(&*range[20004], b"0");
+
+  let mut iter = mmav.iter_range(9999..=10000);
+  assert_eq!(iter.next_back().as_deref(), Some(&b"10000"[..]));
+  assert_eq!(iter.next().as_deref(), Some(&b"9999"[..]));
+  assert_eq!(iter.next(), None);
+  assert_eq!(iter.next_back(), None);
+
+  assert_eq!(mmav.iter_range(20000..usize::MAX).count(), 5);
+  assert_eq!(mmav.iter_range(30000..).count(), 0);
+
+  std::fs::remove_dir_all(id).unwrap_or_default();
+}
@@ -1,10 +1,35 @@
 use std::collections::HashMap;
 use std::io::{Read, Write};
+use std::sync::{Arc, Mutex, RwLock};
 
+use crate::db::mmav_db::change_log::{ChangeLog, CHANGE_LOG_ID};
+use crate::db::mmav_db::commit_log::{remove_sensor_dir, CommitLog};
+use crate::db::mmav_db::consumer_offsets::ConsumerOffsets;
+use crate::db::mmav_db::group_by_sensor;
+use crate::db::mmav_db::idempotency_window::IdempotencyWindow;
 use crate::db::mmav_db::mmav::MMAV;
+use crate::db::validate_id;
+use crate::db::BlockingPool;
+use crate::db::Change;
+use crate::db::Notification;
+use crate::db::Notifier;
+use crate::db::RecordRef;
+use crate::db::SensorSchema;
+use crate::db::Snapshot;
+use crate::db::Subscription;
+use crate::db::SubscriptionFilter;
+use crate::db::WriteBatch;
+use crate::errors::Error;
 use crate::traits::IAsyncDatabase;
+use crate::traits::RecordStream;
 use crate::types::AggregateFn;
 
+/// Number of recent idempotency keys remembered per sensor
+const IDEMPOTENCY_WINDOW_SIZE: usize = 1024;
+
+/// Number of records read at once by `stream_range`
+const CHUNK_SIZE: usize = 256;
+
 /// Memory Mapped Append-only Vector Database
 ///
 /// This is the database abstraction, it futures a further
@@ -12,46 +37,45 @@ use crate::types::AggregateFn;
 /// Each sensor is stored in a MMAV.
 /// And enables meta data and index data to be stored for each sensor.
 ///
+/// Sensors are kept in a sharded concurrent map, and each MMAV has its
+/// own lock, so reads and writes to different sensors proceed
+/// concurrently.
+///
+/// Operations that touch the disk run on a bounded `BlockingPool`, so
+/// file creation, mmap and page faults do not stall the async executor.
+/// Operations on in-memory state, such as meta data and aggregates, run
+/// directly on the caller.
+///
 /// ## Examples
 /// ```no_run
-/// use crate::rapiddb::traits::IDatabase;
+/// use crate::rapiddb::traits::IAsyncDatabase;
 ///
-/// let db = std::sync::Arc::new(
-///   std::sync::RwLock::new(
-///     rapiddb::db::MMAVDatabase::new()
-///   )
-/// );
+/// # tokio_test::block_on(async {
+/// let db = std::sync::Arc::new(rapiddb::db::MMAVAsyncDatabase::new());
 ///
 /// let value = b"{\"key\": \"value\"}";
-/// db.write().unwrap().post("test-0", value);
-/// assert_eq!(db.write().unwrap().get_latest("test-0"), value);
+/// db.post("test-0", value).await.unwrap();
+/// assert_eq!(db.get_latest("test-0").await, value);
+/// # })
 /// ```
 pub struct MMAVAsyncDatabase {
-  db_path: String,
-  sensors: std::collections::HashMap<String, MMAV>,
-  meta: std::collections::HashMap<String, Vec<u8>>,
-  aggregates: std::collections::HashMap<
-    String,
-    std::sync::Arc<std::sync::Mutex<Vec<u8>>>,
-  >,
-  aggregates_fn: HashMap<String, AggregateFn>,
+  state: Arc<State>,
+  pool: BlockingPool,
 }
 impl MMAVAsyncDatabase {
   /// Memory Mapped Append-only Vector Database Constructor
   ///
   /// ## Examples
   /// ```no_run
-  /// use crate::rapiddb::traits::IDatabase;
+  /// use crate::rapiddb::traits::IAsyncDatabase;
   ///
-  /// let db = std::sync::Arc::new(
-  ///   std::sync::RwLock::new(
-  ///     rapiddb::db::MMAVDatabase::new()
-  ///   )
-  /// );
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
   ///
   /// let value = b"{\"key\": \"value\"}";
-  /// db.write().unwrap().post("test-0", value);
-  /// assert_eq!(db.write().unwrap().get_latest("test-0"), value);
+  /// db.post("test-0", value).await.unwrap();
+  /// assert_eq!(db.get_latest("test-0").await, value);
+  /// # })
   /// ```
   pub fn new() -> Self {
     Self::new_with_all(".db", Default::default())
@@ -64,41 +88,158 @@ impl MMAVAsyncDatabase {
   ///
   /// ## Examples
   /// ```no_run
-  /// use crate::rapiddb::traits::IDatabase;
+  /// use crate::rapiddb::traits::IAsyncDatabase;
   ///
-  /// let db = std::sync::Arc::new(
-  ///   std::sync::RwLock::new(
-  ///     rapiddb::db::MMAVDatabase::new_with_all(".temp/my_path/", Default::default())
-  ///   )
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new_with_all(
+  ///   ".temp/my_path/",
+  ///   Default::default(),
   /// );
   ///
   /// let value = b"{\"key\": \"value\"}";
-  /// db.write().unwrap().post("test-0", value);
-  /// assert_eq!(db.write().unwrap().get_latest("test-0"), value);
+  /// db.post("test-0", value).await.unwrap();
+  /// assert_eq!(db.get_latest("test-0").await, value);
+  /// # })
   /// ```
   pub fn new_with_all(
     db_path: &str,
     aggregates_fn: HashMap<String, AggregateFn>,
   ) -> Self {
-    let mut sensors: std::collections::HashMap<String, MMAV> =
+    Self {
+      state: Arc::new(State::new(db_path, aggregates_fn)),
+      pool: Default::default(),
+    }
+  }
+
+  /// Use `pool` to run the disk IO of the database
+  ///
+  /// The size of the pool bounds the number of disk operations queued
+  /// or running at once, further operations wait for a free slot.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new()
+  ///   .with_blocking_pool(rapiddb::db::BlockingPool::new(64));
+  /// ```
+  pub fn with_blocking_pool(mut self, pool: BlockingPool) -> Self {
+    self.pool = pool;
+    self
+  }
+
+  /// Run `f` with the state of the database on the blocking pool
+  async fn run<F, T>(&self, f: F) -> T
+  where
+    F: FnOnce(&State) -> T + Send + 'static,
+    T: Send + 'static,
+  {
+    let state = self.state.clone();
+    self.pool.run(move || f(&state)).await
+  }
+}
+
+impl Default for MMAVAsyncDatabase {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Receiver of the watch of a sensor, see `State::watch`
+///
+/// Removes the watch once the last of its receivers is dropped, so only
+/// sensors which are waited on keep one.
+struct Watch<'a> {
+  watches: &'a dashmap::DashMap<String, tokio::sync::watch::Sender<usize>>,
+  id: String,
+  receiver: tokio::sync::watch::Receiver<usize>,
+}
+impl Drop for Watch<'_> {
+  fn drop(&mut self) {
+    // the receiver of this watch is dropped after the check, the entry
+    // lock keeps other waiters from subscribing in between
+    self.watches.remove_if(&self.id, |_, x| x.receiver_count() == 1);
+  }
+}
+
+/// Entry of the change log, queued by a write until it is appended, see
+/// `State::sequence`
+enum ChangeEntry {
+  Records { id: String, rec_id: usize, count: usize },
+  Meta { id: String, value: Vec<u8> },
+}
+
+/// State of the Memory Mapped Append-only Vector Database
+///
+/// Holds the sensors and does the blocking disk IO, it is shared with
+/// the blocking pool through an `Arc`.
+struct State {
+  db_path: String,
+  sensors: dashmap::DashMap<String, Arc<RwLock<MMAV>>>,
+  meta: dashmap::DashMap<String, Vec<u8>>,
+  schemas: dashmap::DashMap<String, Arc<SensorSchema>>,
+  idempotency_windows: dashmap::DashMap<String, Arc<Mutex<IdempotencyWindow>>>,
+  consumer_offsets: dashmap::DashMap<String, Arc<Mutex<ConsumerOffsets>>>,
+  commit_log: Mutex<CommitLog>,
+  changes: RwLock<ChangeLog>,
+  queued: std::sync::mpsc::Sender<ChangeEntry>,
+  queue: Mutex<std::sync::mpsc::Receiver<ChangeEntry>>,
+  aggregates: dashmap::DashMap<String, Arc<Mutex<Vec<u8>>>>,
+  aggregates_fn: HashMap<String, AggregateFn>,
+  notifier: Notifier,
+  watches: dashmap::DashMap<String, tokio::sync::watch::Sender<usize>>,
+}
+impl State {
+  /// Load the state of the database at `db_path`
+  ///
+  /// ## Panics
+  /// if invalid `db_path` is provided
+  fn new(db_path: &str, aggregates_fn: HashMap<String, AggregateFn>) -> Self {
+    let sensors: dashmap::DashMap<String, Arc<RwLock<MMAV>>> =
       Default::default();
-    let mut meta: std::collections::HashMap<String, Vec<u8>> =
+    let meta: dashmap::DashMap<String, Vec<u8>> = Default::default();
+    let schemas: dashmap::DashMap<String, Arc<SensorSchema>> =
       Default::default();
+    let consumer_offsets: dashmap::DashMap<
+      String,
+      Arc<Mutex<ConsumerOffsets>>,
+    > = Default::default();
+
+    // a batch which cannot be rolled back leaves the sensors in an
+    // unknown state, so the database is not opened
+    let commit_log = CommitLog::new(&format!("{db_path}/.commit"));
+    commit_log.recover(db_path).expect("failed to recover the commit log");
 
     let paths = std::fs::read_dir(db_path).unwrap_or_else(|_| {
       std::fs::create_dir_all(db_path).unwrap_or_default();
       std::fs::read_dir(db_path).unwrap()
     });
 
+    let changes = ChangeLog::new(db_path);
+    let (queued, queue) = std::sync::mpsc::channel();
+
     for path in paths {
+      let path = path.unwrap();
+      if !path.file_type().map(|x| x.is_dir()).unwrap_or_default()
+        || path.file_name().to_string_lossy().starts_with('.')
+      {
+        continue;
+      }
+
       path
-        .unwrap()
         .file_name()
         .into_string()
         .unwrap_or_default()
         .parse::<String>()
         .map(|x| {
-          sensors.insert(x.clone(), MMAV::new(&format!("{db_path}/{x}")));
+          sensors.insert(
+            x.clone(),
+            Arc::new(RwLock::new(MMAV::new(&format!("{db_path}/{x}")))),
+          );
+          consumer_offsets.insert(
+            x.clone(),
+            Arc::new(Mutex::new(ConsumerOffsets::new(&format!(
+              "{db_path}/{x}/offsets"
+            )))),
+          );
 
           let mut data = vec![];
           let file_name = format!("{db_path}/{x}/meta");
@@ -134,6 +275,10 @@ impl MMAVAsyncDatabase {
 
           file.read_to_end(&mut data).unwrap_or_default();
 
+          if let Ok(Some(schema)) = SensorSchema::from_meta(&data) {
+            schemas.insert(x.clone(), Arc::new(schema));
+          }
+
           meta.insert(x, data);
         })
         .unwrap_or_default();
@@ -143,73 +288,401 @@ impl MMAVAsyncDatabase {
       db_path: db_path.to_owned(),
       sensors,
       meta,
+      schemas,
+      idempotency_windows: Default::default(),
+      consumer_offsets,
+      commit_log: Mutex::new(commit_log),
+      changes: RwLock::new(changes),
+      queued,
+      queue: Mutex::new(queue),
       aggregates: Default::default(),
       aggregates_fn,
+      notifier: Default::default(),
+      watches: Default::default(),
     }
   }
-}
 
-impl Default for MMAVAsyncDatabase {
-  fn default() -> Self {
-    Self::new()
+  /// Get the MMAV of the sensor with `id`
+  ///
+  /// The lock of the MMAV is cloned out of the sensor map, so the shard
+  /// of the map is not locked while the MMAV is in use.
+  fn sensor(&self, id: &str) -> Option<Arc<RwLock<MMAV>>> {
+    self.sensors.get(id).map(|x| x.value().clone())
   }
-}
-#[async_trait::async_trait]
-impl IAsyncDatabase for MMAVAsyncDatabase {
-  async fn contains(&self, id: &str) -> bool {
-    self.sensors.contains_key(id)
+
+  /// Get the MMAV of the sensor with `id`, creating it if it does not
+  /// exist
+  fn sensor_or_insert(&self, id: &str) -> Arc<RwLock<MMAV>> {
+    if let Some(x) = self.sensor(id) {
+      return x;
+    }
+
+    self
+      .sensors
+      .entry(id.to_owned())
+      .or_insert_with(|| {
+        Arc::new(RwLock::new(MMAV::new(&format!("{}/{id}", self.db_path))))
+      })
+      .value()
+      .clone()
   }
 
-  async fn get(&mut self, id: &str, rec_id: usize) -> Vec<u8> {
-    if !self.contains(id).await {
-      return Default::default();
+  /// Signal the waiters on the sensor with `id` that it has `len`
+  /// records
+  ///
+  /// Called while holding the write lock of the sensor, so waiters see
+  /// lengths in the order they are written.
+  fn signal(&self, id: &str, len: usize) {
+    if let Some(x) = self.watches.get(id) {
+      x.send_replace(len);
     }
+  }
+
+  /// Watch the number of records of the sensor with `id`
+  ///
+  /// The watch starts at zero and only follows writes from now on, so
+  /// the length of the sensor must be read after subscribing, to not
+  /// miss a write in between.
+  fn watch(&self, id: &str) -> Watch<'_> {
+    let receiver = self
+      .watches
+      .entry(id.to_owned())
+      .or_insert_with(|| tokio::sync::watch::channel(0).0)
+      .subscribe();
+
+    Watch { watches: &self.watches, id: id.to_owned(), receiver }
+  }
 
-    self.sensors.get_mut(id).unwrap().get(rec_id)
+  /// Append the entries queued by writes to the change log
+  ///
+  /// Writes queue their entries while holding the write lock of their
+  /// sensor, so the entries of a sensor are in the order of its writes,
+  /// and append them once the lock is released, so a write does not
+  /// hold its sensor while it waits for the change log. The first write
+  /// to lock the change log appends the entries of all writes queued so
+  /// far.
+  fn sequence(&self) {
+    self.append_queued(&mut self.changes.write().unwrap());
   }
 
-  async fn post(&mut self, id: &str, value: &[u8]) {
-    if !self.contains(id).await {
-      self
-        .sensors
-        .insert(id.to_owned(), MMAV::new(&format!("{}/{id}", self.db_path)));
+  /// Append the entries queued by writes to `changes`, see `sequence`
+  fn append_queued(&self, changes: &mut ChangeLog) {
+    let queue = self.queue.lock().unwrap();
+
+    for entry in queue.try_iter() {
+      // entries of records are a few bytes, and entries of metadata are
+      // checked before they are queued, so they fit in the change log
+      match entry {
+        ChangeEntry::Records { id, rec_id, count } => {
+          changes.push_records(&id, rec_id, count)
+        }
+        ChangeEntry::Meta { id, value } => changes.push_meta(&id, &value),
+      }
+      .expect("queued entries fit in the change log");
     }
+  }
+
+  /// Validate and push `values` to the sensor with `id`, and run the
+  /// aggregate function of the sensor for each value while holding its
+  /// write lock, so aggregates see records in the order they are pushed
+  fn push_batch(
+    &self,
+    id: &str,
+    values: &[&[u8]],
+  ) -> Result<Vec<usize>, Error> {
+    validate_id(id)?;
 
-    if !self.aggregates.contains_key(id) {
-      self.aggregates.insert(
-        id.to_string(),
-        std::sync::Arc::new(std::sync::Mutex::new(
+    let schema = self.schemas.get(id).map(|x| x.value().clone());
+    if let Some(schema) = schema {
+      for value in values {
+        schema.validate(value)?;
+      }
+    }
+
+    if values.is_empty() {
+      return Ok(Default::default());
+    }
+
+    let sensor = self.sensor_or_insert(id);
+    let mut sensor = sensor.write().unwrap();
+
+    let index = sensor.push_batch(values)?;
+    self
+      .queued
+      .send(ChangeEntry::Records {
+        id: id.to_owned(),
+        rec_id: index,
+        count: values.len(),
+      })
+      .unwrap_or_default();
+    self.aggregate(id, values);
+
+    self.notifier.records(
+      &values
+        .iter()
+        .zip(index..)
+        .map(|(value, rec_id)| (id, rec_id, *value))
+        .collect::<Vec<_>>(),
+    );
+    self.signal(id, sensor.len());
+
+    drop(sensor);
+    self.sequence();
+
+    Ok((index..index + values.len()).collect())
+  }
+
+  /// Run the aggregate function of the sensor with `id` for each of
+  /// `values`, in order
+  fn aggregate(&self, id: &str, values: &[&[u8]]) {
+    let aggregate = self
+      .aggregates
+      .entry(id.to_owned())
+      .or_insert_with(|| {
+        Arc::new(Mutex::new(
           serde_json::json!({}).to_string().as_bytes().to_owned(),
-        )),
-      );
+        ))
+      })
+      .value()
+      .clone();
+
+    if let Some(x) = self.aggregates_fn.get(id) {
+      x.lock()
+        .map(|f| {
+          for value in values {
+            f(id, value, &aggregate);
+          }
+        })
+        .err();
+    }
+  }
+
+  /// Validate and push `value` to the sensor with `id`
+  fn push(&self, id: &str, value: &[u8]) -> Result<usize, Error> {
+    self.push_batch(id, &[value]).map(|x| x[0])
+  }
+
+  fn contains(&self, id: &str) -> bool {
+    self.sensors.contains_key(id)
+  }
+
+  fn get(&self, id: &str, rec_id: usize) -> Vec<u8> {
+    match self.sensor(id) {
+      Some(x) => x.read().unwrap().get(rec_id),
+      None => Default::default(),
+    }
+  }
+
+  fn post(&self, id: &str, value: &[u8]) -> Result<usize, Error> {
+    self.push(id, value)
+  }
+
+  fn post_with_key(
+    &self,
+    id: &str,
+    key: &str,
+    value: &[u8],
+  ) -> Result<usize, Error> {
+    validate_id(id)?;
+
+    let window = self
+      .idempotency_windows
+      .entry(id.to_owned())
+      .or_insert_with(|| {
+        Arc::new(Mutex::new(IdempotencyWindow::new(
+          &format!("{}/{id}/idempotency", self.db_path),
+          IDEMPOTENCY_WINDOW_SIZE,
+        )))
+      })
+      .value()
+      .clone();
+    let mut window = window.lock().unwrap();
+
+    if let Some(rec_id) = window.get(key) {
+      return Ok(rec_id);
     }
 
-    if let Some(aggregate) = self.aggregates.get(id) {
-      if let Some(x) = self.aggregates_fn.get(id) {
-        x.lock().map(|f| f(id, value, aggregate)).err();
+    let rec_id = self.push(id, value)?;
+    window.insert(key, rec_id);
+
+    Ok(rec_id)
+  }
+
+  fn post_batch(
+    &self,
+    id: &str,
+    values: &[&[u8]],
+  ) -> Result<Vec<usize>, Error> {
+    self.push_batch(id, values)
+  }
+
+  fn post_many(&self, records: &[(&str, &[u8])]) -> Result<Vec<usize>, Error> {
+    for (id, value) in records {
+      validate_id(id)?;
+      let schema = self.schemas.get(*id).map(|x| x.value().clone());
+      if let Some(schema) = schema {
+        schema.validate(value)?;
       }
     }
 
-    self.sensors.get_mut(id).unwrap().push(value);
+    let mut result = vec![0; records.len()];
+
+    for (id, positions) in group_by_sensor(records) {
+      let values = positions.iter().map(|x| records[*x].1).collect::<Vec<_>>();
+
+      for (position, rec_id) in
+        positions.iter().zip(self.push_batch(id, &values)?)
+      {
+        result[*position] = rec_id;
+      }
+    }
+
+    Ok(result)
   }
 
-  async fn get_meta(&mut self, id: &str) -> Vec<u8> {
-    if !self.contains(id).await {
-      return Default::default();
+  /// Commit the records of `batch` atomically
+  ///
+  /// Holds the write locks of all sensors in the batch, taken in order
+  /// of sensor id, and then the lock of the change log, while the batch
+  /// is written, so concurrent commits cannot deadlock. Other writes do
+  /// not lock the change log while holding their sensor.
+  fn commit(&self, batch: &WriteBatch) -> Result<Vec<usize>, Error> {
+    let records = batch.records();
+
+    for (id, value) in &records {
+      validate_id(id)?;
+      let schema = self.schemas.get(*id).map(|x| x.value().clone());
+      if let Some(schema) = schema {
+        schema.validate(value)?;
+      }
     }
 
+    if records.is_empty() {
+      return Ok(Default::default());
+    }
+
+    let commit_log = self.commit_log.lock().unwrap();
+
+    let groups = group_by_sensor(&records);
+
+    let mut ids = groups.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+    ids.sort_unstable();
+
+    let mut created = vec![];
+    let shared = ids
+      .iter()
+      .map(|x| match self.sensors.entry(x.to_string()) {
+        dashmap::mapref::entry::Entry::Occupied(x) => x.get().clone(),
+        dashmap::mapref::entry::Entry::Vacant(x) => {
+          let mmav = MMAV::new(&format!("{}/{}", self.db_path, x.key()));
+          created.push(x.key().clone());
+          x.insert(Arc::new(RwLock::new(mmav))).value().clone()
+        }
+      })
+      .collect::<Vec<_>>();
+    let mut sensors =
+      shared.iter().map(|x| x.write().unwrap()).collect::<Vec<_>>();
+
+    // the entries queued by earlier writes to the sensors of the batch
+    // are appended before the entries of the batch
+    let mut changes = self.changes.write().unwrap();
+    self.append_queued(&mut changes);
+
+    let mut lens = ids
+      .iter()
+      .zip(&sensors)
+      .map(|(id, x)| (id.to_string(), x.len()))
+      .collect::<HashMap<_, _>>();
+    lens.insert(CHANGE_LOG_ID.to_owned(), changes.len());
+
+    commit_log.begin(&lens, &created)?;
+
+    let mut result = vec![0; records.len()];
+
+    let committed = (|| {
+      for (id, positions) in &groups {
+        let values =
+          positions.iter().map(|x| records[*x].1).collect::<Vec<_>>();
+        let sensor = &mut sensors[ids.binary_search(id).unwrap()];
+
+        let index = sensor.push_batch(&values)?;
+        changes.push_records(id, index, values.len())?;
+
+        for (position, rec_id) in positions.iter().zip(index..) {
+          result[*position] = rec_id;
+        }
+      }
+
+      sensors.iter().try_for_each(|x| x.flush())?;
+      changes.flush()?;
+
+      commit_log.end()
+    })();
+
+    if let Err(error) = committed {
+      // the commit log is kept, if the batch cannot be rolled back, so
+      // it is rolled back again when the database is opened
+      let rolled_back = (|| {
+        for (id, sensor) in ids.iter().zip(&mut sensors) {
+          sensor.truncate(lens[*id]);
+          sensor.flush()?;
+        }
+        // a created sensor is only removed if no other write is waiting
+        // on it, which holds the only other reference to it
+        for id in &created {
+          if self
+            .sensors
+            .remove_if(id, |_, x| Arc::strong_count(x) == 2)
+            .is_some()
+          {
+            remove_sensor_dir(&format!("{}/{id}", self.db_path))?;
+          }
+        }
+        changes.truncate(lens[CHANGE_LOG_ID]);
+        changes.flush()?;
+
+        commit_log.end()
+      })();
+
+      return Err(rolled_back.err().unwrap_or(error));
+    }
+
+    // aggregates are only applied once the batch is committed, so a
+    // rolled back batch leaves them unchanged
+    for (id, positions) in &groups {
+      let values = positions.iter().map(|x| records[*x].1).collect::<Vec<_>>();
+      self.aggregate(id, &values);
+    }
+
+    for (id, sensor) in ids.iter().zip(&sensors) {
+      self.signal(id, sensor.len());
+    }
+
+    self.notifier.records(
+      &records
+        .iter()
+        .zip(&result)
+        .map(|((id, value), rec_id)| (*id, *rec_id, *value))
+        .collect::<Vec<_>>(),
+    );
+
+    Ok(result)
+  }
+
+  fn get_meta(&self, id: &str) -> Vec<u8> {
     match self.meta.get(id) {
-      Some(x) => x.clone(),
+      Some(x) => x.value().clone(),
       None => Default::default(),
     }
   }
 
-  async fn post_meta(&mut self, id: &str, data: Vec<u8>) {
-    if !self.contains(id).await {
-      self
-        .sensors
-        .insert(id.to_owned(), MMAV::new(&format!("{}/{id}", self.db_path)));
-    }
+  fn post_meta(&self, id: &str, data: Vec<u8>) -> Result<(), Error> {
+    validate_id(id)?;
+    let schema = SensorSchema::from_meta(&data)?;
+    self.changes.read().unwrap().check_meta(id, &data)?;
+
+    let sensor = self.sensor_or_insert(id);
+    let sensor = sensor.write().unwrap();
 
     let file_name = format!("{}/{id}/meta", self.db_path);
 
@@ -217,7 +690,7 @@ impl IAsyncDatabase for MMAVAsyncDatabase {
       .read(true)
       .write(true)
       .create(true)
-      .truncate(false)
+      .truncate(true)
       .open(&file_name)
       .unwrap_or_else(|error| {
         if error.kind() == std::io::ErrorKind::NotFound {
@@ -237,107 +710,526 @@ impl IAsyncDatabase for MMAVAsyncDatabase {
           .read(true)
           .write(true)
           .create(true)
-          .truncate(false)
+          .truncate(true)
           .open(file_name)
           .unwrap()
       });
 
     file.write_all(&data).unwrap_or_default();
+    self.notifier.meta(id);
+    self
+      .queued
+      .send(ChangeEntry::Meta { id: id.to_owned(), value: data.clone() })
+      .unwrap_or_default();
     self.meta.insert(id.to_owned(), data);
-  }
 
-  async fn get_aggregates(&self, id: &str) -> Vec<u8> {
-    if !self.aggregates.contains_key(id) {
-      return Default::default();
-    }
+    match schema {
+      Some(x) => self.schemas.insert(id.to_owned(), Arc::new(x)),
+      None => self.schemas.remove(id).map(|x| x.1),
+    };
+
+    drop(sensor);
+    self.sequence();
 
-    self.aggregates[id].lock().unwrap().clone()
+    Ok(())
   }
 
-  async fn get_latest(&mut self, id: &str) -> Vec<u8> {
-    if !self.contains(id).await {
-      return Default::default();
+  fn get_aggregates(&self, id: &str) -> Vec<u8> {
+    match self.aggregates.get(id) {
+      Some(x) => x.value().lock().unwrap().clone(),
+      None => Default::default(),
     }
+  }
 
-    self.sensors.get(id).unwrap().last()
+  fn get_latest(&self, id: &str) -> Vec<u8> {
+    match self.sensor(id) {
+      Some(x) => x.read().unwrap().last(),
+      None => Default::default(),
+    }
   }
 
-  async fn get_latest_with_limit(
-    &mut self,
-    id: &str,
-    limit: usize,
-  ) -> Vec<Vec<u8>> {
-    if !self.contains(id).await {
-      return Default::default();
+  fn get_latest_with_limit(&self, id: &str, limit: usize) -> Vec<Vec<u8>> {
+    match self.sensor(id) {
+      Some(x) => x.read().unwrap().last_limit(limit),
+      None => Default::default(),
     }
+  }
 
-    self.sensors.get_mut(id).unwrap().last_limit(limit)
+  fn get_range(&self, id: &str, start: usize, end: usize) -> Vec<Vec<u8>> {
+    match self.sensor(id) {
+      Some(x) => x.read().unwrap().range(start, end),
+      None => Default::default(),
+    }
   }
 
-  async fn get_range(
-    &mut self,
+  fn get_range_ref(
+    &self,
     id: &str,
     start: usize,
     end: usize,
-  ) -> Vec<Vec<u8>> {
-    if !self.contains(id).await {
-      return Default::default();
+  ) -> Vec<RecordRef> {
+    match self.sensor(id) {
+      Some(x) => x.read().unwrap().iter_range(start..=end).collect(),
+      None => Default::default(),
     }
+  }
 
-    self.sensors.get_mut(id).unwrap().range(start, end)
+  fn get_all_meta(&self) -> HashMap<String, Vec<u8>> {
+    self.meta.iter().map(|x| (x.key().clone(), x.value().clone())).collect()
   }
 
-  async fn get_all_meta(&mut self) -> std::collections::HashMap<&str, Vec<u8>> {
-    let mut result: std::collections::HashMap<&str, Vec<u8>> =
-      Default::default();
+  fn get_all_aggregates(&self) -> HashMap<String, Vec<u8>> {
+    self
+      .aggregates
+      .iter()
+      .map(|x| (x.key().clone(), x.value().lock().unwrap().clone()))
+      .collect()
+  }
+
+  fn get_all_latest(&self) -> HashMap<String, Vec<u8>> {
+    let sensors = self
+      .sensors
+      .iter()
+      .map(|x| (x.key().clone(), x.value().clone()))
+      .collect::<Vec<_>>();
+
+    let mut result: HashMap<String, Vec<u8>> = Default::default();
 
-    for (id, value) in &self.meta {
-      result.insert(id, value.clone());
+    for (id, sensor) in sensors {
+      result.insert(id, sensor.read().unwrap().last());
     }
 
     result
   }
 
-  async fn get_all_aggregates(
+  fn get_all_latest_with_limit(
     &self,
-  ) -> std::collections::HashMap<&str, Vec<u8>> {
-    let mut result: std::collections::HashMap<&str, Vec<u8>> =
-      Default::default();
+    limit: usize,
+  ) -> HashMap<String, Vec<Vec<u8>>> {
+    let sensors = self
+      .sensors
+      .iter()
+      .map(|x| (x.key().clone(), x.value().clone()))
+      .collect::<Vec<_>>();
+
+    let mut result: HashMap<String, Vec<Vec<u8>>> = Default::default();
 
-    for (key, value) in &self.aggregates {
-      result.insert(key, value.lock().unwrap().clone());
+    for (id, sensor) in sensors {
+      let item = sensor.read().unwrap().last_limit(limit);
+      if !item.is_empty() {
+        result.insert(id, item);
+      }
     }
 
     result
   }
 
-  async fn get_all_latest(
-    &mut self,
-  ) -> std::collections::HashMap<&str, Vec<u8>> {
-    let mut result: std::collections::HashMap<&str, Vec<u8>> =
-      Default::default();
+  /// Take a snapshot of the length of every sensor
+  ///
+  /// Holds the read locks of all sensors, taken in order of sensor id,
+  /// while the lengths are read, so the snapshot sees either all or none
+  /// of the records of a commit.
+  fn snapshot(&self) -> Snapshot {
+    let mut sensors = self
+      .sensors
+      .iter()
+      .map(|x| (x.key().clone(), x.value().clone()))
+      .collect::<Vec<_>>();
+    sensors.sort_unstable_by(|x, y| x.0.cmp(&y.0));
+
+    let locks =
+      sensors.iter().map(|(_, x)| x.read().unwrap()).collect::<Vec<_>>();
+
+    Snapshot::new(
+      sensors
+        .iter()
+        .zip(&locks)
+        .map(|((id, _), x)| (id.clone(), x.len()))
+        .collect(),
+    )
+  }
 
-    for (id, sensor) in &mut self.sensors {
-      result.insert(id, sensor.last());
+  fn changes_since(&self, lsn: usize, limit: usize) -> Vec<Change> {
+    let mut result = self.changes.read().unwrap().since(lsn, limit);
+
+    for change in &mut result {
+      if let Some(rec_id) = change.rec_id {
+        change.value = self.get(&change.id, rec_id);
+      }
     }
 
     result
   }
 
+  fn get_offset(&self, consumer: &str, id: &str) -> usize {
+    match self.consumer_offsets.get(id) {
+      Some(x) => x.value().lock().unwrap().get(consumer),
+      None => Default::default(),
+    }
+  }
+
+  fn poll(
+    &self,
+    consumer: &str,
+    id: &str,
+    max: usize,
+  ) -> (usize, Vec<(usize, Vec<u8>)>) {
+    let Some(sensor) = self.sensor(id) else {
+      return Default::default();
+    };
+    let sensor = sensor.read().unwrap();
+
+    let offset = self.get_offset(consumer, id);
+    let end = sensor.len().min(offset.saturating_add(max));
+
+    (offset, (offset..end).map(|x| (x, sensor.get(x))).collect())
+  }
+
+  fn len(&self, id: &str) -> usize {
+    match self.sensor(id) {
+      Some(x) => x.read().unwrap().len(),
+      None => Default::default(),
+    }
+  }
+
+  fn commit_offset(
+    &self,
+    consumer: &str,
+    id: &str,
+    offset: usize,
+  ) -> Result<(), Error> {
+    let len = self.len(id);
+    if offset > len {
+      return Err(Error::IndexOutOfRange);
+    }
+
+    if !self.contains(id) {
+      return Ok(());
+    }
+
+    let offsets = self
+      .consumer_offsets
+      .entry(id.to_owned())
+      .or_insert_with(|| {
+        Arc::new(Mutex::new(ConsumerOffsets::new(&format!(
+          "{}/{id}/offsets",
+          self.db_path
+        ))))
+      })
+      .value()
+      .clone();
+
+    let mut offsets = offsets.lock().unwrap();
+    offsets.commit(consumer, offset)
+  }
+}
+
+#[async_trait::async_trait]
+impl IAsyncDatabase for MMAVAsyncDatabase {
+  async fn contains(&self, id: &str) -> bool {
+    self.state.contains(id)
+  }
+
+  async fn len(&self, id: &str) -> usize {
+    let id = id.to_owned();
+    self.run(move |x| x.len(&id)).await
+  }
+
+  async fn get(&self, id: &str, rec_id: usize) -> Vec<u8> {
+    let id = id.to_owned();
+    self.run(move |x| x.get(&id, rec_id)).await
+  }
+
+  async fn post(&self, id: &str, value: &[u8]) -> Result<usize, Error> {
+    let id = id.to_owned();
+    let value = value.to_owned();
+    self.run(move |x| x.post(&id, &value)).await
+  }
+
+  async fn post_with_key(
+    &self,
+    id: &str,
+    key: &str,
+    value: &[u8],
+  ) -> Result<usize, Error> {
+    let id = id.to_owned();
+    let key = key.to_owned();
+    let value = value.to_owned();
+    self.run(move |x| x.post_with_key(&id, &key, &value)).await
+  }
+
+  async fn post_batch(
+    &self,
+    id: &str,
+    values: &[&[u8]],
+  ) -> Result<Vec<usize>, Error> {
+    let id = id.to_owned();
+    let values = values.iter().map(|x| x.to_vec()).collect::<Vec<_>>();
+    self
+      .run(move |x| {
+        x.post_batch(&id, &values.iter().map(|x| &x[..]).collect::<Vec<_>>())
+      })
+      .await
+  }
+
+  async fn post_many(
+    &self,
+    records: &[(&str, &[u8])],
+  ) -> Result<Vec<usize>, Error> {
+    let records = records
+      .iter()
+      .map(|(id, value)| (id.to_string(), value.to_vec()))
+      .collect::<Vec<_>>();
+    self
+      .run(move |x| {
+        x.post_many(
+          &records
+            .iter()
+            .map(|(id, value)| (&id[..], &value[..]))
+            .collect::<Vec<_>>(),
+        )
+      })
+      .await
+  }
+
+  async fn commit(&self, batch: &WriteBatch) -> Result<Vec<usize>, Error> {
+    let batch = batch.clone();
+    self.run(move |x| x.commit(&batch)).await
+  }
+
+  async fn get_meta(&self, id: &str) -> Vec<u8> {
+    self.state.get_meta(id)
+  }
+
+  async fn post_meta(&self, id: &str, data: Vec<u8>) -> Result<(), Error> {
+    let id = id.to_owned();
+    self.run(move |x| x.post_meta(&id, data)).await
+  }
+
+  async fn get_aggregates(&self, id: &str) -> Vec<u8> {
+    self.state.get_aggregates(id)
+  }
+
+  async fn get_latest(&self, id: &str) -> Vec<u8> {
+    let id = id.to_owned();
+    self.run(move |x| x.get_latest(&id)).await
+  }
+
+  async fn get_latest_with_limit(
+    &self,
+    id: &str,
+    limit: usize,
+  ) -> Vec<Vec<u8>> {
+    let id = id.to_owned();
+    self.run(move |x| x.get_latest_with_limit(&id, limit)).await
+  }
+
+  async fn get_range(
+    &self,
+    id: &str,
+    start: usize,
+    end: usize,
+  ) -> Vec<Vec<u8>> {
+    let id = id.to_owned();
+    self.run(move |x| x.get_range(&id, start, end)).await
+  }
+
+  /// Records are streamed as handles to the memory map, so they are not
+  /// copied before they are written out.
+  fn stream_range(
+    self: Arc<Self>,
+    id: &str,
+    start: usize,
+    end: usize,
+  ) -> RecordStream {
+    use futures_util::StreamExt;
+
+    let state = (self, id.to_owned(), start, None);
+    let chunks = futures_util::stream::unfold(
+      state,
+      move |(db, id, next, len)| async move {
+        let sensor = id.clone();
+        let (chunk, len) = db
+          .run(move |x| {
+            let len = len.unwrap_or_else(|| x.len(&sensor));
+            let last = end
+              .min(len.saturating_sub(1))
+              .min(next.saturating_add(CHUNK_SIZE - 1));
+            match len > 0 && next <= last {
+              true => (x.get_range_ref(&sensor, next, last), len),
+              false => (Default::default(), len),
+            }
+          })
+          .await;
+        if chunk.is_empty() {
+          return None;
+        }
+
+        let next = next + chunk.len();
+        Some((futures_util::stream::iter(chunk), (db, id, next, Some(len))))
+      },
+    );
+
+    chunks.flatten().boxed()
+  }
+
+  async fn get_all_meta(&self) -> HashMap<String, Vec<u8>> {
+    self.state.get_all_meta()
+  }
+
+  async fn get_all_aggregates(&self) -> HashMap<String, Vec<u8>> {
+    self.state.get_all_aggregates()
+  }
+
+  async fn get_all_latest(&self) -> HashMap<String, Vec<u8>> {
+    self.run(|x| x.get_all_latest()).await
+  }
+
   async fn get_all_latest_with_limit(
-    &mut self,
+    &self,
     limit: usize,
-  ) -> std::collections::HashMap<&str, Vec<Vec<u8>>> {
-    let mut result: std::collections::HashMap<&str, Vec<Vec<u8>>> =
-      Default::default();
+  ) -> HashMap<String, Vec<Vec<u8>>> {
+    self.run(move |x| x.get_all_latest_with_limit(limit)).await
+  }
 
-    for (id, sensor) in &mut self.sensors {
-      let item = sensor.last_limit(limit);
-      if !item.is_empty() {
-        result.insert(id, item);
-      }
+  async fn snapshot(&self) -> Snapshot {
+    self.run(|x| x.snapshot()).await
+  }
+
+  async fn changes_since(&self, lsn: usize, limit: usize) -> Vec<Change> {
+    self.run(move |x| x.changes_since(lsn, limit)).await
+  }
+
+  async fn get_offset(&self, consumer: &str, id: &str) -> usize {
+    self.state.get_offset(consumer, id)
+  }
+
+  async fn poll(
+    &self,
+    consumer: &str,
+    id: &str,
+    max: usize,
+  ) -> (usize, Vec<(usize, Vec<u8>)>) {
+    let consumer = consumer.to_owned();
+    let id = id.to_owned();
+    self.run(move |x| x.poll(&consumer, &id, max)).await
+  }
+
+  async fn commit_offset(
+    &self,
+    consumer: &str,
+    id: &str,
+    offset: usize,
+  ) -> Result<(), Error> {
+    let consumer = consumer.to_owned();
+    let id = id.to_owned();
+    self.run(move |x| x.commit_offset(&consumer, &id, offset)).await
+  }
+
+  fn notifications(&self) -> tokio::sync::broadcast::Receiver<Notification> {
+    self.state.notifier.subscribe()
+  }
+
+  fn subscribe(&self, filter: SubscriptionFilter) -> Subscription {
+    self.state.notifier.subscription(filter)
+  }
+
+  async fn wait_for(
+    &self,
+    id: &str,
+    len: usize,
+    timeout: std::time::Duration,
+  ) -> Option<usize> {
+    let mut watch = self.state.watch(id);
+
+    let current = self.len(id).await;
+    if current > len {
+      return Some(current);
     }
 
-    result
+    tokio::time::timeout(timeout, watch.receiver.wait_for(|x| *x > len))
+      .await
+      .ok()
+      .and_then(|x| x.ok().map(|x| *x))
   }
 }
+
+#[test]
+fn test_wait_for() {
+  let db_path = ".temp/test/mmav_async_database/test_wait_for";
+  let db = MMAVAsyncDatabase::new_with_all(db_path, Default::default());
+
+  tokio_test::block_on(async {
+    let timeout = std::time::Duration::from_millis(10);
+
+    assert_eq!(db.wait_for("test-0", 0, timeout).await, None);
+    assert!(db.state.watches.is_empty());
+
+    db.post("test-0", b"{}").await.unwrap();
+    assert_eq!(db.wait_for("test-0", 0, timeout).await, Some(1));
+    assert_eq!(db.wait_for("test-0", 1, timeout).await, None);
+    assert!(db.state.watches.is_empty());
+  });
+
+  std::fs::remove_dir_all(db_path).unwrap_or_default();
+}
+
+#[test]
+fn test_changes_since() {
+  let db_path = ".temp/test/mmav_async_database/test_changes_since";
+  let db = MMAVAsyncDatabase::new_with_all(db_path, Default::default());
+
+  std::thread::scope(|scope| {
+    for x in 0..4 {
+      let state = &db.state;
+      scope.spawn(move || {
+        for _ in 0..250 {
+          state.post(&format!("test-{}", x % 2), b"{}").unwrap();
+        }
+      });
+    }
+  });
+
+  let changes = db.state.changes_since(0, usize::MAX);
+  assert_eq!(changes.len(), 1000);
+  for id in ["test-0", "test-1"] {
+    let rec_ids = changes
+      .iter()
+      .filter(|x| x.id == id)
+      .map(|x| x.rec_id.unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(rec_ids, (0..500).collect::<Vec<_>>());
+  }
+
+  let value = vec![b' '; 15_000_000];
+  assert!(matches!(db.state.post_meta("test-0", value), Err(Error::FileFull)));
+  assert_eq!(db.state.get_meta("test-0"), b"");
+  assert_eq!(db.state.changes_since(1000, 10).len(), 0);
+
+  std::fs::remove_dir_all(db_path).unwrap_or_default();
+}
+
+#[test]
+fn test_commit_rollback() {
+  let db_path = ".temp/test/mmav_async_database/test_commit_rollback";
+  let db = MMAVAsyncDatabase::new_with_all(db_path, Default::default());
+
+  tokio_test::block_on(async {
+    let (id, id0) = ("test-0", "test-1");
+    db.post(id, b"{\"n\":0}").await.unwrap();
+
+    let value = vec![b'0'; 15_000_000];
+    assert!(db
+      .commit(
+        crate::db::WriteBatch::new().post(id, b"{\"n\":1}").post(id0, &value)
+      )
+      .await
+      .is_err());
+    assert_eq!(db.len(id).await, 1);
+    assert!(!db.contains(id0).await);
+    assert!(!std::path::Path::new(&format!("{db_path}/{id0}")).exists());
+    assert!(!std::path::Path::new(&format!("{db_path}/.commit")).exists());
+  });
+
+  std::fs::remove_dir_all(db_path).unwrap_or_default();
+}
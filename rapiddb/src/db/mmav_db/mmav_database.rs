@@ -1,10 +1,25 @@
 use std::collections::HashMap;
 use std::io::{Read, Write};
 
+use crate::db::mmav_db::change_log::{ChangeLog, CHANGE_LOG_ID};
+use crate::db::mmav_db::commit_log::{remove_sensor_dir, CommitLog};
+use crate::db::mmav_db::consumer_offsets::ConsumerOffsets;
+use crate::db::mmav_db::group_by_sensor;
+use crate::db::mmav_db::idempotency_window::IdempotencyWindow;
 use crate::db::mmav_db::mmav::MMAV;
+use crate::db::validate_id;
+use crate::db::Change;
+use crate::db::RecordRef;
+use crate::db::SensorSchema;
+use crate::db::Snapshot;
+use crate::db::WriteBatch;
+use crate::errors::Error;
 use crate::traits::IDatabase;
 use crate::types::AggregateFn;
 
+/// Number of recent idempotency keys remembered per sensor
+const IDEMPOTENCY_WINDOW_SIZE: usize = 1024;
+
 /// Memory Mapped Append-only Vector Database
 ///
 /// This is the database abstraction, it futures a further
@@ -23,13 +38,18 @@ use crate::types::AggregateFn;
 /// );
 ///
 /// let value = b"{\"key\": \"value\"}";
-/// db.write().unwrap().post("test-0", value);
-/// assert_eq!(db.write().unwrap().get_latest("test-0"), value);
+/// db.write().unwrap().post("test-0", value).unwrap();
+/// assert_eq!(db.read().unwrap().get_latest("test-0"), value);
 /// ```
 pub struct MMAVDatabase {
   db_path: String,
   sensors: std::collections::HashMap<String, MMAV>,
   meta: std::collections::HashMap<String, Vec<u8>>,
+  schemas: std::collections::HashMap<String, SensorSchema>,
+  idempotency_windows: std::collections::HashMap<String, IdempotencyWindow>,
+  consumer_offsets: std::collections::HashMap<String, ConsumerOffsets>,
+  commit_log: CommitLog,
+  changes: ChangeLog,
   aggregates: std::collections::HashMap<
     String,
     std::sync::Arc<std::sync::Mutex<Vec<u8>>>,
@@ -50,8 +70,8 @@ impl MMAVDatabase {
   /// );
   ///
   /// let value = b"{\"key\": \"value\"}";
-  /// db.write().unwrap().post("test-0", value);
-  /// assert_eq!(db.write().unwrap().get_latest("test-0"), value);
+  /// db.write().unwrap().post("test-0", value).unwrap();
+  /// assert_eq!(db.read().unwrap().get_latest("test-0"), value);
   /// ```
   pub fn new() -> Self {
     Self::new_with_all(".db", Default::default())
@@ -73,8 +93,8 @@ impl MMAVDatabase {
   /// );
   ///
   /// let value = b"{\"key\": \"value\"}";
-  /// db.write().unwrap().post("test-0", value);
-  /// assert_eq!(db.write().unwrap().get_latest("test-0"), value);
+  /// db.write().unwrap().post("test-0", value).unwrap();
+  /// assert_eq!(db.read().unwrap().get_latest("test-0"), value);
   /// ```
   pub fn new_with_all(
     db_path: &str,
@@ -84,21 +104,44 @@ impl MMAVDatabase {
       Default::default();
     let mut meta: std::collections::HashMap<String, Vec<u8>> =
       Default::default();
+    let mut schemas: std::collections::HashMap<String, SensorSchema> =
+      Default::default();
+    let mut consumer_offsets: std::collections::HashMap<
+      String,
+      ConsumerOffsets,
+    > = Default::default();
+
+    // a batch which cannot be rolled back leaves the sensors in an
+    // unknown state, so the database is not opened
+    let commit_log = CommitLog::new(&format!("{db_path}/.commit"));
+    commit_log.recover(db_path).expect("failed to recover the commit log");
 
     let paths = std::fs::read_dir(db_path).unwrap_or_else(|_| {
       std::fs::create_dir_all(db_path).unwrap_or_default();
       std::fs::read_dir(db_path).unwrap()
     });
 
+    let changes = ChangeLog::new(db_path);
+
     for path in paths {
+      let path = path.unwrap();
+      if !path.file_type().map(|x| x.is_dir()).unwrap_or_default()
+        || path.file_name().to_string_lossy().starts_with('.')
+      {
+        continue;
+      }
+
       path
-        .unwrap()
         .file_name()
         .into_string()
         .unwrap_or_default()
         .parse::<String>()
         .map(|x| {
           sensors.insert(x.clone(), MMAV::new(&format!("{db_path}/{x}")));
+          consumer_offsets.insert(
+            x.clone(),
+            ConsumerOffsets::new(&format!("{db_path}/{x}/offsets")),
+          );
 
           let mut data = vec![];
           let file_name = format!("{db_path}/{x}/meta");
@@ -134,6 +177,10 @@ impl MMAVDatabase {
 
           file.read_to_end(&mut data).unwrap_or_default();
 
+          if let Ok(Some(schema)) = SensorSchema::from_meta(&data) {
+            schemas.insert(x.clone(), schema);
+          }
+
           meta.insert(x, data);
         })
         .unwrap_or_default();
@@ -143,10 +190,104 @@ impl MMAVDatabase {
       db_path: db_path.to_owned(),
       sensors,
       meta,
+      schemas,
+      idempotency_windows: Default::default(),
+      consumer_offsets,
+      commit_log,
+      changes,
       aggregates: Default::default(),
       aggregates_fn,
     }
   }
+
+  /// Get the record with `rec_id` of the sensor with `id`, without
+  /// copying it out of the memory map
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IDatabase;
+  ///
+  /// let mut db = rapiddb::db::MMAVDatabase::new();
+  ///
+  /// let value = b"{\"key\": \"value\"}";
+  /// db.post("test-0", value).unwrap();
+  /// assert_eq!(&*db.get_ref("test-0", 0).unwrap(), value);
+  /// ```
+  pub fn get_ref(&self, id: &str, rec_id: usize) -> Option<RecordRef> {
+    self.sensors.get(id).and_then(|x| x.get_ref(rec_id))
+  }
+
+  /// Iterate over the records from `start` to `end` of the sensor with
+  /// `id`, without copying them out of the memory map
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IDatabase;
+  ///
+  /// let mut db = rapiddb::db::MMAVDatabase::new();
+  ///
+  /// let value = b"{\"key\": \"value\"}";
+  /// db.post("test-0", value).unwrap();
+  /// db.post("test-0", value).unwrap();
+  /// assert_eq!(db.iter_range_ref("test-0", 0, 1).count(), 2);
+  /// ```
+  pub fn iter_range_ref(
+    &self,
+    id: &str,
+    start: usize,
+    end: usize,
+  ) -> Box<dyn DoubleEndedIterator<Item = RecordRef> + '_> {
+    match self.sensors.get(id) {
+      Some(x) => Box::new(x.iter_range(start..=end)),
+      None => Box::new(std::iter::empty()),
+    }
+  }
+
+  /// Apply the aggregate function of the sensor with `id` to `values`
+  fn aggregate(&mut self, id: &str, values: &[&[u8]]) {
+    if !self.aggregates.contains_key(id) {
+      self.aggregates.insert(
+        id.to_string(),
+        std::sync::Arc::new(std::sync::Mutex::new(
+          serde_json::json!({}).to_string().as_bytes().to_owned(),
+        )),
+      );
+    }
+
+    if let Some(aggregate) = self.aggregates.get(id) {
+      if let Some(x) = self.aggregates_fn.get(id) {
+        x.lock()
+          .map(|f| {
+            for value in values {
+              f(id, value, aggregate);
+            }
+          })
+          .err();
+      }
+    }
+  }
+
+  /// Append `values` to the sensor with `id`, returns the index of the
+  /// first value
+  fn push_batch(&mut self, id: &str, values: &[&[u8]]) -> Result<usize, Error> {
+    if !self.contains(id) {
+      self
+        .sensors
+        .insert(id.to_owned(), MMAV::new(&format!("{}/{id}", self.db_path)));
+    }
+
+    let sensor = self.sensors.get_mut(id).unwrap();
+    let index = sensor.push_batch(values)?;
+
+    // the records are removed again, if they cannot be logged, so a
+    // write which fails is not stored
+    if let Err(error) = self.changes.push_records(id, index, values.len()) {
+      sensor.truncate(index);
+      return Err(error);
+    }
+
+    Ok(index)
+  }
 }
 
 impl Default for MMAVDatabase {
@@ -159,40 +300,190 @@ impl IDatabase for MMAVDatabase {
     self.sensors.contains_key(id)
   }
 
-  fn get(&mut self, id: &str, rec_id: usize) -> Vec<u8> {
+  fn len(&self, id: &str) -> usize {
+    self.sensors.get(id).map(|x| x.len()).unwrap_or_default()
+  }
+
+  fn get(&self, id: &str, rec_id: usize) -> Vec<u8> {
     if !self.contains(id) {
       return Default::default();
     }
 
-    self.sensors.get_mut(id).unwrap().get(rec_id)
+    self.sensors[id].get(rec_id)
   }
 
-  fn post(&mut self, id: &str, value: &[u8]) {
-    if !self.contains(id) {
-      self
-        .sensors
-        .insert(id.to_owned(), MMAV::new(&format!("{}/{id}", self.db_path)));
+  fn post(&mut self, id: &str, value: &[u8]) -> Result<usize, Error> {
+    self.post_batch(id, &[value]).map(|x| x[0])
+  }
+
+  fn post_batch(
+    &mut self,
+    id: &str,
+    values: &[&[u8]],
+  ) -> Result<Vec<usize>, Error> {
+    validate_id(id)?;
+
+    if let Some(schema) = self.schemas.get(id) {
+      for value in values {
+        schema.validate(value)?;
+      }
     }
 
-    if !self.aggregates.contains_key(id) {
-      self.aggregates.insert(
-        id.to_string(),
-        std::sync::Arc::new(std::sync::Mutex::new(
-          serde_json::json!({}).to_string().as_bytes().to_owned(),
-        )),
+    if values.is_empty() {
+      return Ok(Default::default());
+    }
+
+    let index = self.push_batch(id, values)?;
+    self.aggregate(id, values);
+
+    Ok((index..index + values.len()).collect())
+  }
+
+  fn post_many(
+    &mut self,
+    records: &[(&str, &[u8])],
+  ) -> Result<Vec<usize>, Error> {
+    for (id, value) in records {
+      validate_id(id)?;
+      if let Some(schema) = self.schemas.get(*id) {
+        schema.validate(value)?;
+      }
+    }
+
+    let mut result = vec![0; records.len()];
+
+    for (id, positions) in group_by_sensor(records) {
+      let values = positions.iter().map(|x| records[*x].1).collect::<Vec<_>>();
+
+      for (position, rec_id) in
+        positions.iter().zip(self.post_batch(id, &values)?)
+      {
+        result[*position] = rec_id;
+      }
+    }
+
+    Ok(result)
+  }
+
+  fn post_with_key(
+    &mut self,
+    id: &str,
+    key: &str,
+    value: &[u8],
+  ) -> Result<usize, Error> {
+    validate_id(id)?;
+
+    if !self.idempotency_windows.contains_key(id) {
+      self.idempotency_windows.insert(
+        id.to_owned(),
+        IdempotencyWindow::new(
+          &format!("{}/{id}/idempotency", self.db_path),
+          IDEMPOTENCY_WINDOW_SIZE,
+        ),
       );
     }
 
-    if let Some(aggregate) = self.aggregates.get(id) {
-      if let Some(x) = self.aggregates_fn.get(id) {
-        x.lock().map(|f| f(id, value, aggregate)).err();
+    if let Some(rec_id) = self.idempotency_windows[id].get(key) {
+      return Ok(rec_id);
+    }
+
+    let rec_id = self.post(id, value)?;
+    self.idempotency_windows.get_mut(id).unwrap().insert(key, rec_id);
+
+    Ok(rec_id)
+  }
+
+  fn commit(&mut self, batch: &WriteBatch) -> Result<Vec<usize>, Error> {
+    let records = batch.records();
+
+    for (id, value) in &records {
+      validate_id(id)?;
+      if let Some(schema) = self.schemas.get(*id) {
+        schema.validate(value)?;
+      }
+    }
+
+    if records.is_empty() {
+      return Ok(Default::default());
+    }
+
+    let groups = group_by_sensor(&records);
+
+    let mut lens = groups
+      .iter()
+      .map(|(id, _)| {
+        (
+          id.to_string(),
+          self.sensors.get(*id).map(|x| x.len()).unwrap_or_default(),
+        )
+      })
+      .collect::<HashMap<_, _>>();
+    let changes_len = self.changes.len();
+    lens.insert(CHANGE_LOG_ID.to_owned(), changes_len);
+
+    let created = groups
+      .iter()
+      .filter(|(id, _)| !self.contains(id))
+      .map(|(id, _)| id.to_string())
+      .collect::<Vec<_>>();
+
+    self.commit_log.begin(&lens, &created)?;
+
+    let mut result = vec![0; records.len()];
+
+    let committed = (|| {
+      for (id, positions) in &groups {
+        let values =
+          positions.iter().map(|x| records[*x].1).collect::<Vec<_>>();
+
+        let index = self.push_batch(id, &values)?;
+        for (position, rec_id) in positions.iter().zip(index..) {
+          result[*position] = rec_id;
+        }
       }
+
+      for (id, _) in &groups {
+        self.sensors[*id].flush()?;
+      }
+      self.changes.flush()?;
+
+      self.commit_log.end()
+    })();
+
+    if let Err(error) = committed {
+      // the commit log is kept, if the batch cannot be rolled back, so
+      // it is rolled back again when the database is opened
+      let rolled_back = (|| {
+        for (id, len) in &lens {
+          if let Some(x) = self.sensors.get_mut(id) {
+            x.truncate(*len);
+            x.flush()?;
+          }
+        }
+        for id in &created {
+          self.sensors.remove(id);
+          remove_sensor_dir(&format!("{}/{id}", self.db_path))?;
+        }
+        self.changes.truncate(changes_len);
+        self.changes.flush()?;
+
+        self.commit_log.end()
+      })();
+
+      return Err(rolled_back.err().unwrap_or(error));
     }
 
-    self.sensors.get_mut(id).unwrap().push(value);
+    // aggregates are only applied once the batch is committed, so a
+    // rolled back batch leaves them unchanged
+    for (id, positions) in &groups {
+      let values = positions.iter().map(|x| records[*x].1).collect::<Vec<_>>();
+      self.aggregate(id, &values);
+    }
+
+    Ok(result)
   }
 
-  fn get_meta(&mut self, id: &str) -> Vec<u8> {
+  fn get_meta(&self, id: &str) -> Vec<u8> {
     if !self.contains(id) {
       return Default::default();
     }
@@ -203,20 +494,25 @@ impl IDatabase for MMAVDatabase {
     }
   }
 
-  fn post_meta(&mut self, id: &str, data: Vec<u8>) {
+  fn post_meta(&mut self, id: &str, data: Vec<u8>) -> Result<(), Error> {
+    validate_id(id)?;
+    let schema = SensorSchema::from_meta(&data)?;
+
     if !self.contains(id) {
       self
         .sensors
         .insert(id.to_owned(), MMAV::new(&format!("{}/{id}", self.db_path)));
     }
 
+    self.changes.push_meta(id, &data)?;
+
     let file_name = format!("{}/{id}/meta", self.db_path);
 
     let mut file = std::fs::OpenOptions::new()
       .read(true)
       .write(true)
       .create(true)
-      .truncate(false)
+      .truncate(true)
       .open(&file_name)
       .unwrap_or_else(|error| {
         if error.kind() == std::io::ErrorKind::NotFound {
@@ -236,13 +532,20 @@ impl IDatabase for MMAVDatabase {
           .read(true)
           .write(true)
           .create(true)
-          .truncate(false)
+          .truncate(true)
           .open(file_name)
           .unwrap()
       });
 
     file.write_all(&data).unwrap_or_default();
     self.meta.insert(id.to_owned(), data);
+
+    match schema {
+      Some(x) => self.schemas.insert(id.to_owned(), x),
+      None => self.schemas.remove(id),
+    };
+
+    Ok(())
   }
 
   fn get_aggregates(&self, id: &str) -> Vec<u8> {
@@ -253,7 +556,7 @@ impl IDatabase for MMAVDatabase {
     self.aggregates[id].lock().unwrap().clone()
   }
 
-  fn get_latest(&mut self, id: &str) -> Vec<u8> {
+  fn get_latest(&self, id: &str) -> Vec<u8> {
     if !self.contains(id) {
       return Default::default();
     }
@@ -261,23 +564,35 @@ impl IDatabase for MMAVDatabase {
     self.sensors.get(id).unwrap().last()
   }
 
-  fn get_latest_with_limit(&mut self, id: &str, limit: usize) -> Vec<Vec<u8>> {
+  fn get_latest_with_limit(&self, id: &str, limit: usize) -> Vec<Vec<u8>> {
     if !self.contains(id) {
       return Default::default();
     }
 
-    self.sensors.get_mut(id).unwrap().last_limit(limit)
+    self.sensors[id].last_limit(limit)
   }
 
-  fn get_range(&mut self, id: &str, start: usize, end: usize) -> Vec<Vec<u8>> {
+  fn get_range(&self, id: &str, start: usize, end: usize) -> Vec<Vec<u8>> {
     if !self.contains(id) {
       return Default::default();
     }
 
-    self.sensors.get_mut(id).unwrap().range(start, end)
+    self.sensors[id].range(start, end)
   }
 
-  fn get_all_meta(&mut self) -> std::collections::HashMap<&str, Vec<u8>> {
+  fn iter_range<'a>(
+    &'a self,
+    id: &'a str,
+    start: usize,
+    end: usize,
+  ) -> Box<dyn DoubleEndedIterator<Item = Vec<u8>> + 'a> {
+    match self.sensors.get(id) {
+      Some(x) => Box::new(x.iter_range(start..=end).map(|x| x.to_vec())),
+      None => Box::new(std::iter::empty()),
+    }
+  }
+
+  fn get_all_meta(&self) -> std::collections::HashMap<&str, Vec<u8>> {
     let mut result: std::collections::HashMap<&str, Vec<u8>> =
       Default::default();
 
@@ -299,11 +614,11 @@ impl IDatabase for MMAVDatabase {
     result
   }
 
-  fn get_all_latest(&mut self) -> std::collections::HashMap<&str, Vec<u8>> {
+  fn get_all_latest(&self) -> std::collections::HashMap<&str, Vec<u8>> {
     let mut result: std::collections::HashMap<&str, Vec<u8>> =
       Default::default();
 
-    for (id, sensor) in &mut self.sensors {
+    for (id, sensor) in &self.sensors {
       result.insert(id, sensor.last());
     }
 
@@ -311,13 +626,13 @@ impl IDatabase for MMAVDatabase {
   }
 
   fn get_all_latest_with_limit(
-    &mut self,
+    &self,
     limit: usize,
   ) -> std::collections::HashMap<&str, Vec<Vec<u8>>> {
     let mut result: std::collections::HashMap<&str, Vec<Vec<u8>>> =
       Default::default();
 
-    for (id, sensor) in &mut self.sensors {
+    for (id, sensor) in &self.sensors {
       let item = sensor.last_limit(limit);
       if !item.is_empty() {
         result.insert(id, item);
@@ -326,4 +641,231 @@ impl IDatabase for MMAVDatabase {
 
     result
   }
+
+  fn snapshot(&self) -> Snapshot {
+    Snapshot::new(
+      self.sensors.iter().map(|(id, x)| (id.to_owned(), x.len())).collect(),
+    )
+  }
+
+  fn changes_since(&self, lsn: usize, limit: usize) -> Vec<Change> {
+    let mut result = self.changes.since(lsn, limit);
+
+    for change in &mut result {
+      if let Some(rec_id) = change.rec_id {
+        change.value = self.get(&change.id, rec_id);
+      }
+    }
+
+    result
+  }
+
+  fn get_offset(&self, consumer: &str, id: &str) -> usize {
+    match self.consumer_offsets.get(id) {
+      Some(x) => x.get(consumer),
+      None => Default::default(),
+    }
+  }
+
+  fn poll(
+    &self,
+    consumer: &str,
+    id: &str,
+    max: usize,
+  ) -> (usize, Vec<(usize, Vec<u8>)>) {
+    if !self.contains(id) {
+      return Default::default();
+    }
+
+    let sensor = &self.sensors[id];
+    let offset = self.get_offset(consumer, id);
+    let end = sensor.len().min(offset.saturating_add(max));
+
+    (offset, (offset..end).map(|x| (x, sensor.get(x))).collect())
+  }
+
+  fn commit_offset(
+    &mut self,
+    consumer: &str,
+    id: &str,
+    offset: usize,
+  ) -> Result<(), Error> {
+    if offset > self.len(id) {
+      return Err(Error::IndexOutOfRange);
+    }
+
+    if !self.contains(id) {
+      return Ok(());
+    }
+
+    if !self.consumer_offsets.contains_key(id) {
+      self.consumer_offsets.insert(
+        id.to_owned(),
+        ConsumerOffsets::new(&format!("{}/{id}/offsets", self.db_path)),
+      );
+    }
+
+    self.consumer_offsets.get_mut(id).unwrap().commit(consumer, offset)
+  }
+}
+
+#[test]
+fn test_get_ref() {
+  let db_path = ".temp/test/mmav_database/test_get_ref";
+  let mut db = MMAVDatabase::new_with_all(db_path, Default::default());
+
+  let id = "test-0";
+  assert_eq!(db.get_ref(id, 0), None);
+
+  db.post(id, b"{\"n\":0}").unwrap();
+  db.post(id, b"{\"n\":1}").unwrap();
+
+  let record = db.get_ref(id, 1).unwrap();
+  assert_eq!(&*record, b"{\"n\":1}");
+  assert_eq!(record, RecordRef::from(db.get(id, 1)));
+  assert_eq!(db.get_ref(id, 2), None);
+
+  drop(db);
+  assert_eq!(&*record, b"{\"n\":1}");
+
+  std::fs::remove_dir_all(db_path).unwrap_or_default();
+}
+
+#[test]
+fn test_iter_range_ref() {
+  let db_path = ".temp/test/mmav_database/test_iter_range_ref";
+  let mut db = MMAVDatabase::new_with_all(db_path, Default::default());
+
+  let id = "test-0";
+  assert_eq!(db.iter_range_ref(id, 0, 9).count(), 0);
+
+  let values = (0..10010)
+    .map(|x| format!("{{\"n\":{x}}}").into_bytes())
+    .collect::<Vec<_>>();
+  db.post_batch(id, &values.iter().map(|x| &x[..]).collect::<Vec<_>>())
+    .unwrap();
+
+  assert_eq!(
+    db.iter_range_ref(id, 9995, 10004).collect::<Vec<_>>(),
+    values[9995..=10004]
+      .iter()
+      .cloned()
+      .map(RecordRef::from)
+      .collect::<Vec<_>>()
+  );
+  assert_eq!(
+    db.iter_range_ref(id, 9995, 10004).rev().collect::<Vec<_>>(),
+    values[9995..=10004]
+      .iter()
+      .rev()
+      .cloned()
+      .map(RecordRef::from)
+      .collect::<Vec<_>>()
+  );
+  assert_eq!(db.iter_range_ref(id, 10005, usize::MAX).count(), 5);
+
+  std::fs::remove_dir_all(db_path).unwrap_or_default();
+}
+
+#[test]
+fn test_post_batch() {
+  let db_path = ".temp/test/mmav_database/test_post_batch";
+  let mut db = MMAVDatabase::new_with_all(db_path, Default::default());
+
+  let id = "test-0";
+  assert_eq!(db.post_batch(id, &[]).unwrap(), Vec::<usize>::new());
+  assert_eq!(db.post_batch(id, &[b"{\"n\":0}", b"{\"n\":1}"]).unwrap(), [0, 1]);
+
+  let values = (2..10010)
+    .map(|x| format!("{{\"n\":{x}}}").into_bytes())
+    .collect::<Vec<_>>();
+  let rec_ids = db
+    .post_batch(id, &values.iter().map(|x| &x[..]).collect::<Vec<_>>())
+    .unwrap();
+  assert_eq!(rec_ids, (2..10010).collect::<Vec<_>>());
+  assert_eq!(db.get(id, 10009), b"{\"n\":10009}");
+
+  let value = vec![b' '; 15_000_000];
+  assert!(matches!(db.post_batch(id, &[b"{}", &value]), Err(Error::FileFull)));
+  assert!(matches!(db.post(id, &value), Err(Error::FileFull)));
+  assert_eq!(db.len(id), 10010);
+
+  std::fs::remove_dir_all(db_path).unwrap_or_default();
+}
+
+#[test]
+fn test_post_many() {
+  let db_path = ".temp/test/mmav_database/test_post_many";
+  let mut db = MMAVDatabase::new_with_all(db_path, Default::default());
+
+  let (id, id0) = ("test-0", "test-1");
+  assert_eq!(
+    db.post_many(&[
+      (id, b"{\"n\":0}"),
+      (id0, b"{\"n\":1}"),
+      (id, b"{\"n\":2}")
+    ])
+    .unwrap(),
+    [0, 0, 1]
+  );
+  assert_eq!(db.get_latest(id), b"{\"n\":2}");
+  assert_eq!(db.get_latest(id0), b"{\"n\":1}");
+
+  db.post_meta(id0, br#"{"schema":{"required":["n"]}}"#.to_vec()).unwrap();
+  assert!(matches!(
+    db.post_many(&[(id, b"{\"n\":3}"), (id0, b"{}")]),
+    Err(Error::SchemaViolation(_))
+  ));
+  assert!(matches!(
+    db.post_many(&[(id, b"{\"n\":3}"), (".changes", b"{}")]),
+    Err(Error::InvalidId(_))
+  ));
+  assert_eq!(db.len(id), 2);
+  assert_eq!(db.len(id0), 1);
+
+  std::fs::remove_dir_all(db_path).unwrap_or_default();
+}
+
+#[test]
+fn test_commit_rollback() {
+  let db_path = ".temp/test/mmav_database/test_commit_rollback";
+  let mut db = MMAVDatabase::new_with_all(db_path, Default::default());
+
+  let (id, id0) = ("test-0", "test-1");
+  db.post(id, b"{\"n\":0}").unwrap();
+
+  let value = vec![b'0'; 15_000_000];
+  assert!(db
+    .commit(
+      crate::db::WriteBatch::new().post(id, b"{\"n\":1}").post(id0, &value)
+    )
+    .is_err());
+  assert_eq!(db.len(id), 1);
+  assert!(!db.contains(id0));
+  assert!(!std::path::Path::new(&format!("{db_path}/{id0}")).exists());
+  assert!(!std::path::Path::new(&format!("{db_path}/.commit")).exists());
+
+  std::fs::write(
+    format!("{db_path}/.commit"),
+    serde_json::json!({ "lens": { id: 0 }, "created": [id] }).to_string(),
+  )
+  .unwrap();
+  drop(db);
+
+  let db = MMAVDatabase::new_with_all(db_path, Default::default());
+  assert!(!db.contains(id));
+  assert!(!std::path::Path::new(&format!("{db_path}/.commit")).exists());
+
+  std::fs::remove_dir_all(db_path).unwrap_or_default();
+}
+
+#[test]
+#[should_panic(expected = "failed to recover the commit log")]
+fn test_commit_log_corrupted() {
+  let db_path = ".temp/test/mmav_database/test_commit_log_corrupted";
+  std::fs::remove_dir_all(db_path).unwrap_or_default();
+  std::fs::create_dir_all(db_path).unwrap();
+  std::fs::write(format!("{db_path}/.commit"), b"{").unwrap();
+
+  MMAVDatabase::new_with_all(db_path, Default::default());
 }
@@ -1,3 +1,4 @@
+use crate::db::RecordRef;
 use crate::errors::Error;
 
 /// Memory Mapped Append-only Vector Unit
@@ -6,18 +7,26 @@ use crate::errors::Error;
 /// regular memory.
 /// It implements a simple statically sized append-only vector.
 ///
+/// The mapping is shared with the `RecordRef` handles of its records,
+/// which stay valid after the unit is dropped, and only read from it.
+/// The unit is the only writer of the mapping, and never writes to the
+/// payload of a record once it is pushed, not even when the record is
+/// truncated, so the handles can read it while the unit keeps
+/// appending. The file must not be modified by other means while it is
+/// mapped.
+///
 /// ## Examples
 /// ```ignore
 /// let mut unit = MMAVUnit::new("test-0/0", 4000000, 80008);
 ///
 /// let data = b"{\"key\":\"value\"}";
-/// unit.push(data).unwrap_or_default();
+/// unit.push_batch(&[data]);
 /// assert_eq!(unit.last(), data);
 /// ```
 pub struct MMAVUnit {
   seek: usize,
   seek_index: usize,
-  mmap: memmap2::MmapMut,
+  mmap: std::sync::Arc<memmap2::MmapRaw>,
   data_start_index: usize,
 }
 impl MMAVUnit {
@@ -33,7 +42,7 @@ impl MMAVUnit {
   /// let mut unit = MMAVUnit::new("test-0/0", 4000000, 80008);
   ///
   /// let data = b"{\"key\":\"value\"}";
-  /// unit.push(data).unwrap_or_default();
+  /// unit.push_batch(&[data]);
   /// assert_eq!(unit.last(), data);
   /// ```
   pub fn new(
@@ -57,79 +66,159 @@ impl MMAVUnit {
 
     file.set_len(size as u64)?;
 
-    let mut mmap = unsafe { memmap2::MmapMut::map_mut(&file)? };
+    let mmap = memmap2::MmapRaw::map_raw(&file)?;
     mmap.advise(memmap2::Advice::Random).unwrap_or_default();
 
-    let mut seek = data_start_index;
+    let mut result = Self {
+      seek: data_start_index,
+      seek_index: 8,
+      mmap: std::sync::Arc::new(mmap),
+      data_start_index,
+    };
+
     if (file_exists) {
-      seek = u32::from_ne_bytes(mmap[0..4].try_into()?) as usize;
+      result.seek = u32::from_ne_bytes(result.bytes(0, 4).try_into()?) as usize;
 
-      if seek > mmap.len() {
+      if result.seek > result.mmap.len() {
         return Err(Error::IndexOutOfRange);
       }
     }
 
-    let mut seek_index = 8;
     if (file_exists) {
-      seek_index = u32::from_ne_bytes(mmap[4..8].try_into()?) as usize;
+      result.seek_index =
+        u32::from_ne_bytes(result.bytes(4, 8).try_into()?) as usize;
 
-      if seek_index > data_start_index {
+      if result.seek_index > data_start_index {
         return Err(Error::IndexOutOfRange);
       }
     }
 
-    if (mmap[seek] == 0) {
-      mmap[seek] = 0;
+    return Ok(result);
+  }
+
+  /// Get the bytes of the mapping from `start` to `end`
+  ///
+  /// ## Panics
+  /// if `start` to `end` is not within the mapping
+  fn bytes(&self, start: usize, end: usize) -> &[u8] {
+    assert!(start <= end && end <= self.mmap.len(), "index out of range");
+
+    // SAFETY: the bytes are within the mapping. The unit is the only
+    // writer of the mapping, and its writes take `&mut self`, so the bytes
+    // cannot be written while the returned slice borrows `self`.
+    unsafe {
+      std::slice::from_raw_parts(self.mmap.as_ptr().add(start), end - start)
     }
+  }
 
-    return Ok(Self { seek, seek_index, mmap, data_start_index });
+  /// Write `value` to the mapping at `offset`
+  ///
+  /// ## Panics
+  /// if `value` does not fit in the mapping at `offset`
+  fn write(&mut self, offset: usize, value: &[u8]) {
+    assert!(offset + value.len() <= self.mmap.len(), "index out of range");
+
+    // SAFETY: the bytes are within the mapping, and are either the header,
+    // the index, or past `seek`, which is only ever moved forward. The
+    // `RecordRef` handles of the mapping only point to payloads before
+    // `seek`, so none of them can observe the write.
+    unsafe {
+      std::ptr::copy_nonoverlapping(
+        value.as_ptr(),
+        self.mmap.as_mut_ptr().add(offset),
+        value.len(),
+      );
+    }
   }
 
-  /// Set seek to `len`
+  /// Push as many of `values` to vector as fit, in order
+  ///
+  /// Writes the payloads and index entries of all pushed values, and
+  /// updates the header once, after the payloads are written.
+  ///
+  /// Returns the number of values pushed.
   ///
   /// ## Examples
   /// ```ignore
-  /// let value: [u8; 0] = Default::default();
-  /// self.set_seek(value.len());
+  /// let mut unit = MMAVUnit::new("test-0/0", 4000000, 80008);
+  ///
+  /// let data = b"{\"key\":\"value\"}";
+  /// assert_eq!(unit.push_batch(&[data, data]), 2);
+  /// assert_eq!(unit.len(), 2);
   /// ```
-  fn set_seek(&mut self, len: usize) {
-    let end = self.seek + len;
+  pub fn push_batch(&mut self, values: &[&[u8]]) -> usize {
+    let mut seek = self.seek;
+    let mut seek_index = self.seek_index;
+    let mut count = 0;
+
+    for value in values {
+      if (seek_index - 8) / 8 > 9999 {
+        break;
+      }
 
-    self.mmap[self.seek_index..(self.seek_index + 4)]
-      .clone_from_slice(&(self.seek as u32).to_ne_bytes());
-    self.mmap[(self.seek_index + 4)..(self.seek_index + 8)]
-      .clone_from_slice(&(end as u32).to_ne_bytes());
+      if seek + value.len() > self.mmap.len() {
+        break;
+      }
 
-    self.seek_index += 8;
-    self.mmap[4..8].clone_from_slice(&(self.seek_index as u32).to_ne_bytes());
+      let end = seek + value.len();
+
+      self.write(seek, value);
+      self.write(seek_index, &(seek as u32).to_ne_bytes());
+      self.write(seek_index + 4, &(end as u32).to_ne_bytes());
+
+      seek = end;
+      seek_index += 8;
+      count += 1;
+    }
 
-    self.seek = end;
-    self.mmap[0..4].clone_from_slice(&(self.seek as u32).to_ne_bytes());
+    if count == 0 {
+      return count;
+    }
+
+    self.seek_index = seek_index;
+    self.write(4, &(self.seek_index as u32).to_ne_bytes());
+
+    self.seek = seek;
+    self.write(0, &(self.seek as u32).to_ne_bytes());
+
+    count
   }
 
-  /// Push `value` to vector
+  /// Truncate vector to `len` items
+  ///
+  /// Only the index is truncated, the payloads of the removed items are
+  /// kept and their space is not reused, so `RecordRef` handles to them
+  /// stay valid.
   ///
   /// ## Examples
   /// ```ignore
   /// let mut unit = MMAVUnit::new("test-0/0", 4000000, 80008);
   ///
   /// let data = b"{\"key\":\"value\"}";
-  /// unit.push(data).unwrap_or_default();
-  /// assert_eq!(unit.last(), data);
+  /// unit.push_batch(&[data, data]);
+  /// unit.truncate(1);
+  /// assert_eq!(unit.len(), 1);
   /// ```
-  pub fn push(&mut self, value: &[u8]) -> Result<(), Error> {
-    if self.len() > 9999 {
-      return Err(Error::ArrayFull);
-    }
-
-    if self.seek + value.len() > self.mmap.len() {
-      return Err(Error::FileFull);
+  pub fn truncate(&mut self, len: usize) {
+    if len >= self.len() {
+      return;
     }
 
-    self.mmap[self.seek..self.seek + value.len()].clone_from_slice(value);
-    self.set_seek(value.len());
+    self.seek_index = 8 * len + 8;
+    self.write(4, &(self.seek_index as u32).to_ne_bytes());
+  }
 
-    Ok(())
+  /// Flush outstanding changes of vector to disk
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let mut unit = MMAVUnit::new("test-0/0", 4000000, 80008);
+  ///
+  /// unit.push_batch(&[b"{\"key\":\"value\"}"]);
+  /// unit.flush().unwrap();
+  /// ```
+  pub fn flush(&self) -> Result<(), Error> {
+    Ok(self.mmap.flush()?)
   }
 
   /// Get `index` from vector
@@ -139,10 +228,24 @@ impl MMAVUnit {
   /// let mut unit = MMAVUnit::new("test-0/0", 4000000, 80008);
   ///
   /// let data = b"{\"key\":\"value\"}";
-  /// unit.push(data).unwrap_or_default();
+  /// unit.push_batch(&[data]);
   /// assert_eq!(unit.get(0), data);
   /// ```
   pub fn get(&self, index: usize) -> Result<Vec<u8>, Error> {
+    self.get_ref(index).map(|x| x.to_vec())
+  }
+
+  /// Get `index` from vector, without copying it out of the mapping
+  ///
+  /// ## Examples
+  /// ```ignore
+  /// let mut unit = MMAVUnit::new("test-0/0", 4000000, 80008);
+  ///
+  /// let data = b"{\"key\":\"value\"}";
+  /// unit.push_batch(&[data]);
+  /// assert_eq!(&*unit.get_ref(0).unwrap(), data);
+  /// ```
+  pub fn get_ref(&self, index: usize) -> Result<RecordRef, Error> {
     if self.seek_index == 8 {
       return Err(Error::ArrayEmpty);
     }
@@ -157,18 +260,18 @@ impl MMAVUnit {
 
     let i = 8 * index + 8;
 
-    let start = u32::from_ne_bytes(self.mmap[i..i + 4].try_into()?) as usize;
-    let end = u32::from_ne_bytes(self.mmap[i + 4..i + 8].try_into()?) as usize;
+    let start = u32::from_ne_bytes(self.bytes(i, i + 4).try_into()?) as usize;
+    let end = u32::from_ne_bytes(self.bytes(i + 4, i + 8).try_into()?) as usize;
 
     if start < self.data_start_index || start > self.mmap.len() {
       return Err(Error::IndexOutOfRange);
     }
 
-    if end < self.data_start_index || end > self.mmap.len() {
+    if end < start || end > self.mmap.len() {
       return Err(Error::IndexOutOfRange);
     }
 
-    Ok((self.mmap[start..end]).to_vec())
+    Ok(RecordRef::mapped(self.mmap.clone(), start, end))
   }
 
   /// Get last item in vector
@@ -180,7 +283,7 @@ impl MMAVUnit {
   /// let mut unit = MMAVUnit::new("test-0/0", 4000000, 80008);
   ///
   /// let data = b"{\"key\":\"value\"}";
-  /// unit.push(data).unwrap_or_default();
+  /// unit.push_batch(&[data]);
   /// assert_eq!(unit.last(), data);
   /// ```
   pub fn last(&self) -> Vec<u8> {
@@ -198,7 +301,7 @@ impl MMAVUnit {
   /// let mut unit = MMAVUnit::new("test-0/0", 4000000, 80008);
   ///
   /// let data = b"{\"key\":\"value\"}";
-  /// unit.push(data).unwrap_or_default();
+  /// unit.push_batch(&[data]);
   /// assert_eq!(unit.len(), 1);
   /// ```
   pub fn len(&self) -> usize {
@@ -209,3 +312,30 @@ impl MMAVUnit {
     (self.seek_index - 8) / 8
   }
 }
+
+#[test]
+fn test_truncate() {
+  let file_name = ".temp/test/mmav_unit/test_truncate/0";
+  let mut unit = MMAVUnit::new(file_name, 4000000, 80008).unwrap();
+
+  unit.push_batch(&[b"{\"n\":0}", b"{\"n\":1}"]);
+  let record = unit.get_ref(1).unwrap();
+
+  unit.truncate(1);
+  assert_eq!(unit.len(), 1);
+  assert!(matches!(unit.get_ref(1), Err(Error::IndexOutOfBounds)));
+
+  unit.push_batch(&[b"{\"n\":2}"]);
+  assert_eq!(&*unit.get_ref(1).unwrap(), b"{\"n\":2}");
+  assert_eq!(&*record, b"{\"n\":1}");
+
+  drop(unit);
+  assert_eq!(&*record, b"{\"n\":1}");
+
+  let unit = MMAVUnit::new(file_name, 4000000, 80008).unwrap();
+  assert_eq!(unit.len(), 2);
+  assert_eq!(unit.last(), b"{\"n\":2}");
+
+  std::fs::remove_dir_all(".temp/test/mmav_unit/test_truncate")
+    .unwrap_or_default();
+}
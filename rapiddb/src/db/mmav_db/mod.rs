@@ -1,5 +1,9 @@
 //! RapidDB Databases
 
+mod change_log;
+mod commit_log;
+mod consumer_offsets;
+mod idempotency_window;
 mod mmav;
 mod mmav_async_database;
 mod mmav_database;
@@ -7,3 +11,23 @@ mod mmav_unit;
 
 pub use mmav_async_database::MMAVAsyncDatabase;
 pub use mmav_database::MMAVDatabase;
+
+/// Group `records` by sensor id, in order of first appearance, with the
+/// positions of the records of each sensor in `records`
+fn group_by_sensor<'a>(
+  records: &[(&'a str, &[u8])],
+) -> Vec<(&'a str, Vec<usize>)> {
+  let mut result: Vec<(&str, Vec<usize>)> = vec![];
+  let mut groups: std::collections::HashMap<&str, usize> = Default::default();
+
+  for (position, (id, _)) in records.iter().enumerate() {
+    let group = *groups.entry(id).or_insert_with(|| {
+      result.push((id, vec![]));
+      result.len() - 1
+    });
+
+    result[group].1.push(position);
+  }
+
+  result
+}
@@ -1,8 +1,43 @@
 //! RapidDB Databases
 
+mod async_database_adapter;
+mod blocking_pool;
+mod change;
 mod database_test_factory;
 mod mmav_db;
+mod notifier;
+mod record_ref;
+mod sensor_schema;
+mod snapshot;
+mod subscription;
+mod write_batch;
 
+pub use async_database_adapter::AsyncDatabaseAdapter;
+pub use blocking_pool::BlockingPool;
+pub use change::Change;
 pub use database_test_factory::DatabaseTestFactory;
 pub use mmav_db::MMAVAsyncDatabase;
 pub use mmav_db::MMAVDatabase;
+pub use notifier::Notification;
+pub use notifier::Notifier;
+pub use record_ref::RecordRef;
+pub use sensor_schema::SensorSchema;
+pub use snapshot::Snapshot;
+pub use subscription::Subscription;
+pub use subscription::SubscriptionEvent;
+pub use subscription::SubscriptionFilter;
+pub use write_batch::WriteBatch;
+
+/// Checks that `id` can name a sensor
+///
+/// Each sensor is stored in a directory named by its id, so an id may
+/// not be empty or contain a path separator, and may not start with a
+/// dot, which is reserved for the internal logs of the database, such as
+/// the change log and the commit log.
+pub(crate) fn validate_id(id: &str) -> Result<(), crate::errors::Error> {
+  if id.is_empty() || id.starts_with('.') || id.contains(['/', '\\']) {
+    return Err(crate::errors::Error::InvalidId(id.to_owned()));
+  }
+
+  Ok(())
+}
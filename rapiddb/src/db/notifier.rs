@@ -0,0 +1,193 @@
+use crate::db::subscription::Written;
+use crate::db::Subscription;
+use crate::db::SubscriptionFilter;
+
+/// Number of notifications buffered for every receiver, before a slow
+/// receiver lags behind
+const NOTIFICATION_CAPACITY: usize = 1024;
+
+/// Number of records buffered for every subscription, before a slow
+/// subscription lags behind on the records of its filter
+const SUBSCRIPTION_CAPACITY: usize = 1024;
+
+/// Notification
+///
+/// A write to the database, as broadcast by a `Notifier`. Notifications
+/// only signal that a sensor changed, the records themselves are read
+/// from the database, so a receiver which lags behind can catch up by
+/// reading from the last index it has seen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notification {
+  /// Records up to and including index `rec_id` were posted to the
+  /// sensor with `id`
+  Record { id: String, rec_id: usize },
+  /// Metadata was posted to the sensor with `id`
+  Meta { id: String },
+}
+impl Notification {
+  /// Get the id of the sensor which was written
+  ///
+  /// ## Examples
+  /// ```
+  /// let notification =
+  ///   rapiddb::db::Notification::Meta { id: "test-0".to_owned() };
+  /// assert_eq!(notification.id(), "test-0");
+  /// ```
+  pub fn id(&self) -> &str {
+    match self {
+      Self::Record { id, .. } => id,
+      Self::Meta { id } => id,
+    }
+  }
+}
+
+/// Notifier
+///
+/// Broadcasts a `Notification` for every write to a database to any
+/// number of receivers, and the records themselves to any number of
+/// subscriptions, backed by bounded broadcast channels. Subscriptions
+/// have a channel per filter, which only gets the records selected by
+/// the filter, so a subscription does not lag behind on the records of
+/// other sensors.
+///
+/// ## Examples
+/// ```
+/// # tokio_test::block_on(async {
+/// let notifier = rapiddb::db::Notifier::default();
+/// let mut receiver = notifier.subscribe();
+///
+/// notifier.records(&[("test-0", 0, &b"{}"[..]), ("test-0", 1, &b"{}"[..])]);
+///
+/// assert_eq!(
+///   receiver.recv().await.unwrap(),
+///   rapiddb::db::Notification::Record { id: "test-0".to_owned(), rec_id: 1 }
+/// );
+/// # })
+/// ```
+#[derive(Clone)]
+pub struct Notifier {
+  sender: tokio::sync::broadcast::Sender<Notification>,
+  subscriptions: std::sync::Arc<
+    std::sync::Mutex<
+      std::collections::HashMap<
+        SubscriptionFilter,
+        tokio::sync::broadcast::Sender<Written>,
+      >,
+    >,
+  >,
+}
+impl Notifier {
+  /// Receive the notifications of every write from now on
+  ///
+  /// ## Examples
+  /// ```
+  /// let notifier = rapiddb::db::Notifier::default();
+  /// let receiver = notifier.subscribe();
+  /// ```
+  pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<Notification> {
+    self.sender.subscribe()
+  }
+
+  /// Receive the records of the sensors selected by `filter` from now
+  /// on
+  ///
+  /// ## Examples
+  /// ```
+  /// use futures_util::StreamExt;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let notifier = rapiddb::db::Notifier::default();
+  /// let mut subscription =
+  ///   notifier.subscription(rapiddb::db::SubscriptionFilter::All);
+  ///
+  /// notifier.records(&[("test-0", 0, &b"{}"[..])]);
+  ///
+  /// assert_eq!(
+  ///   subscription.next().await,
+  ///   Some(rapiddb::db::SubscriptionEvent::Record {
+  ///     id: "test-0".to_owned(),
+  ///     rec_id: 0,
+  ///     value: b"{}".to_vec(),
+  ///   })
+  /// );
+  /// # })
+  /// ```
+  pub fn subscription(&self, filter: SubscriptionFilter) -> Subscription {
+    let receiver = self
+      .subscriptions
+      .lock()
+      .unwrap()
+      .entry(filter)
+      .or_insert_with(|| {
+        tokio::sync::broadcast::channel(SUBSCRIPTION_CAPACITY).0
+      })
+      .subscribe();
+
+    Subscription::new(receiver)
+  }
+
+  /// Notify that `records`, as sensor id, record index and value, were
+  /// posted
+  ///
+  /// Sends one notification per sensor, with the highest record index,
+  /// and every record to the channels of the filters which select it.
+  ///
+  /// ## Examples
+  /// ```
+  /// let notifier = rapiddb::db::Notifier::default();
+  ///
+  /// notifier.records(&[("test-0", 0, &b"{}"[..]), ("test-1", 0, &b"{}"[..])]);
+  /// ```
+  pub fn records(&self, records: &[(&str, usize, &[u8])]) {
+    let mut subscriptions = self.subscriptions.lock().unwrap();
+    subscriptions.retain(|_, x| x.receiver_count() > 0);
+
+    for (id, rec_id, value) in records {
+      let mut written = None;
+
+      for (filter, sender) in subscriptions.iter() {
+        if filter.matches(id) {
+          let written = written.get_or_insert_with(|| {
+            std::sync::Arc::new(((*id).to_owned(), *rec_id, value.to_vec()))
+          });
+          sender.send(written.clone()).ok();
+        }
+      }
+    }
+    drop(subscriptions);
+
+    let mut result: Vec<(&str, usize)> = vec![];
+
+    for (id, rec_id, _) in records {
+      match result.iter_mut().find(|(x, _)| x == id) {
+        Some(x) => x.1 = x.1.max(*rec_id),
+        None => result.push((id, *rec_id)),
+      }
+    }
+
+    for (id, rec_id) in result {
+      self.sender.send(Notification::Record { id: id.to_owned(), rec_id }).ok();
+    }
+  }
+
+  /// Notify that metadata was posted to the sensor with `id`
+  ///
+  /// ## Examples
+  /// ```
+  /// let notifier = rapiddb::db::Notifier::default();
+  ///
+  /// notifier.meta("test-0");
+  /// ```
+  pub fn meta(&self, id: &str) {
+    self.sender.send(Notification::Meta { id: id.to_owned() }).ok();
+  }
+}
+
+impl Default for Notifier {
+  fn default() -> Self {
+    Self {
+      sender: tokio::sync::broadcast::channel(NOTIFICATION_CAPACITY).0,
+      subscriptions: Default::default(),
+    }
+  }
+}
@@ -0,0 +1,93 @@
+/// Record Reference
+///
+/// A reference-counted handle to the bytes of a record. Records read from
+/// a memory mapped unit point into the mapping, which is kept alive for
+/// as long as any handle to it exists, so reading them does not copy the
+/// record.
+///
+/// ## Examples
+/// ```
+/// let record = rapiddb::db::RecordRef::from(b"{\"key\":\"value\"}".to_vec());
+///
+/// assert_eq!(&*record, b"{\"key\":\"value\"}");
+/// assert_eq!(record.to_vec(), b"{\"key\":\"value\"}");
+/// ```
+#[derive(Clone)]
+pub struct RecordRef {
+  inner: Inner,
+}
+
+#[derive(Clone)]
+enum Inner {
+  /// The bytes from `start` to `end` of a memory mapping
+  Mapped { mmap: std::sync::Arc<memmap2::MmapRaw>, start: usize, end: usize },
+  /// Bytes owned by the handle
+  Owned(std::sync::Arc<Vec<u8>>),
+}
+
+impl RecordRef {
+  /// Point to the bytes from `start` to `end` of `mmap`
+  ///
+  /// The bytes must not be written to for as long as the handle exists,
+  /// which holds for the payload of a record once it is pushed, as units
+  /// never write to it again, see `MMAVUnit`.
+  ///
+  /// ## Panics
+  /// if `start` to `end` is not within `mmap`
+  pub(crate) fn mapped(
+    mmap: std::sync::Arc<memmap2::MmapRaw>,
+    start: usize,
+    end: usize,
+  ) -> Self {
+    assert!(start <= end && end <= mmap.len(), "index out of range");
+
+    Self { inner: Inner::Mapped { mmap, start, end } }
+  }
+}
+
+impl std::ops::Deref for RecordRef {
+  type Target = [u8];
+
+  fn deref(&self) -> &[u8] {
+    match &self.inner {
+      // SAFETY: `mapped` checks that the bytes are within the mapping,
+      // which lives as long as `mmap`, and they are not written to while
+      // the handle exists.
+      Inner::Mapped { mmap, start, end } => unsafe {
+        std::slice::from_raw_parts(mmap.as_ptr().add(*start), end - start)
+      },
+      Inner::Owned(x) => x,
+    }
+  }
+}
+
+impl AsRef<[u8]> for RecordRef {
+  fn as_ref(&self) -> &[u8] {
+    self
+  }
+}
+
+impl From<Vec<u8>> for RecordRef {
+  fn from(value: Vec<u8>) -> Self {
+    Self { inner: Inner::Owned(std::sync::Arc::new(value)) }
+  }
+}
+
+impl Default for RecordRef {
+  fn default() -> Self {
+    Vec::new().into()
+  }
+}
+
+impl PartialEq for RecordRef {
+  fn eq(&self, other: &Self) -> bool {
+    **self == **other
+  }
+}
+impl Eq for RecordRef {}
+
+impl std::fmt::Debug for RecordRef {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_tuple("RecordRef").field(&&**self).finish()
+  }
+}
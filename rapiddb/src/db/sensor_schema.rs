@@ -0,0 +1,82 @@
+use crate::errors::Error;
+
+/// Sensor Schema
+///
+/// A compiled JSON Schema taken from the `schema` key of a sensor's
+/// metadata, which every record posted to the sensor is validated
+/// against.
+///
+/// ## Examples
+/// ```no_run
+/// let meta = br#"{"schema": {"type": "object", "required": ["temp"]}}"#;
+/// let schema = rapiddb::db::SensorSchema::from_meta(meta).unwrap().unwrap();
+///
+/// assert!(schema.validate(br#"{"temp": 4.00}"#).is_ok());
+/// assert!(schema.validate(br#"{"hum": 40}"#).is_err());
+/// ```
+pub struct SensorSchema {
+  validator: jsonschema::Validator,
+}
+impl SensorSchema {
+  /// Compile the `schema` key of the sensor metadata `meta`
+  ///
+  /// Returns `Ok(None)` if `meta` is not a JSON object or has no
+  /// `schema` key, and `Error::InvalidSchema` if the schema does not
+  /// compile.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// let meta = br#"{"schema": {"type": "object"}}"#;
+  /// assert!(rapiddb::db::SensorSchema::from_meta(meta).unwrap().is_some());
+  /// assert!(rapiddb::db::SensorSchema::from_meta(b"{}").unwrap().is_none());
+  /// ```
+  pub fn from_meta(meta: &[u8]) -> Result<Option<Self>, Error> {
+    let schema = match serde_json::from_slice::<serde_json::Value>(meta) {
+      Ok(serde_json::Value::Object(mut x)) => match x.remove("schema") {
+        Some(schema) => schema,
+        None => return Ok(None),
+      },
+      _ => return Ok(None),
+    };
+
+    jsonschema::validator_for(&schema)
+      .map(|validator| Some(Self { validator }))
+      .map_err(|error| Error::InvalidSchema(error.to_string()))
+  }
+
+  /// Validate the record `value` against the schema
+  ///
+  /// Returns `Error::SchemaViolation` listing every violation, where
+  /// each violation is prefixed with the JSON pointer to the offending
+  /// value.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// let meta = br#"{"schema": {"type": "object"}}"#;
+  /// let schema = rapiddb::db::SensorSchema::from_meta(meta).unwrap().unwrap();
+  ///
+  /// assert!(schema.validate(b"[]").is_err());
+  /// ```
+  pub fn validate(&self, value: &[u8]) -> Result<(), Error> {
+    let instance = serde_json::from_slice::<serde_json::Value>(value)
+      .map_err(|error| Error::SchemaViolation(vec![format!("/: {error}")]))?;
+
+    let violations = self
+      .validator
+      .iter_errors(&instance)
+      .map(|error| {
+        let path = error.instance_path.to_string();
+        if path.is_empty() {
+          return format!("/: {error}");
+        }
+        format!("{path}: {error}")
+      })
+      .collect::<Vec<_>>();
+
+    if !violations.is_empty() {
+      return Err(Error::SchemaViolation(violations));
+    }
+
+    Ok(())
+  }
+}
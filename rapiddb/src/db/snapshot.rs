@@ -0,0 +1,65 @@
+/// Snapshot
+///
+/// The length of every sensor in the database, captured at one instant.
+/// Reads as of a snapshot only see the records that were written before
+/// it was taken, so reads across sensors are consistent with each
+/// other.
+///
+/// ## Examples
+/// ```no_run
+/// use crate::rapiddb::traits::IAsyncDatabase;
+///
+/// # tokio_test::block_on(async {
+/// let db = rapiddb::db::MMAVAsyncDatabase::new();
+///
+/// let snapshot = db.snapshot().await;
+/// db.post("test-0", b"{\"key\": \"value\"}").await.unwrap();
+///
+/// assert!(db.get_latest_as_of(&snapshot, "test-0").await.is_empty());
+/// # })
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Snapshot {
+  lens: std::collections::HashMap<String, usize>,
+}
+impl Snapshot {
+  /// Snapshot Constructor
+  ///
+  /// ## Examples
+  /// ```
+  /// let snapshot = rapiddb::db::Snapshot::new(
+  ///   [("test-0".to_owned(), 1)].into(),
+  /// );
+  /// assert_eq!(snapshot.len("test-0"), 1);
+  /// ```
+  pub fn new(lens: std::collections::HashMap<String, usize>) -> Self {
+    Self { lens }
+  }
+
+  /// Get the length of the sensor with `id` when the snapshot was taken
+  ///
+  /// Sensors which did not exist have a length of zero.
+  ///
+  /// ## Examples
+  /// ```
+  /// let snapshot = rapiddb::db::Snapshot::default();
+  /// assert_eq!(snapshot.len("test-0"), 0);
+  /// ```
+  pub fn len(&self, id: &str) -> usize {
+    self.lens.get(id).copied().unwrap_or_default()
+  }
+
+  /// Get the ids of the sensors which existed when the snapshot was
+  /// taken
+  ///
+  /// ## Examples
+  /// ```
+  /// let snapshot = rapiddb::db::Snapshot::new(
+  ///   [("test-0".to_owned(), 1)].into(),
+  /// );
+  /// assert_eq!(snapshot.ids().collect::<Vec<_>>(), vec!["test-0"]);
+  /// ```
+  pub fn ids(&self) -> impl Iterator<Item = &str> {
+    self.lens.keys().map(|x| x.as_str())
+  }
+}
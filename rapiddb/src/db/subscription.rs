@@ -0,0 +1,216 @@
+/// A record as broadcast to subscriptions, the sensor id, record index
+/// and value, shared between all receivers
+pub(crate) type Written = std::sync::Arc<(String, usize, Vec<u8>)>;
+
+/// Sensors selected by a `Subscription`
+///
+/// ## Examples
+/// ```
+/// let filter = rapiddb::db::SubscriptionFilter::Prefix("test-".to_owned());
+/// assert!(filter.matches("test-0"));
+/// assert!(!filter.matches("phase-0"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SubscriptionFilter {
+  /// The sensor with exactly this id
+  Id(String),
+  /// Every sensor with an id starting with this prefix
+  Prefix(String),
+  /// Every sensor
+  All,
+}
+impl SubscriptionFilter {
+  /// Checks if the sensor with `id` is selected
+  ///
+  /// ## Examples
+  /// ```
+  /// let filter = rapiddb::db::SubscriptionFilter::Id("test-0".to_owned());
+  /// assert!(filter.matches("test-0"));
+  /// assert!(!filter.matches("test-1"));
+  /// ```
+  pub fn matches(&self, id: &str) -> bool {
+    match self {
+      Self::Id(x) => id == x,
+      Self::Prefix(x) => id.starts_with(x.as_str()),
+      Self::All => true,
+    }
+  }
+}
+
+/// An event of a `Subscription`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubscriptionEvent {
+  /// The record with `rec_id` and `value` was posted to the sensor with
+  /// `id`
+  Record { id: String, rec_id: usize, value: Vec<u8> },
+  /// The subscription fell behind and this number of records selected by
+  /// its filter were dropped, they can be read from the database
+  Lagged(u64),
+}
+
+/// Subscription
+///
+/// A stream of the records posted to the sensors selected by a
+/// `SubscriptionFilter`, in the order they are written. Records are
+/// buffered in a bounded broadcast channel of the filter, which only
+/// gets the records selected by the filter. A subscription which falls
+/// behind drops the oldest records and gets a `SubscriptionEvent::Lagged`
+/// in their place.
+///
+/// The stream ends once the database is dropped.
+///
+/// ## Examples
+/// ```no_run
+/// use crate::rapiddb::traits::IAsyncDatabase;
+/// use futures_util::StreamExt;
+///
+/// # tokio_test::block_on(async {
+/// let db = rapiddb::db::MMAVAsyncDatabase::new();
+///
+/// let mut subscription =
+///   db.subscribe(rapiddb::db::SubscriptionFilter::Id("test-0".to_owned()));
+/// db.post("test-0", b"{\"key\": \"value\"}").await.unwrap();
+///
+/// assert_eq!(
+///   subscription.next().await,
+///   Some(rapiddb::db::SubscriptionEvent::Record {
+///     id: "test-0".to_owned(),
+///     rec_id: 0,
+///     value: b"{\"key\": \"value\"}".to_vec(),
+///   })
+/// );
+/// # })
+/// ```
+pub struct Subscription {
+  inner: futures_util::stream::BoxStream<'static, SubscriptionEvent>,
+}
+impl Subscription {
+  /// Subscribe to the records received by `receiver`, from the channel
+  /// of a filter
+  pub(crate) fn new(
+    receiver: tokio::sync::broadcast::Receiver<Written>,
+  ) -> Self {
+    let inner =
+      futures_util::stream::unfold(receiver, move |mut receiver| async move {
+        let event = match receiver.recv().await {
+          Ok(x) => SubscriptionEvent::Record {
+            id: x.0.clone(),
+            rec_id: x.1,
+            value: x.2.clone(),
+          },
+          Err(tokio::sync::broadcast::error::RecvError::Lagged(x)) => {
+            SubscriptionEvent::Lagged(x)
+          }
+          Err(tokio::sync::broadcast::error::RecvError::Closed) => {
+            return None;
+          }
+        };
+
+        Some((event, receiver))
+      });
+
+    Self { inner: Box::pin(inner) }
+  }
+}
+
+impl futures_util::Stream for Subscription {
+  type Item = SubscriptionEvent;
+
+  fn poll_next(
+    mut self: std::pin::Pin<&mut Self>,
+    cx: &mut std::task::Context<'_>,
+  ) -> std::task::Poll<Option<Self::Item>> {
+    self.inner.as_mut().poll_next(cx)
+  }
+}
+
+#[test]
+fn test_subscribe() {
+  use futures_util::StreamExt;
+
+  let database_test_factory = crate::db::DatabaseTestFactory::new(
+    ".temp/test/subscription/test_subscribe",
+  );
+
+  tokio_test::block_on(async {
+    for db in database_test_factory.get_instance().values() {
+      let filters = [
+        SubscriptionFilter::Id("test-0".to_owned()),
+        SubscriptionFilter::Prefix("test-".to_owned()),
+        SubscriptionFilter::All,
+      ];
+      let mut subscriptions =
+        filters.iter().map(|x| db.subscribe(x.clone())).collect::<Vec<_>>();
+
+      for id in ["test-0", "test-1", "phase-0", "test-0"] {
+        db.post(id, b"{}").await.unwrap();
+      }
+
+      // the last record is selected by every filter, so it ends each
+      // subscription's records
+      let expected = [
+        vec![("test-0", 0), ("test-0", 1)],
+        vec![("test-0", 0), ("test-1", 0), ("test-0", 1)],
+        vec![("test-0", 0), ("test-1", 0), ("phase-0", 0), ("test-0", 1)],
+      ];
+
+      for (subscription, expected) in subscriptions.iter_mut().zip(expected) {
+        let mut records = vec![];
+        while records.len() < expected.len() {
+          match subscription.next().await {
+            Some(SubscriptionEvent::Record { id, rec_id, .. }) => {
+              records.push((id, rec_id))
+            }
+            event => panic!("unexpected event {event:?}"),
+          }
+        }
+
+        assert_eq!(
+          records,
+          expected
+            .iter()
+            .map(|(id, rec_id)| (id.to_string(), *rec_id))
+            .collect::<Vec<_>>()
+        );
+      }
+    }
+  });
+}
+
+#[test]
+fn test_lagged() {
+  use futures_util::StreamExt;
+
+  let notifier = crate::db::Notifier::default();
+  let mut subscription =
+    notifier.subscription(SubscriptionFilter::Id("test-0".to_owned()));
+
+  tokio_test::block_on(async {
+    // records of other sensors are not buffered for the subscription,
+    // so they do not make it lag behind
+    for rec_id in 0..2000 {
+      notifier.records(&[("test-1", rec_id, &b"{}"[..])]);
+    }
+    notifier.records(&[("test-0", 0, &b"{}"[..])]);
+    assert_eq!(
+      subscription.next().await,
+      Some(SubscriptionEvent::Record {
+        id: "test-0".to_owned(),
+        rec_id: 0,
+        value: b"{}".to_vec(),
+      })
+    );
+
+    for rec_id in 1..1030 {
+      notifier.records(&[
+        ("test-1", rec_id, &b"{}"[..]),
+        ("test-0", rec_id, &b"{}"[..]),
+      ]);
+    }
+    assert_eq!(subscription.next().await, Some(SubscriptionEvent::Lagged(5)));
+    assert!(matches!(
+      subscription.next().await,
+      Some(SubscriptionEvent::Record { rec_id: 6, .. })
+    ));
+  });
+}
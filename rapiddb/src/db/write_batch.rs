@@ -0,0 +1,87 @@
+/// Write Batch
+///
+/// A group of records for one or more sensors, which is committed to
+/// the database atomically, either all of the records are written or
+/// none of them are.
+///
+/// ## Examples
+/// ```no_run
+/// use crate::rapiddb::traits::IDatabase;
+///
+/// let mut db = rapiddb::db::MMAVDatabase::new();
+///
+/// let mut batch = rapiddb::db::WriteBatch::new();
+/// batch
+///   .post("phase-0", b"{\"power\": 1}")
+///   .post("phase-1", b"{\"power\": 2}")
+///   .post("phase-2", b"{\"power\": 3}");
+///
+/// db.commit(&batch).unwrap();
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct WriteBatch {
+  records: Vec<(String, Vec<u8>)>,
+}
+impl WriteBatch {
+  /// Write Batch Constructor
+  ///
+  /// ## Examples
+  /// ```
+  /// let batch = rapiddb::db::WriteBatch::new();
+  /// assert!(batch.is_empty());
+  /// ```
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  /// Stage a record with `value` for the sensor with `id`
+  ///
+  /// ## Examples
+  /// ```
+  /// let mut batch = rapiddb::db::WriteBatch::new();
+  /// batch.post("test-0", b"{\"key\": \"value\"}");
+  /// assert_eq!(batch.len(), 1);
+  /// ```
+  pub fn post(&mut self, id: &str, value: &[u8]) -> &mut Self {
+    self.records.push((id.to_owned(), value.to_owned()));
+    self
+  }
+
+  /// Get the staged records, as pairs of sensor `id` and `value`
+  ///
+  /// ## Examples
+  /// ```
+  /// let mut batch = rapiddb::db::WriteBatch::new();
+  /// batch.post("test-0", b"value");
+  /// assert_eq!(batch.records(), vec![("test-0", &b"value"[..])]);
+  /// ```
+  pub fn records(&self) -> Vec<(&str, &[u8])> {
+    self
+      .records
+      .iter()
+      .map(|(id, value)| (id.as_str(), value.as_slice()))
+      .collect()
+  }
+
+  /// Get the number of staged records
+  ///
+  /// ## Examples
+  /// ```
+  /// let batch = rapiddb::db::WriteBatch::new();
+  /// assert_eq!(batch.len(), 0);
+  /// ```
+  pub fn len(&self) -> usize {
+    self.records.len()
+  }
+
+  /// Checks if no records are staged
+  ///
+  /// ## Examples
+  /// ```
+  /// let batch = rapiddb::db::WriteBatch::new();
+  /// assert!(batch.is_empty());
+  /// ```
+  pub fn is_empty(&self) -> bool {
+    self.records.is_empty()
+  }
+}
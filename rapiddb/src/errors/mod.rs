@@ -32,6 +32,10 @@ pub enum Error {
   ArrayEmpty,
   IndexOutOfRange,
   IndexOutOfBounds,
+  InvalidSchema(String),
+  SchemaViolation(Vec<String>),
+  InvalidId(String),
+  NotSupported,
   StdNumParseIntError(std::num::ParseIntError),
   StdIoError(std::io::Error),
   StdArrayTryFromSliceError(std::array::TryFromSliceError),
@@ -65,6 +69,12 @@ impl std::fmt::Display for Error {
       Self::ArrayEmpty => write!(f, "Array is empty"),
       Self::IndexOutOfRange => write!(f, "Index out of range"),
       Self::IndexOutOfBounds => write!(f, "Index out of bounds"),
+      Self::InvalidSchema(e) => write!(f, "Invalid schema: {e}"),
+      Self::SchemaViolation(e) => {
+        write!(f, "Schema violation: {}", e.join(", "))
+      }
+      Self::InvalidId(e) => write!(f, "Invalid id: {e}"),
+      Self::NotSupported => write!(f, "Not supported"),
       Self::StdNumParseIntError(e) => std::fmt::Display::fmt(e, f),
       Self::StdIoError(e) => std::fmt::Display::fmt(e, f),
       Self::StdArrayTryFromSliceError(e) => std::fmt::Display::fmt(e, f),
@@ -1,18 +1,37 @@
-/// IDatabase trait abstracts the underlying Database implementation
+use crate::db::validate_id;
+use crate::db::Change;
+use crate::db::Notification;
+use crate::db::RecordRef;
+use crate::db::SensorSchema;
+use crate::db::Snapshot;
+use crate::db::Subscription;
+use crate::db::SubscriptionFilter;
+use crate::db::WriteBatch;
+use crate::errors::Error;
+
+/// Number of records read at once by `IAsyncDatabase::stream_range`
+const CHUNK_SIZE: usize = 256;
+
+/// A stream of records read from a database
+pub type RecordStream = futures_util::stream::BoxStream<'static, RecordRef>;
+
+/// IAsyncDatabase trait abstracts the underlying Database implementation
+///
+/// All methods take a shared reference, so implementations are expected
+/// to synchronize internally, and can be shared between tasks with an
+/// `Arc` without a global lock.
 ///
 /// ## Examples
 /// ```no_run
-/// use crate::rapiddb::traits::IDatabase;
+/// use crate::rapiddb::traits::IAsyncDatabase;
 ///
-/// let db = std::sync::Arc::new(
-///   std::sync::RwLock::new(
-///     rapiddb::db::MMAVDatabase::new()
-///   )
-/// );
+/// # tokio_test::block_on(async {
+/// let db = rapiddb::db::MMAVAsyncDatabase::new();
 ///
 /// let value = b"{\"key\": \"value\"}";
-/// db.write().unwrap().post("test-0", value);
-/// assert_eq!(db.write().unwrap().get_latest("test-0"), value);
+/// db.post("test-0", value).await.unwrap();
+/// assert_eq!(db.get_latest("test-0").await, value);
+/// # })
 /// ```
 #[async_trait::async_trait]
 pub trait IAsyncDatabase: Send + Sync {
@@ -20,110 +39,274 @@ pub trait IAsyncDatabase: Send + Sync {
   ///
   /// ## Examples
   /// ```no_run
-  /// use crate::rapiddb::traits::IDatabase;
+  /// use crate::rapiddb::traits::IAsyncDatabase;
   ///
-  /// let db = std::sync::Arc::new(
-  ///   std::sync::RwLock::new(
-  ///     rapiddb::db::MMAVDatabase::new()
-  ///   )
-  /// );
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
   ///
   /// let id = "test-0";
   ///
-  /// if db.read().unwrap().contains(id) {
+  /// if db.contains(id).await {
   ///     println!("{id} exists in the Database");
   /// }
+  /// # })
   /// ```
   async fn contains(&self, id: &str) -> bool;
 
+  /// Get the number of records of the sensor with `id` in the Database
+  ///
+  /// Unlike `snapshot`, only the sensor with `id` is read.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IAsyncDatabase;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
+  ///
+  /// db.post("test-0", b"{\"key\": \"value\"}").await.unwrap();
+  /// assert_eq!(db.len("test-0").await, 1);
+  /// # })
+  /// ```
+  async fn len(&self, id: &str) -> usize;
+
   /// Get the record with `rec_id` from the sensor with `id` in the
   /// Database
   ///
-  /// May load data from disk, if it is not in-memory,
-  /// as such it is mutable, even though a get normaly
-  /// is immutable.
+  /// May load data from disk, if it is not in-memory.
   ///
   /// ## Examples
   /// ```no_run
-  /// use crate::rapiddb::traits::IDatabase;
+  /// use crate::rapiddb::traits::IAsyncDatabase;
   ///
-  /// let db = std::sync::Arc::new(
-  ///   std::sync::RwLock::new(
-  ///     rapiddb::db::MMAVDatabase::new()
-  ///   )
-  /// );
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
   ///
-  /// db.write().unwrap().get("test-0", 0);
+  /// db.get("test-0", 0).await;
+  /// # })
   /// ```
-  async fn get(&mut self, id: &str, rec_id: usize) -> Vec<u8>;
+  async fn get(&self, id: &str, rec_id: usize) -> Vec<u8>;
 
   /// Post a record with `value` to the sensor with `id` in the Database
   ///
+  /// Returns the index assigned to the record, which can be passed as
+  /// `rec_id` to `get`
+  ///
+  /// ## Errors
+  /// `Error::SchemaViolation` if the sensor metadata has a `schema` and
+  /// `value` does not conform to it
+  ///
   /// ## Examples
   /// ```no_run
-  /// use crate::rapiddb::traits::IDatabase;
+  /// use crate::rapiddb::traits::IAsyncDatabase;
   ///
-  /// let db = std::sync::Arc::new(
-  ///   std::sync::RwLock::new(
-  ///     rapiddb::db::MMAVDatabase::new()
-  ///   )
-  /// );
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
   ///
-  /// db.write().unwrap().post(
+  /// db.post(
   ///   "test-0",
   ///   b"{\"key\": \"value\"}"
-  /// );
+  /// ).await.unwrap();
+  /// # })
+  /// ```
+  async fn post(&self, id: &str, value: &[u8]) -> Result<usize, Error>;
+
+  /// Post a record with `value` to the sensor with `id` in the Database,
+  /// unless a record was recently posted to the sensor with the same
+  /// idempotency `key`
+  ///
+  /// Returns the index assigned to the record, or the index of the
+  /// original record if `key` is in the sensor's window of recent keys.
+  ///
+  /// ## Errors
+  /// `Error::SchemaViolation` if the sensor metadata has a `schema` and
+  /// `value` does not conform to it
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IAsyncDatabase;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
+  ///
+  /// let value = b"{\"key\": \"value\"}";
+  /// let rec_id =
+  ///   db.post_with_key("test-0", "key-0", value).await.unwrap();
+  /// let retry_rec_id =
+  ///   db.post_with_key("test-0", "key-0", value).await.unwrap();
+  /// assert_eq!(rec_id, retry_rec_id);
+  /// # })
+  /// ```
+  async fn post_with_key(
+    &self,
+    id: &str,
+    key: &str,
+    value: &[u8],
+  ) -> Result<usize, Error>;
+
+  /// Post a batch of records with `values` to the sensor with `id` in
+  /// the Database, in order
+  ///
+  /// Returns the index assigned to each record. The default
+  /// implementation posts each record in turn.
+  ///
+  /// ## Errors
+  /// `Error::SchemaViolation` if the sensor metadata has a `schema` and
+  /// a value does not conform to it
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IAsyncDatabase;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
+  ///
+  /// db.post_batch(
+  ///   "test-0",
+  ///   &[b"{\"key\": \"value\"}", b"{\"key\": \"value\"}"]
+  /// ).await.unwrap();
+  /// # })
+  /// ```
+  async fn post_batch(
+    &self,
+    id: &str,
+    values: &[&[u8]],
+  ) -> Result<Vec<usize>, Error> {
+    let mut result = Vec::with_capacity(values.len());
+    for value in values {
+      result.push(self.post(id, value).await?);
+    }
+
+    Ok(result)
+  }
+
+  /// Post a batch of records to multiple sensors in the Database, each
+  /// record is a pair of sensor `id` and `value`
+  ///
+  /// Records of the same sensor are posted in order. Returns the index
+  /// assigned to each record. Every record is validated before any is
+  /// written, the default implementation then posts each record in turn.
+  ///
+  /// ## Errors
+  /// `Error::InvalidId` if a sensor id is invalid, or
+  /// `Error::SchemaViolation` if the metadata of a sensor has a `schema`
+  /// and a value does not conform to it, in which case no record is
+  /// written
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IAsyncDatabase;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
+  ///
+  /// db.post_many(&[
+  ///   ("test-0", b"{\"key\": \"value\"}"),
+  ///   ("test-1", b"{\"key\": \"value\"}"),
+  /// ]).await.unwrap();
+  /// # })
+  /// ```
+  async fn post_many(
+    &self,
+    records: &[(&str, &[u8])],
+  ) -> Result<Vec<usize>, Error> {
+    let mut schemas: std::collections::HashMap<&str, Option<SensorSchema>> =
+      Default::default();
+    for (id, value) in records {
+      validate_id(id)?;
+      if !schemas.contains_key(id) {
+        schemas.insert(id, SensorSchema::from_meta(&self.get_meta(id).await)?);
+      }
+      if let Some(schema) = &schemas[id] {
+        schema.validate(value)?;
+      }
+    }
+
+    let mut result = Vec::with_capacity(records.len());
+    for (id, value) in records {
+      result.push(self.post(id, value).await?);
+    }
+
+    Ok(result)
+  }
+
+  /// Commit the records staged in `batch` to the Database atomically,
+  /// either all of the records are written or none of them are, also
+  /// across a crash
+  ///
+  /// Returns the index assigned to each record. The default
+  /// implementation returns `Error::NotSupported`.
+  ///
+  /// ## Errors
+  /// `Error::SchemaViolation` if the metadata of a sensor has a `schema`
+  /// and a value does not conform to it, nothing is written
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IAsyncDatabase;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
+  ///
+  /// let mut batch = rapiddb::db::WriteBatch::new();
+  /// batch
+  ///   .post("test-0", b"{\"key\": \"value\"}")
+  ///   .post("test-1", b"{\"key\": \"value\"}");
+  ///
+  /// db.commit(&batch).await.unwrap();
+  /// # })
   /// ```
-  async fn post(&mut self, id: &str, value: &[u8]);
+  async fn commit(&self, batch: &WriteBatch) -> Result<Vec<usize>, Error> {
+    let _ = batch;
+    Err(Error::NotSupported)
+  }
 
   /// Get metadata from the sensor with `id` in the Database
   ///
   /// ## Examples
   /// ```no_run
-  /// use crate::rapiddb::traits::IDatabase;
+  /// use crate::rapiddb::traits::IAsyncDatabase;
   ///
-  /// let db = std::sync::Arc::new(
-  ///   std::sync::RwLock::new(
-  ///     rapiddb::db::MMAVDatabase::new()
-  ///   )
-  /// );
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
   ///
-  /// db.write().unwrap().get_meta("test-0");
+  /// db.get_meta("test-0").await;
+  /// # })
   /// ```
-  async fn get_meta(&mut self, id: &str) -> Vec<u8>;
+  async fn get_meta(&self, id: &str) -> Vec<u8>;
 
   /// Post metadata with `value` to the sensor with `id` in the Database
   ///
+  /// ## Errors
+  /// `Error::InvalidSchema` if `value` has a `schema` key, which is not
+  /// a valid JSON Schema
+  ///
   /// ## Examples
   /// ```no_run
-  /// use crate::rapiddb::traits::IDatabase;
+  /// use crate::rapiddb::traits::IAsyncDatabase;
   ///
-  /// let db = std::sync::Arc::new(
-  ///   std::sync::RwLock::new(
-  ///     rapiddb::db::MMAVDatabase::new()
-  ///   )
-  /// );
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
   ///
-  /// db.write().unwrap().post_meta(
+  /// db.post_meta(
   ///   "test-0",
   ///   b"{\"key\": \"value\"}".to_vec()
-  /// );
+  /// ).await.unwrap();
+  /// # })
   /// ```
-  async fn post_meta(&mut self, id: &str, value: Vec<u8>);
+  async fn post_meta(&self, id: &str, value: Vec<u8>) -> Result<(), Error>;
 
   /// Get aggregates from the sensor with `id` in the Database
   ///
   /// ## Examples
   /// ```no_run
-  /// use crate::rapiddb::traits::IDatabase;
+  /// use crate::rapiddb::traits::IAsyncDatabase;
   ///
-  /// let db = std::sync::Arc::new(
-  ///   std::sync::RwLock::new(
-  ///     rapiddb::db::MMAVDatabase::new()
-  ///   )
-  /// );
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
   ///
-  /// db.read().unwrap().get_aggregates("test-0");
+  /// db.get_aggregates("test-0").await;
+  /// # })
   /// ```
   async fn get_aggregates(&self, id: &str) -> Vec<u8>;
 
@@ -131,143 +314,533 @@ pub trait IAsyncDatabase: Send + Sync {
   ///
   /// ## Examples
   /// ```no_run
-  /// use crate::rapiddb::traits::IDatabase;
+  /// use crate::rapiddb::traits::IAsyncDatabase;
   ///
-  /// let db = std::sync::Arc::new(
-  ///   std::sync::RwLock::new(
-  ///     rapiddb::db::MMAVDatabase::new()
-  ///   )
-  /// );
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
   ///
-  /// db.write().unwrap().get_latest("test-0");
+  /// db.get_latest("test-0").await;
+  /// # })
   /// ```
-  // fn get_latest(&mut self, id: &str) -> Vec<u8>;
-  async fn get_latest(&mut self, id: &str) -> Vec<u8>;
+  async fn get_latest(&self, id: &str) -> Vec<u8>;
 
   /// Get the latest `limit` number of records from the sensor with `id`
   /// in the Database
   ///
-  /// May load data from disk, if it is not in-memory,
-  /// as such it is mutable, even though a get latest limit normaly is
-  /// immutable.
+  /// May load data from disk, if it is not in-memory.
   ///
   /// ## Examples
   /// ```no_run
-  /// use crate::rapiddb::traits::IDatabase;
+  /// use crate::rapiddb::traits::IAsyncDatabase;
   ///
-  /// let db = std::sync::Arc::new(
-  ///   std::sync::RwLock::new(
-  ///     rapiddb::db::MMAVDatabase::new()
-  ///   )
-  /// );
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
   ///
-  /// db.write().unwrap().get_latest_with_limit("test-0", 10);
+  /// db.get_latest_with_limit("test-0", 10).await;
+  /// # })
   /// ```
-  async fn get_latest_with_limit(
-    &mut self,
-    id: &str,
-    limit: usize,
-  ) -> Vec<Vec<u8>>;
+  async fn get_latest_with_limit(&self, id: &str, limit: usize)
+    -> Vec<Vec<u8>>;
 
   /// Get a range from `start` to `end` of records from the sensor with
   /// `id` in the Database
   ///
-  /// May load data from disk, if it is not in-memory,
-  /// as such it is mutable, even though a range scan normaly is
-  /// immutable.
+  /// May load data from disk, if it is not in-memory.
   ///
   /// ## Examples
   /// ```no_run
-  /// use crate::rapiddb::traits::IDatabase;
+  /// use crate::rapiddb::traits::IAsyncDatabase;
   ///
-  /// let db = std::sync::Arc::new(
-  ///   std::sync::RwLock::new(
-  ///     rapiddb::db::MMAVDatabase::new()
-  ///   )
-  /// );
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
   ///
-  /// db.write().unwrap().get_range("test-0", 0, 10);
+  /// db.get_range("test-0", 0, 10).await;
+  /// # })
   /// ```
-  async fn get_range(
-    &mut self,
+  async fn get_range(&self, id: &str, start: usize, end: usize)
+    -> Vec<Vec<u8>>;
+
+  /// Stream the records in range `start` to `end` of the sensor with `id`
+  /// in the Database
+  ///
+  /// Records are read in chunks as the stream is polled, so streaming a
+  /// large range uses constant memory. The range is clamped to the
+  /// records of the sensor when the first chunk is read.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IAsyncDatabase;
+  /// use futures_util::StreamExt;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let db = std::sync::Arc::new(rapiddb::db::MMAVAsyncDatabase::new());
+  ///
+  /// let mut records = db.stream_range("test-0", 0, usize::MAX);
+  /// while let Some(record) = records.next().await {
+  ///   println!("{}", String::from_utf8_lossy(&record));
+  /// }
+  /// # })
+  /// ```
+  fn stream_range(
+    self: std::sync::Arc<Self>,
     id: &str,
     start: usize,
     end: usize,
-  ) -> Vec<Vec<u8>>;
+  ) -> RecordStream
+  where
+    Self: 'static,
+  {
+    use futures_util::StreamExt;
+
+    let state = (self, id.to_owned(), start, None);
+    let chunks = futures_util::stream::unfold(
+      state,
+      move |(db, id, next, len)| async move {
+        let len = match len {
+          Some(x) => x,
+          None => db.len(&id).await,
+        };
+        let last =
+          end.min(len.checked_sub(1)?).min(next.saturating_add(CHUNK_SIZE - 1));
+        if next > last {
+          return None;
+        }
+
+        let chunk = db.get_range(&id, next, last).await;
+        if chunk.is_empty() {
+          return None;
+        }
+
+        let chunk = chunk.into_iter().map(RecordRef::from);
+        Some((futures_util::stream::iter(chunk), (db, id, last + 1, Some(len))))
+      },
+    );
+
+    chunks.flatten().boxed()
+  }
 
   /// Get metadata from all sensors in the Database
   ///
   /// ## Examples
   /// ```no_run
-  /// use crate::rapiddb::traits::IDatabase;
+  /// use crate::rapiddb::traits::IAsyncDatabase;
   ///
-  /// let db = std::sync::Arc::new(
-  ///   std::sync::RwLock::new(
-  ///     rapiddb::db::MMAVDatabase::new()
-  ///   )
-  /// );
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
   ///
-  /// db.write().unwrap().get_all_meta();
+  /// db.get_all_meta().await;
+  /// # })
   /// ```
-  async fn get_all_meta(&mut self) -> std::collections::HashMap<&str, Vec<u8>>;
+  async fn get_all_meta(&self) -> std::collections::HashMap<String, Vec<u8>>;
 
   /// Get aggregates from all sensors in the Database
   ///
   /// ## Examples
   /// ```no_run
-  /// use crate::rapiddb::traits::IDatabase;
+  /// use crate::rapiddb::traits::IAsyncDatabase;
   ///
-  /// let db = std::sync::Arc::new(
-  ///   std::sync::RwLock::new(
-  ///     rapiddb::db::MMAVDatabase::new()
-  ///   )
-  /// );
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
   ///
-  /// db.read().unwrap().get_all_aggregates();
+  /// db.get_all_aggregates().await;
+  /// # })
   /// ```
   async fn get_all_aggregates(
     &self,
-  ) -> std::collections::HashMap<&str, Vec<u8>>;
+  ) -> std::collections::HashMap<String, Vec<u8>>;
 
   /// Get the latest record from all sensors in the Database
   ///
   /// ## Examples
   /// ```no_run
-  /// use crate::rapiddb::traits::IDatabase;
+  /// use crate::rapiddb::traits::IAsyncDatabase;
   ///
-  /// let db = std::sync::Arc::new(
-  ///   std::sync::RwLock::new(
-  ///     rapiddb::db::MMAVDatabase::new()
-  ///   )
-  /// );
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
   ///
-  /// db.write().unwrap().get_all_latest();
+  /// db.get_all_latest().await;
+  /// # })
   /// ```
-  async fn get_all_latest(
-    &mut self,
-  ) -> std::collections::HashMap<&str, Vec<u8>>;
+  async fn get_all_latest(&self) -> std::collections::HashMap<String, Vec<u8>>;
 
   /// Get the latest `limit` number of records from all sensors in the
   /// Database
   ///
-  /// May load data from disk, if it is not in-memory,
-  /// as such it is mutable, even though a get latest limit normaly is
-  /// immutable.
+  /// May load data from disk, if it is not in-memory.
   ///
   /// ## Examples
   /// ```no_run
-  /// use crate::rapiddb::traits::IDatabase;
+  /// use crate::rapiddb::traits::IAsyncDatabase;
   ///
-  /// let db = std::sync::Arc::new(
-  ///   std::sync::RwLock::new(
-  ///     rapiddb::db::MMAVDatabase::new()
-  ///   )
-  /// );
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
   ///
-  /// db.write().unwrap().get_all_latest_with_limit(10);
+  /// db.get_all_latest_with_limit(10).await;
+  /// # })
   /// ```
   async fn get_all_latest_with_limit(
-    &mut self,
+    &self,
+    limit: usize,
+  ) -> std::collections::HashMap<String, Vec<Vec<u8>>>;
+
+  /// Take a snapshot of the length of every sensor in the Database, at
+  /// one instant
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IAsyncDatabase;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
+  ///
+  /// let snapshot = db.snapshot().await;
+  /// # })
+  /// ```
+  async fn snapshot(&self) -> Snapshot;
+
+  /// Get the latest record from the sensor with `id` in the Database, as
+  /// of `snapshot`
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IAsyncDatabase;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
+  ///
+  /// let snapshot = db.snapshot().await;
+  /// db.get_latest_as_of(&snapshot, "test-0").await;
+  /// # })
+  /// ```
+  async fn get_latest_as_of(&self, snapshot: &Snapshot, id: &str) -> Vec<u8> {
+    match snapshot.len(id) {
+      0 => Default::default(),
+      len => self.get(id, len - 1).await,
+    }
+  }
+
+  /// Get the latest `limit` number of records from the sensor with `id`
+  /// in the Database, as of `snapshot`
+  ///
+  /// May load data from disk, if it is not in-memory.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IAsyncDatabase;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
+  ///
+  /// let snapshot = db.snapshot().await;
+  /// db.get_latest_with_limit_as_of(&snapshot, "test-0", 10).await;
+  /// # })
+  /// ```
+  async fn get_latest_with_limit_as_of(
+    &self,
+    snapshot: &Snapshot,
+    id: &str,
+    limit: usize,
+  ) -> Vec<Vec<u8>> {
+    let len = snapshot.len(id);
+    if len == 0 || limit == 0 {
+      return Default::default();
+    }
+
+    self.get_range(id, len.saturating_sub(limit), len - 1).await
+  }
+
+  /// Get records in range `start` to `end` from the sensor with `id` in
+  /// the Database, as of `snapshot`
+  ///
+  /// May load data from disk, if it is not in-memory.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IAsyncDatabase;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
+  ///
+  /// let snapshot = db.snapshot().await;
+  /// db.get_range_as_of(&snapshot, "test-0", 0, 10).await;
+  /// # })
+  /// ```
+  async fn get_range_as_of(
+    &self,
+    snapshot: &Snapshot,
+    id: &str,
+    start: usize,
+    end: usize,
+  ) -> Vec<Vec<u8>> {
+    let len = snapshot.len(id);
+    if start >= len {
+      return Default::default();
+    }
+
+    self.get_range(id, start, std::cmp::min(end, len - 1)).await
+  }
+
+  /// Get the latest record from all sensors in the Database, as of
+  /// `snapshot`
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IAsyncDatabase;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
+  ///
+  /// let snapshot = db.snapshot().await;
+  /// db.get_all_latest_as_of(&snapshot).await;
+  /// # })
+  /// ```
+  async fn get_all_latest_as_of(
+    &self,
+    snapshot: &Snapshot,
+  ) -> std::collections::HashMap<String, Vec<u8>> {
+    let mut result: std::collections::HashMap<String, Vec<u8>> =
+      Default::default();
+
+    for id in snapshot.ids() {
+      result.insert(id.to_owned(), self.get_latest_as_of(snapshot, id).await);
+    }
+
+    result
+  }
+
+  /// Get the latest `limit` number of records from all sensors in the
+  /// Database, as of `snapshot`
+  ///
+  /// May load data from disk, if it is not in-memory.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IAsyncDatabase;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
+  ///
+  /// let snapshot = db.snapshot().await;
+  /// db.get_all_latest_with_limit_as_of(&snapshot, 10).await;
+  /// # })
+  /// ```
+  async fn get_all_latest_with_limit_as_of(
+    &self,
+    snapshot: &Snapshot,
     limit: usize,
-  ) -> std::collections::HashMap<&str, Vec<Vec<u8>>>;
+  ) -> std::collections::HashMap<String, Vec<Vec<u8>>> {
+    let mut result: std::collections::HashMap<String, Vec<Vec<u8>>> =
+      Default::default();
+
+    for id in snapshot.ids() {
+      let item = self.get_latest_with_limit_as_of(snapshot, id, limit).await;
+      if !item.is_empty() {
+        result.insert(id.to_owned(), item);
+      }
+    }
+
+    result
+  }
+
+  /// Get at most `limit` changes to the Database after the log sequence
+  /// number `lsn`, in commit order
+  ///
+  /// Every post of a record or metadata is assigned a database-wide log
+  /// sequence number, starting at one, so `lsn` of zero gets the changes
+  /// from the start.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IAsyncDatabase;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
+  ///
+  /// let changes = db.changes_since(0, 100).await;
+  /// if let Some(change) = changes.last() {
+  ///   db.changes_since(change.lsn, 100).await;
+  /// }
+  /// # })
+  /// ```
+  async fn changes_since(&self, lsn: usize, limit: usize) -> Vec<Change>;
+
+  /// Get the committed offset of the named `consumer` of the sensor with
+  /// `id` in the Database
+  ///
+  /// The offset is the index of the next record the consumer has not
+  /// processed yet, zero if it never committed.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IAsyncDatabase;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
+  ///
+  /// let offset = db.get_offset("consumer-0", "test-0").await;
+  /// # })
+  /// ```
+  async fn get_offset(&self, consumer: &str, id: &str) -> usize;
+
+  /// Get the committed offset of the named `consumer` of the sensor with
+  /// `id` in the Database, and at most `max` records after it, paired
+  /// with their index
+  ///
+  /// The offset and the records are read together, so the records
+  /// always start at the returned offset. Polling does not move the
+  /// offset, records are polled again until the consumer commits them
+  /// with `commit_offset`.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IAsyncDatabase;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
+  ///
+  /// let (_, records) = db.poll("consumer-0", "test-0", 100).await;
+  /// if let Some((rec_id, _)) = records.last() {
+  ///   db.commit_offset("consumer-0", "test-0", rec_id + 1).await.unwrap();
+  /// }
+  /// # })
+  /// ```
+  async fn poll(
+    &self,
+    consumer: &str,
+    id: &str,
+    max: usize,
+  ) -> (usize, Vec<(usize, Vec<u8>)>);
+
+  /// Durably commit `offset` for the named `consumer` of the sensor with
+  /// `id` in the Database
+  ///
+  /// Returns `Error::IndexOutOfRange` if `offset` is past the end of the
+  /// sensor.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IAsyncDatabase;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
+  ///
+  /// db.post("test-0", b"{\"key\": \"value\"}").await.unwrap();
+  /// db.commit_offset("consumer-0", "test-0", 1).await.unwrap();
+  /// # })
+  /// ```
+  async fn commit_offset(
+    &self,
+    consumer: &str,
+    id: &str,
+    offset: usize,
+  ) -> Result<(), Error>;
+
+  /// Receive a notification for every write to the Database from now on
+  ///
+  /// Notifications are buffered in a bounded channel, a receiver which
+  /// falls behind gets `RecvError::Lagged` and can catch up by reading
+  /// the records after the last index it has seen.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IAsyncDatabase;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
+  ///
+  /// let mut notifications = db.notifications();
+  /// db.post("test-0", b"{\"key\": \"value\"}").await.unwrap();
+  ///
+  /// let notification = notifications.recv().await.unwrap();
+  /// assert_eq!(notification.id(), "test-0");
+  /// # })
+  /// ```
+  fn notifications(&self) -> tokio::sync::broadcast::Receiver<Notification>;
+
+  /// Subscribe to the records posted from now on to the sensors selected
+  /// by `filter`
+  ///
+  /// The subscription is a stream of the sensor id, record index and
+  /// value of every record, buffered in a bounded channel. A subscription
+  /// which falls behind drops records and gets a
+  /// `SubscriptionEvent::Lagged` instead.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IAsyncDatabase;
+  /// use futures_util::StreamExt;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
+  ///
+  /// let mut subscription =
+  ///   db.subscribe(rapiddb::db::SubscriptionFilter::Prefix("test-".into()));
+  /// db.post("test-0", b"{\"key\": \"value\"}").await.unwrap();
+  ///
+  /// while let Some(event) = subscription.next().await {
+  ///   println!("{event:?}");
+  /// }
+  /// # })
+  /// ```
+  fn subscribe(&self, filter: SubscriptionFilter) -> Subscription;
+
+  /// Wait until the sensor with `id` has more than `len` records, or
+  /// `timeout` elapses
+  ///
+  /// Returns the number of records of the sensor as soon as it is
+  /// greater than `len`, immediately if it already is, or `None` if the
+  /// timeout fires first.
+  ///
+  /// Waits on a length rather than on the index of the last record seen,
+  /// so that waiting for the first record of a sensor, when no index has
+  /// been seen yet, is a `len` of zero. Waiting for a record after index
+  /// `i` is a `len` of `i + 1`.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IAsyncDatabase;
+  ///
+  /// # tokio_test::block_on(async {
+  /// let db = rapiddb::db::MMAVAsyncDatabase::new();
+  ///
+  /// let timeout = std::time::Duration::from_secs(30);
+  /// if let Some(len) = db.wait_for("test-0", 1, timeout).await {
+  ///   db.get_range("test-0", 1, len - 1).await;
+  /// }
+  /// # })
+  /// ```
+  async fn wait_for(
+    &self,
+    id: &str,
+    len: usize,
+    timeout: std::time::Duration,
+  ) -> Option<usize> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut notifications = self.notifications();
+
+    let current = self.len(id).await;
+    if current > len {
+      return Some(current);
+    }
+
+    loop {
+      match tokio::time::timeout_at(deadline, notifications.recv()).await {
+        Ok(Ok(Notification::Record { id: x, rec_id }))
+          if x == id && rec_id >= len =>
+        {
+          return Some(rec_id + 1);
+        }
+        Ok(Ok(_)) => {}
+        Ok(Err(tokio::sync::broadcast::error::RecvError::Lagged(_))) => {
+          let current = self.len(id).await;
+          if current > len {
+            return Some(current);
+          }
+        }
+        Ok(Err(tokio::sync::broadcast::error::RecvError::Closed)) | Err(_) => {
+          return None;
+        }
+      }
+    }
+  }
 }
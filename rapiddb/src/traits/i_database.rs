@@ -1,3 +1,13 @@
+use crate::db::validate_id;
+use crate::db::Change;
+use crate::db::SensorSchema;
+use crate::db::Snapshot;
+use crate::db::WriteBatch;
+use crate::errors::Error;
+
+/// Number of records read at once by `IDatabase::iter_range`
+const CHUNK_SIZE: usize = 256;
+
 /// IDatabase trait abstracts the underlying Database implementation
 ///
 /// ## Examples
@@ -11,8 +21,8 @@
 /// );
 ///
 /// let value = b"{\"key\": \"value\"}";
-/// db.write().unwrap().post("test-0", value);
-/// assert_eq!(db.write().unwrap().get_latest("test-0"), value);
+/// db.write().unwrap().post("test-0", value).unwrap();
+/// assert_eq!(db.read().unwrap().get_latest("test-0"), value);
 /// ```
 pub trait IDatabase: Send + Sync {
   /// Checks if the sensor with `id` exists in the Database
@@ -35,12 +45,29 @@ pub trait IDatabase: Send + Sync {
   /// ```
   fn contains(&self, id: &str) -> bool;
 
+  /// Get the number of records of the sensor with `id` in the Database
+  ///
+  /// Unlike `snapshot`, only the sensor with `id` is read.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IDatabase;
+  ///
+  /// let db = std::sync::Arc::new(
+  ///   std::sync::RwLock::new(
+  ///     rapiddb::db::MMAVDatabase::new()
+  ///   )
+  /// );
+  ///
+  /// db.write().unwrap().post("test-0", b"{\"key\": \"value\"}").unwrap();
+  /// assert_eq!(db.read().unwrap().len("test-0"), 1);
+  /// ```
+  fn len(&self, id: &str) -> usize;
+
   /// Get the record with `rec_id` from the sensor with `id` in the
   /// Database
   ///
-  /// May load data from disk, if it is not in-memory,
-  /// as such it is mutable, even though a get normaly
-  /// is immutable.
+  /// May load data from disk, if it is not in-memory.
   ///
   /// ## Examples
   /// ```no_run
@@ -52,12 +79,19 @@ pub trait IDatabase: Send + Sync {
   ///   )
   /// );
   ///
-  /// db.write().unwrap().get("test-0", 0);
+  /// db.read().unwrap().get("test-0", 0);
   /// ```
-  fn get(&mut self, id: &str, rec_id: usize) -> Vec<u8>;
+  fn get(&self, id: &str, rec_id: usize) -> Vec<u8>;
 
   /// Post a record with `value` to the sensor with `id` in the Database
   ///
+  /// Returns the index assigned to the record, which can be passed as
+  /// `rec_id` to `get`
+  ///
+  /// ## Errors
+  /// `Error::SchemaViolation` if the sensor metadata has a `schema` and
+  /// `value` does not conform to it
+  ///
   /// ## Examples
   /// ```no_run
   /// use crate::rapiddb::traits::IDatabase;
@@ -71,9 +105,160 @@ pub trait IDatabase: Send + Sync {
   /// db.write().unwrap().post(
   ///   "test-0",
   ///   b"{\"key\": \"value\"}"
+  /// ).unwrap();
+  /// ```
+  fn post(&mut self, id: &str, value: &[u8]) -> Result<usize, Error>;
+
+  /// Post a record with `value` to the sensor with `id` in the Database,
+  /// unless a record was recently posted to the sensor with the same
+  /// idempotency `key`
+  ///
+  /// Returns the index assigned to the record, or the index of the
+  /// original record if `key` is in the sensor's window of recent keys.
+  ///
+  /// ## Errors
+  /// `Error::SchemaViolation` if the sensor metadata has a `schema` and
+  /// `value` does not conform to it
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IDatabase;
+  ///
+  /// let db = std::sync::Arc::new(
+  ///   std::sync::RwLock::new(
+  ///     rapiddb::db::MMAVDatabase::new()
+  ///   )
+  /// );
+  ///
+  /// let value = b"{\"key\": \"value\"}";
+  /// let rec_id =
+  ///   db.write().unwrap().post_with_key("test-0", "key-0", value).unwrap();
+  /// let retry_rec_id =
+  ///   db.write().unwrap().post_with_key("test-0", "key-0", value).unwrap();
+  /// assert_eq!(rec_id, retry_rec_id);
+  /// ```
+  fn post_with_key(
+    &mut self,
+    id: &str,
+    key: &str,
+    value: &[u8],
+  ) -> Result<usize, Error>;
+
+  /// Post a batch of records with `values` to the sensor with `id` in
+  /// the Database, in order
+  ///
+  /// Returns the index assigned to each record. The default
+  /// implementation posts each record in turn.
+  ///
+  /// ## Errors
+  /// `Error::SchemaViolation` if the sensor metadata has a `schema` and
+  /// a value does not conform to it
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IDatabase;
+  ///
+  /// let db = std::sync::Arc::new(
+  ///   std::sync::RwLock::new(
+  ///     rapiddb::db::MMAVDatabase::new()
+  ///   )
+  /// );
+  ///
+  /// db.write().unwrap().post_batch(
+  ///   "test-0",
+  ///   &[b"{\"key\": \"value\"}", b"{\"key\": \"value\"}"]
+  /// ).unwrap();
+  /// ```
+  fn post_batch(
+    &mut self,
+    id: &str,
+    values: &[&[u8]],
+  ) -> Result<Vec<usize>, Error> {
+    values.iter().map(|x| self.post(id, x)).collect()
+  }
+
+  /// Post a batch of records to multiple sensors in the Database, each
+  /// record is a pair of sensor `id` and `value`
+  ///
+  /// Records of the same sensor are posted in order. Returns the index
+  /// assigned to each record. Every record is validated before any is
+  /// written, the default implementation then posts each record in turn.
+  ///
+  /// ## Errors
+  /// `Error::InvalidId` if a sensor id is invalid, or
+  /// `Error::SchemaViolation` if the metadata of a sensor has a `schema`
+  /// and a value does not conform to it, in which case no record is
+  /// written
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IDatabase;
+  ///
+  /// let db = std::sync::Arc::new(
+  ///   std::sync::RwLock::new(
+  ///     rapiddb::db::MMAVDatabase::new()
+  ///   )
+  /// );
+  ///
+  /// db.write().unwrap().post_many(&[
+  ///   ("test-0", b"{\"key\": \"value\"}"),
+  ///   ("test-1", b"{\"key\": \"value\"}"),
+  /// ]).unwrap();
+  /// ```
+  fn post_many(
+    &mut self,
+    records: &[(&str, &[u8])],
+  ) -> Result<Vec<usize>, Error> {
+    let mut schemas: std::collections::HashMap<&str, Option<SensorSchema>> =
+      Default::default();
+    for (id, value) in records {
+      validate_id(id)?;
+      let schema = match schemas.get(id) {
+        Some(x) => x,
+        None => schemas
+          .entry(id)
+          .or_insert(SensorSchema::from_meta(&self.get_meta(id))?),
+      };
+      if let Some(schema) = schema {
+        schema.validate(value)?;
+      }
+    }
+
+    records.iter().map(|(id, value)| self.post(id, value)).collect()
+  }
+
+  /// Commit the records staged in `batch` to the Database atomically,
+  /// either all of the records are written or none of them are, also
+  /// across a crash
+  ///
+  /// Returns the index assigned to each record. The default
+  /// implementation returns `Error::NotSupported`.
+  ///
+  /// ## Errors
+  /// `Error::SchemaViolation` if the metadata of a sensor has a `schema`
+  /// and a value does not conform to it, nothing is written
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IDatabase;
+  ///
+  /// let db = std::sync::Arc::new(
+  ///   std::sync::RwLock::new(
+  ///     rapiddb::db::MMAVDatabase::new()
+  ///   )
   /// );
+  ///
+  /// let mut batch = rapiddb::db::WriteBatch::new();
+  /// batch
+  ///   .post("test-0", b"{\"key\": \"value\"}")
+  ///   .post("test-1", b"{\"key\": \"value\"}");
+  ///
+  /// db.write().unwrap().commit(&batch).unwrap();
   /// ```
-  fn post(&mut self, id: &str, value: &[u8]);
+  fn commit(&mut self, batch: &WriteBatch) -> Result<Vec<usize>, Error> {
+    let _ = batch;
+    Err(Error::NotSupported)
+  }
 
   /// Get metadata from the sensor with `id` in the Database
   ///
@@ -87,12 +272,16 @@ pub trait IDatabase: Send + Sync {
   ///   )
   /// );
   ///
-  /// db.write().unwrap().get_meta("test-0");
+  /// db.read().unwrap().get_meta("test-0");
   /// ```
-  fn get_meta(&mut self, id: &str) -> Vec<u8>;
+  fn get_meta(&self, id: &str) -> Vec<u8>;
 
   /// Post metadata with `value` to the sensor with `id` in the Database
   ///
+  /// ## Errors
+  /// `Error::InvalidSchema` if `value` has a `schema` key, which is not
+  /// a valid JSON Schema
+  ///
   /// ## Examples
   /// ```no_run
   /// use crate::rapiddb::traits::IDatabase;
@@ -106,9 +295,9 @@ pub trait IDatabase: Send + Sync {
   /// db.write().unwrap().post_meta(
   ///   "test-0",
   ///   b"{\"key\": \"value\"}".to_vec()
-  /// );
+  /// ).unwrap();
   /// ```
-  fn post_meta(&mut self, id: &str, value: Vec<u8>);
+  fn post_meta(&mut self, id: &str, value: Vec<u8>) -> Result<(), Error>;
 
   /// Get aggregates from the sensor with `id` in the Database
   ///
@@ -138,17 +327,15 @@ pub trait IDatabase: Send + Sync {
   ///   )
   /// );
   ///
-  /// db.write().unwrap().get_latest("test-0");
+  /// db.read().unwrap().get_latest("test-0");
   /// ```
-  // fn get_latest(&mut self, id: &str) -> Vec<u8>;
-  fn get_latest(&mut self, id: &str) -> Vec<u8>;
+  // fn get_latest(&self, id: &str) -> Vec<u8>;
+  fn get_latest(&self, id: &str) -> Vec<u8>;
 
   /// Get the latest `limit` number of records from the sensor with `id`
   /// in the Database
   ///
-  /// May load data from disk, if it is not in-memory,
-  /// as such it is mutable, even though a get latest limit normaly is
-  /// immutable.
+  /// May load data from disk, if it is not in-memory.
   ///
   /// ## Examples
   /// ```no_run
@@ -160,16 +347,14 @@ pub trait IDatabase: Send + Sync {
   ///   )
   /// );
   ///
-  /// db.write().unwrap().get_latest_with_limit("test-0", 10);
+  /// db.read().unwrap().get_latest_with_limit("test-0", 10);
   /// ```
-  fn get_latest_with_limit(&mut self, id: &str, limit: usize) -> Vec<Vec<u8>>;
+  fn get_latest_with_limit(&self, id: &str, limit: usize) -> Vec<Vec<u8>>;
 
   /// Get a range from `start` to `end` of records from the sensor with
   /// `id` in the Database
   ///
-  /// May load data from disk, if it is not in-memory,
-  /// as such it is mutable, even though a range scan normaly is
-  /// immutable.
+  /// May load data from disk, if it is not in-memory.
   ///
   /// ## Examples
   /// ```no_run
@@ -181,9 +366,50 @@ pub trait IDatabase: Send + Sync {
   ///   )
   /// );
   ///
-  /// db.write().unwrap().get_range("test-0", 0, 10);
+  /// db.read().unwrap().get_range("test-0", 0, 10);
   /// ```
-  fn get_range(&mut self, id: &str, start: usize, end: usize) -> Vec<Vec<u8>>;
+  fn get_range(&self, id: &str, start: usize, end: usize) -> Vec<Vec<u8>>;
+
+  /// Iterate over the records in range `start` to `end` of the sensor
+  /// with `id` in the Database
+  ///
+  /// Records are read lazily as the iterator advances, from the front or
+  /// the back, so iterating over a large range uses constant memory. The
+  /// range is clamped to the records of the sensor when the iterator is
+  /// created.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IDatabase;
+  ///
+  /// let db = std::sync::Arc::new(
+  ///   std::sync::RwLock::new(
+  ///     rapiddb::db::MMAVDatabase::new()
+  ///   )
+  /// );
+  ///
+  /// let db = db.read().unwrap();
+  /// for record in db.iter_range("test-0", 0, usize::MAX) {
+  ///   println!("{}", String::from_utf8_lossy(&record));
+  /// }
+  ///
+  /// let latest = db.iter_range("test-0", 0, usize::MAX).rev().take(10);
+  /// ```
+  fn iter_range<'a>(
+    &'a self,
+    id: &'a str,
+    start: usize,
+    end: usize,
+  ) -> Box<dyn DoubleEndedIterator<Item = Vec<u8>> + 'a> {
+    let Some(last) = self.len(id).checked_sub(1) else {
+      return Box::new(std::iter::empty());
+    };
+    let end = end.min(last);
+
+    Box::new((start..end + 1).step_by(CHUNK_SIZE).flat_map(move |x| {
+      self.get_range(id, x, end.min(x.saturating_add(CHUNK_SIZE - 1)))
+    }))
+  }
 
   /// Get metadata from all sensors in the Database
   ///
@@ -197,9 +423,9 @@ pub trait IDatabase: Send + Sync {
   ///   )
   /// );
   ///
-  /// db.write().unwrap().get_all_meta();
+  /// db.read().unwrap().get_all_meta();
   /// ```
-  fn get_all_meta(&mut self) -> std::collections::HashMap<&str, Vec<u8>>;
+  fn get_all_meta(&self) -> std::collections::HashMap<&str, Vec<u8>>;
 
   /// Get aggregates from all sensors in the Database
   ///
@@ -229,16 +455,14 @@ pub trait IDatabase: Send + Sync {
   ///   )
   /// );
   ///
-  /// db.write().unwrap().get_all_latest();
+  /// db.read().unwrap().get_all_latest();
   /// ```
-  fn get_all_latest(&mut self) -> std::collections::HashMap<&str, Vec<u8>>;
+  fn get_all_latest(&self) -> std::collections::HashMap<&str, Vec<u8>>;
 
   /// Get the latest `limit` number of records from all sensors in the
   /// Database
   ///
-  /// May load data from disk, if it is not in-memory,
-  /// as such it is mutable, even though a get latest limit normaly is
-  /// immutable.
+  /// May load data from disk, if it is not in-memory.
   ///
   /// ## Examples
   /// ```no_run
@@ -250,10 +474,275 @@ pub trait IDatabase: Send + Sync {
   ///   )
   /// );
   ///
-  /// db.write().unwrap().get_all_latest_with_limit(10);
+  /// db.read().unwrap().get_all_latest_with_limit(10);
   /// ```
   fn get_all_latest_with_limit(
-    &mut self,
+    &self,
     limit: usize,
   ) -> std::collections::HashMap<&str, Vec<Vec<u8>>>;
+
+  /// Take a snapshot of the length of every sensor in the Database, at
+  /// one instant
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IDatabase;
+  ///
+  /// let db = std::sync::Arc::new(
+  ///   std::sync::RwLock::new(
+  ///     rapiddb::db::MMAVDatabase::new()
+  ///   )
+  /// );
+  ///
+  /// let db = db.read().unwrap();
+  /// let snapshot = db.snapshot();
+  /// ```
+  fn snapshot(&self) -> Snapshot;
+
+  /// Get the latest record from the sensor with `id` in the Database, as
+  /// of `snapshot`
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IDatabase;
+  ///
+  /// let db = std::sync::Arc::new(
+  ///   std::sync::RwLock::new(
+  ///     rapiddb::db::MMAVDatabase::new()
+  ///   )
+  /// );
+  ///
+  /// let db = db.read().unwrap();
+  /// let snapshot = db.snapshot();
+  /// db.get_latest_as_of(&snapshot, "test-0");
+  /// ```
+  fn get_latest_as_of(&self, snapshot: &Snapshot, id: &str) -> Vec<u8> {
+    match snapshot.len(id) {
+      0 => Default::default(),
+      len => self.get(id, len - 1),
+    }
+  }
+
+  /// Get the latest `limit` number of records from the sensor with `id`
+  /// in the Database, as of `snapshot`
+  ///
+  /// May load data from disk, if it is not in-memory.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IDatabase;
+  ///
+  /// let db = std::sync::Arc::new(
+  ///   std::sync::RwLock::new(
+  ///     rapiddb::db::MMAVDatabase::new()
+  ///   )
+  /// );
+  ///
+  /// let db = db.read().unwrap();
+  /// let snapshot = db.snapshot();
+  /// db.get_latest_with_limit_as_of(&snapshot, "test-0", 10);
+  /// ```
+  fn get_latest_with_limit_as_of(
+    &self,
+    snapshot: &Snapshot,
+    id: &str,
+    limit: usize,
+  ) -> Vec<Vec<u8>> {
+    let len = snapshot.len(id);
+    if len == 0 || limit == 0 {
+      return Default::default();
+    }
+
+    self.get_range(id, len.saturating_sub(limit), len - 1)
+  }
+
+  /// Get records in range `start` to `end` from the sensor with `id` in
+  /// the Database, as of `snapshot`
+  ///
+  /// May load data from disk, if it is not in-memory.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IDatabase;
+  ///
+  /// let db = std::sync::Arc::new(
+  ///   std::sync::RwLock::new(
+  ///     rapiddb::db::MMAVDatabase::new()
+  ///   )
+  /// );
+  ///
+  /// let db = db.read().unwrap();
+  /// let snapshot = db.snapshot();
+  /// db.get_range_as_of(&snapshot, "test-0", 0, 10);
+  /// ```
+  fn get_range_as_of(
+    &self,
+    snapshot: &Snapshot,
+    id: &str,
+    start: usize,
+    end: usize,
+  ) -> Vec<Vec<u8>> {
+    let len = snapshot.len(id);
+    if start >= len {
+      return Default::default();
+    }
+
+    self.get_range(id, start, std::cmp::min(end, len - 1))
+  }
+
+  /// Get the latest record from all sensors in the Database, as of
+  /// `snapshot`
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IDatabase;
+  ///
+  /// let db = std::sync::Arc::new(
+  ///   std::sync::RwLock::new(
+  ///     rapiddb::db::MMAVDatabase::new()
+  ///   )
+  /// );
+  ///
+  /// let db = db.read().unwrap();
+  /// let snapshot = db.snapshot();
+  /// db.get_all_latest_as_of(&snapshot);
+  /// ```
+  fn get_all_latest_as_of<'a>(
+    &self,
+    snapshot: &'a Snapshot,
+  ) -> std::collections::HashMap<&'a str, Vec<u8>> {
+    snapshot.ids().map(|id| (id, self.get_latest_as_of(snapshot, id))).collect()
+  }
+
+  /// Get the latest `limit` number of records from all sensors in the
+  /// Database, as of `snapshot`
+  ///
+  /// May load data from disk, if it is not in-memory.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IDatabase;
+  ///
+  /// let db = std::sync::Arc::new(
+  ///   std::sync::RwLock::new(
+  ///     rapiddb::db::MMAVDatabase::new()
+  ///   )
+  /// );
+  ///
+  /// let db = db.read().unwrap();
+  /// let snapshot = db.snapshot();
+  /// db.get_all_latest_with_limit_as_of(&snapshot, 10);
+  /// ```
+  fn get_all_latest_with_limit_as_of<'a>(
+    &self,
+    snapshot: &'a Snapshot,
+    limit: usize,
+  ) -> std::collections::HashMap<&'a str, Vec<Vec<u8>>> {
+    snapshot
+      .ids()
+      .map(|id| (id, self.get_latest_with_limit_as_of(snapshot, id, limit)))
+      .filter(|(_, x)| !x.is_empty())
+      .collect()
+  }
+
+  /// Get at most `limit` changes to the Database after the log sequence
+  /// number `lsn`, in commit order
+  ///
+  /// Every post of a record or metadata is assigned a database-wide log
+  /// sequence number, starting at one, so `lsn` of zero gets the changes
+  /// from the start.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IDatabase;
+  ///
+  /// let db = std::sync::Arc::new(
+  ///   std::sync::RwLock::new(
+  ///     rapiddb::db::MMAVDatabase::new()
+  ///   )
+  /// );
+  ///
+  /// let changes = db.read().unwrap().changes_since(0, 100);
+  /// if let Some(change) = changes.last() {
+  ///   db.read().unwrap().changes_since(change.lsn, 100);
+  /// }
+  /// ```
+  fn changes_since(&self, lsn: usize, limit: usize) -> Vec<Change>;
+
+  /// Get the committed offset of the named `consumer` of the sensor with
+  /// `id` in the Database
+  ///
+  /// The offset is the index of the next record the consumer has not
+  /// processed yet, zero if it never committed.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IDatabase;
+  ///
+  /// let db = std::sync::Arc::new(
+  ///   std::sync::RwLock::new(
+  ///     rapiddb::db::MMAVDatabase::new()
+  ///   )
+  /// );
+  ///
+  /// let offset = db.read().unwrap().get_offset("consumer-0", "test-0");
+  /// ```
+  fn get_offset(&self, consumer: &str, id: &str) -> usize;
+
+  /// Get the committed offset of the named `consumer` of the sensor with
+  /// `id` in the Database, and at most `max` records after it, paired
+  /// with their index
+  ///
+  /// The offset and the records are read together, so the records
+  /// always start at the returned offset. Polling does not move the
+  /// offset, records are polled again until the consumer commits them
+  /// with `commit_offset`.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IDatabase;
+  ///
+  /// let db = std::sync::Arc::new(
+  ///   std::sync::RwLock::new(
+  ///     rapiddb::db::MMAVDatabase::new()
+  ///   )
+  /// );
+  ///
+  /// let (_, records) = db.read().unwrap().poll("consumer-0", "test-0", 100);
+  /// if let Some((rec_id, _)) = records.last() {
+  ///   db.write().unwrap().commit_offset("consumer-0", "test-0", rec_id + 1).unwrap();
+  /// }
+  /// ```
+  fn poll(
+    &self,
+    consumer: &str,
+    id: &str,
+    max: usize,
+  ) -> (usize, Vec<(usize, Vec<u8>)>);
+
+  /// Durably commit `offset` for the named `consumer` of the sensor with
+  /// `id` in the Database
+  ///
+  /// Returns `Error::IndexOutOfRange` if `offset` is past the end of the
+  /// sensor.
+  ///
+  /// ## Examples
+  /// ```no_run
+  /// use crate::rapiddb::traits::IDatabase;
+  ///
+  /// let db = std::sync::Arc::new(
+  ///   std::sync::RwLock::new(
+  ///     rapiddb::db::MMAVDatabase::new()
+  ///   )
+  /// );
+  ///
+  /// db.write().unwrap().post("test-0", b"{\"key\": \"value\"}").unwrap();
+  /// db.write().unwrap().commit_offset("consumer-0", "test-0", 1).unwrap();
+  /// ```
+  fn commit_offset(
+    &mut self,
+    consumer: &str,
+    id: &str,
+    offset: usize,
+  ) -> Result<(), Error>;
 }
@@ -4,4 +4,5 @@ mod i_async_database;
 mod i_database;
 
 pub use i_async_database::IAsyncDatabase;
+pub use i_async_database::RecordStream;
 pub use i_database::IDatabase;